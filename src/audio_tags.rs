@@ -0,0 +1,553 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::isobmff::{self, child_box, read_up_to};
+
+/// Largest tag block we are willing to load into memory.
+const MAX_TAG_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Tags used to lay out music files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioTags {
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<u32>,
+    pub year: Option<i32>,
+}
+
+impl AudioTags {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Applies a Vorbis-comment style `KEY=value` pair.
+    fn set_field(&mut self, key: &str, value: &str) {
+        let value = value.trim();
+        if value.is_empty() {
+            return;
+        }
+
+        match key.to_ascii_uppercase().as_str() {
+            "ARTIST" => set_once(&mut self.artist, value),
+            "ALBUMARTIST" | "ALBUM ARTIST" => set_once(&mut self.album_artist, value),
+            "ALBUM" => set_once(&mut self.album, value),
+            "TITLE" => set_once(&mut self.title, value),
+            "TRACKNUMBER" => self.track = self.track.or_else(|| parse_track(value)),
+            "DATE" | "YEAR" => self.year = self.year.or_else(|| parse_year(value)),
+            _ => {}
+        }
+    }
+}
+
+/// Keeps the first value when a comment is repeated.
+fn set_once(field: &mut Option<String>, value: &str) {
+    if field.is_none() {
+        *field = Some(value.to_string());
+    }
+}
+
+/// Reads tags from MP3 (ID3v2), FLAC, Ogg Vorbis/Opus and MP4/M4A files.
+///
+/// Returns `Ok(None)` when the format is not recognized or has no tags.
+pub fn read_tags(path: &Path) -> io::Result<Option<AudioTags>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 12];
+    let read = read_up_to(&mut file, &mut magic)?;
+    let magic = &magic[..read];
+    file.seek(SeekFrom::Start(0))?;
+
+    let tags = if magic.starts_with(b"ID3") {
+        read_id3v2(&mut file)?
+    } else if magic.starts_with(b"fLaC") {
+        read_flac(&mut file)?
+    } else if magic.starts_with(b"OggS") {
+        read_ogg(&mut file)?
+    } else if magic.len() >= 8 && &magic[4..8] == b"ftyp" {
+        read_mp4(&mut file)?
+    } else {
+        None
+    };
+
+    Ok(tags.filter(|tags| !tags.is_empty()))
+}
+
+fn read_exact_vec(file: &mut File, length: u64) -> io::Result<Vec<u8>> {
+    if length > MAX_TAG_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "tag block too large"));
+    }
+    let mut data = vec![0u8; length as usize];
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Parses "3" or "3/12" into 3.
+fn parse_track(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok().filter(|track| *track > 0)
+}
+
+/// Takes the year from "2004", "2004-05-01" or "2004-05-01T10:00:00".
+fn parse_year(value: &str) -> Option<i32> {
+    let digits: String = value.trim().chars().take(4).collect();
+    if digits.len() == 4 && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, byte| (value << 7) | (*byte & 0x7F) as u32)
+}
+
+fn read_id3v2(file: &mut File) -> io::Result<Option<AudioTags>> {
+    let header = read_exact_vec(file, 10)?;
+    let version = header[3];
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]) as u64;
+    if !(2..=4).contains(&version) {
+        return Ok(None);
+    }
+
+    let mut data = read_exact_vec(file, size)?;
+    if flags & 0x80 != 0 && version < 4 {
+        data = remove_unsynchronisation(&data);
+    }
+
+    let mut offset = 0;
+    if flags & 0x40 != 0 && version >= 3 {
+        let extended = data.get(0..4).unwrap_or_default();
+        offset = if version == 4 {
+            syncsafe(extended) as usize
+        } else {
+            4 + u32::from_be_bytes(extended.try_into().unwrap_or_default()) as usize
+        };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut tags = AudioTags::default();
+
+    while offset + header_len <= data.len() {
+        let frame = &data[offset..];
+        if frame[0] == 0 {
+            break; // padding
+        }
+
+        let id = String::from_utf8_lossy(&frame[..id_len]).to_string();
+        let frame_size = match version {
+            2 => u32::from_be_bytes([0, frame[3], frame[4], frame[5]]),
+            3 => u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]),
+            _ => syncsafe(&frame[4..8]),
+        } as usize;
+
+        let body = match frame.get(header_len..header_len + frame_size) {
+            Some(body) => body,
+            None => break,
+        };
+
+        if let Some(text) = id3_text(body) {
+            match id.as_str() {
+                "TPE1" | "TP1" => tags.artist = Some(text),
+                "TPE2" | "TP2" => tags.album_artist = Some(text),
+                "TALB" | "TAL" => tags.album = Some(text),
+                "TIT2" | "TT2" => tags.title = Some(text),
+                "TRCK" | "TRK" => tags.track = parse_track(&text),
+                "TYER" | "TYE" | "TDRC" => tags.year = tags.year.or_else(|| parse_year(&text)),
+                _ => {}
+            }
+        }
+
+        offset += header_len + frame_size;
+    }
+
+    Ok(Some(tags))
+}
+
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut previous = 0u8;
+    for &byte in data {
+        if !(previous == 0xFF && byte == 0x00) {
+            output.push(byte);
+        }
+        previous = byte;
+    }
+    output
+}
+
+/// Decodes an ID3 text frame body; multiple values keep only the first.
+fn id3_text(body: &[u8]) -> Option<String> {
+    let (&encoding, text) = body.split_first()?;
+    let decoded = match encoding {
+        0 => text.iter().map(|&b| b as char).collect(),
+        1 | 2 => decode_utf16(text, encoding == 2),
+        3 => String::from_utf8_lossy(text).to_string(),
+        _ => return None,
+    };
+
+    let value = decoded.split('\0').next().unwrap_or_default().trim().to_string();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn decode_utf16(bytes: &[u8], default_big_endian: bool) -> String {
+    let (big_endian, bytes) = match bytes {
+        [0xFE, 0xFF, rest @ ..] => (true, rest),
+        [0xFF, 0xFE, rest @ ..] => (false, rest),
+        _ => (default_big_endian, bytes),
+    };
+
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            if big_endian {
+                u16::from_be_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_le_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn read_flac(file: &mut File) -> io::Result<Option<AudioTags>> {
+    file.seek(SeekFrom::Start(4))?;
+
+    loop {
+        let header = read_exact_vec(file, 4)?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+
+        if block_type == 4 {
+            let block = read_exact_vec(file, length)?;
+            return Ok(parse_vorbis_comment(&block));
+        }
+        if is_last {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Current(length as i64))?;
+    }
+}
+
+/// Parses a Vorbis comment block (vendor string followed by `KEY=value` pairs).
+fn parse_vorbis_comment(data: &[u8]) -> Option<AudioTags> {
+    let le_u32 = |offset: usize| -> Option<usize> {
+        Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize)
+    };
+
+    let vendor_len = le_u32(0)?;
+    let mut offset = 4 + vendor_len;
+    let count = le_u32(offset)?;
+    offset += 4;
+
+    let mut tags = AudioTags::default();
+    for _ in 0..count {
+        let length = le_u32(offset)?;
+        offset += 4;
+        let comment = String::from_utf8_lossy(data.get(offset..offset + length)?);
+        if let Some((key, value)) = comment.split_once('=') {
+            tags.set_field(key, value);
+        }
+        offset += length;
+    }
+    Some(tags)
+}
+
+/// Reassembles the second Ogg packet, which holds the comment header for
+/// both Vorbis and Opus streams.
+fn read_ogg(file: &mut File) -> io::Result<Option<AudioTags>> {
+    let mut packets: Vec<Vec<u8>> = vec![Vec::new()];
+    let mut total = 0u64;
+
+    while packets.len() < 3 && total < MAX_TAG_BYTES {
+        let mut header = [0u8; 27];
+        if read_up_to(file, &mut header)? < 27 || &header[..4] != b"OggS" {
+            break;
+        }
+        let segment_count = header[26] as u64;
+        let segments = read_exact_vec(file, segment_count)?;
+
+        for &length in &segments {
+            let data = read_exact_vec(file, length as u64)?;
+            packets.last_mut().unwrap().extend_from_slice(&data);
+            total += length as u64;
+            // A segment shorter than 255 bytes ends the packet
+            if length < 255 {
+                packets.push(Vec::new());
+            }
+        }
+    }
+
+    let comment = match packets.get(1) {
+        Some(packet) if packets.len() > 2 => packet,
+        _ => return Ok(None),
+    };
+
+    if let Some(body) = comment.strip_prefix(b"\x03vorbis") {
+        Ok(parse_vorbis_comment(body))
+    } else if let Some(body) = comment.strip_prefix(b"OpusTags") {
+        Ok(parse_vorbis_comment(body))
+    } else {
+        Ok(None)
+    }
+}
+
+fn read_mp4(file: &mut File) -> io::Result<Option<AudioTags>> {
+    let file_len = file.metadata()?.len();
+
+    // moov is often written after the media data, so walk the top level
+    let moov = match isobmff::find_box(file, 0, file_len, b"moov")? {
+        Some((start, end)) => {
+            file.seek(SeekFrom::Start(start))?;
+            read_exact_vec(file, end - start)?
+        }
+        None => return Ok(None),
+    };
+    let ilst = child_box(&moov, b"udta")
+        .and_then(|udta| child_box(udta, b"meta"))
+        .and_then(|meta| child_box(meta.get(4..)?, b"ilst"));
+    Ok(ilst.map(parse_ilst))
+}
+
+fn parse_ilst(ilst: &[u8]) -> AudioTags {
+    let mut tags = AudioTags::default();
+
+    for (kind, item) in isobmff::boxes(ilst) {
+        // Value lives in a `data` child after 4 bytes of type and 4 of locale
+        let value = match child_box(item, b"data").and_then(|data| data.get(8..)) {
+            Some(value) => value,
+            None => continue,
+        };
+
+        // Empty strings count as missing, so the unknown buckets apply
+        let text = String::from_utf8_lossy(value).trim().to_string();
+        let text = Some(text).filter(|text| !text.is_empty());
+        match kind {
+            b"\xa9ART" => tags.artist = text,
+            b"aART" => tags.album_artist = text,
+            b"\xa9alb" => tags.album = text,
+            b"\xa9nam" => tags.title = text,
+            b"\xa9day" => tags.year = text.as_deref().and_then(parse_year),
+            b"trkn" if value.len() >= 4 => {
+                let track = u16::from_be_bytes([value[2], value[3]]) as u32;
+                tags.track = Some(track).filter(|track| *track > 0);
+            }
+            _ => {}
+        }
+    }
+
+    tags
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn id3_frame(id: &str, text: &str) -> Vec<u8> {
+        let mut body = vec![3u8];
+        body.extend_from_slice(text.as_bytes());
+        let mut frame = id.as_bytes().to_vec();
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&body);
+        frame
+    }
+
+    /// Builds an MP3 that is nothing but an ID3v2.3 tag.
+    pub(crate) fn build_id3(artist: &str, album: &str, title: &str, track: &str) -> Vec<u8> {
+        let mut frames = Vec::new();
+        frames.extend(id3_frame("TPE1", artist));
+        frames.extend(id3_frame("TALB", album));
+        frames.extend(id3_frame("TIT2", title));
+        frames.extend(id3_frame("TRCK", track));
+        frames.extend(id3_frame("TYER", "1999"));
+        frames.extend([0u8; 16]); // padding
+
+        let size = frames.len() as u32;
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        data.extend_from_slice(&[
+            ((size >> 21) & 0x7F) as u8,
+            ((size >> 14) & 0x7F) as u8,
+            ((size >> 7) & 0x7F) as u8,
+            (size & 0x7F) as u8,
+        ]);
+        data.extend_from_slice(&frames);
+        data.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        data
+    }
+
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(b"test");
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+        data
+    }
+
+    fn ogg_page(packet: &[u8], sequence: u32) -> Vec<u8> {
+        let mut page = b"OggS\0".to_vec();
+        page.push(if sequence == 0 { 2 } else { 0 });
+        page.extend_from_slice(&[0u8; 8]);
+        page.extend_from_slice(&1u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0u8; 4]);
+
+        let mut lacing = vec![255u8; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        page.extend_from_slice(packet);
+        page
+    }
+
+    fn mp4_box(kind: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn mp4_item(kind: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        data.extend_from_slice(value);
+        mp4_box(kind, &mp4_box(b"data", &data))
+    }
+
+    fn write(dir: &Path, name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_id3v2() {
+        let dir = tempdir().unwrap();
+        let path = write(dir.path(), "song.mp3", &build_id3("Daft Punk", "Discovery", "One More Time", "1/14"));
+
+        let tags = read_tags(&path).unwrap().unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Daft Punk"));
+        assert_eq!(tags.album.as_deref(), Some("Discovery"));
+        assert_eq!(tags.title.as_deref(), Some("One More Time"));
+        assert_eq!(tags.track, Some(1));
+        assert_eq!(tags.year, Some(1999));
+    }
+
+    #[test]
+    fn test_id3_utf16_text() {
+        let mut body = vec![1u8, 0xFF, 0xFE];
+        for unit in "Björk".encode_utf16() {
+            body.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(id3_text(&body).as_deref(), Some("Björk"));
+    }
+
+    #[test]
+    fn test_read_flac() {
+        let dir = tempdir().unwrap();
+        let comment = vorbis_comment(&["ARTIST=Portishead", "album=Dummy", "TRACKNUMBER=3", "DATE=1994-08-22"]);
+
+        let mut data = b"fLaC".to_vec();
+        // STREAMINFO block, then the comment block marked as last
+        data.extend_from_slice(&[0x00, 0, 0, 34]);
+        data.extend_from_slice(&[0u8; 34]);
+        data.push(0x84);
+        data.extend_from_slice(&(comment.len() as u32).to_be_bytes()[1..]);
+        data.extend_from_slice(&comment);
+        let path = write(dir.path(), "song.flac", &data);
+
+        let tags = read_tags(&path).unwrap().unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Portishead"));
+        assert_eq!(tags.album.as_deref(), Some("Dummy"));
+        assert_eq!(tags.track, Some(3));
+        assert_eq!(tags.year, Some(1994));
+    }
+
+    #[test]
+    fn test_read_ogg_vorbis() {
+        let dir = tempdir().unwrap();
+        let mut comment = b"\x03vorbis".to_vec();
+        comment.extend_from_slice(&vorbis_comment(&["ARTIST=Radiohead", "TITLE=Airbag"]));
+        comment.push(1);
+
+        let mut data = ogg_page(b"\x01vorbis-identification", 0);
+        data.extend_from_slice(&ogg_page(&comment, 1));
+        let path = write(dir.path(), "song.ogg", &data);
+
+        let tags = read_tags(&path).unwrap().unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Radiohead"));
+        assert_eq!(tags.title.as_deref(), Some("Airbag"));
+    }
+
+    #[test]
+    fn test_read_m4a() {
+        let dir = tempdir().unwrap();
+        let mut ilst = mp4_item(b"\xa9ART", b"Massive Attack");
+        ilst.extend_from_slice(&mp4_item(b"\xa9alb", b"Mezzanine"));
+        ilst.extend_from_slice(&mp4_item(b"trkn", &[0, 0, 0, 5, 0, 11, 0, 0]));
+        ilst.extend_from_slice(&mp4_item(b"\xa9day", b"1998"));
+
+        let mut meta = vec![0u8; 4];
+        meta.extend_from_slice(&mp4_box(b"ilst", &ilst));
+        let moov = mp4_box(b"moov", &mp4_box(b"udta", &mp4_box(b"meta", &meta)));
+
+        let mut data = mp4_box(b"ftyp", b"M4A \0\0\0\0");
+        data.extend_from_slice(&mp4_box(b"mdat", &[0u8; 32]));
+        data.extend_from_slice(&moov);
+        let path = write(dir.path(), "song.m4a", &data);
+
+        let tags = read_tags(&path).unwrap().unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Massive Attack"));
+        assert_eq!(tags.album.as_deref(), Some("Mezzanine"));
+        assert_eq!(tags.track, Some(5));
+        assert_eq!(tags.year, Some(1998));
+    }
+
+    #[test]
+    fn test_empty_m4a_artist_is_missing() {
+        let dir = tempdir().unwrap();
+        let mut ilst = mp4_item(b"\xa9ART", b"  ");
+        ilst.extend_from_slice(&mp4_item(b"\xa9alb", b"Mezzanine"));
+        let mut meta = vec![0u8; 4];
+        meta.extend_from_slice(&mp4_box(b"ilst", &ilst));
+        let mut data = mp4_box(b"ftyp", b"M4A \0\0\0\0");
+        data.extend_from_slice(&mp4_box(b"moov", &mp4_box(b"udta", &mp4_box(b"meta", &meta))));
+        let path = write(dir.path(), "song.m4a", &data);
+
+        let tags = read_tags(&path).unwrap().unwrap();
+        assert_eq!(tags.artist, None);
+        assert_eq!(tags.album.as_deref(), Some("Mezzanine"));
+    }
+
+    #[test]
+    fn test_m4a_with_huge_largesize() {
+        // ftyp, then a `free` box whose 64-bit size runs past the end of
+        // the file and would wrap the offset back to 0
+        let dir = tempdir().unwrap();
+        let mut data = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(&(u64::MAX - 23).to_be_bytes());
+        assert_eq!(data.len(), 40);
+        let path = write(dir.path(), "song.m4a", &data);
+
+        assert_eq!(read_tags(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_untagged_file() {
+        let dir = tempdir().unwrap();
+        let path = write(dir.path(), "song.wav", b"RIFF\0\0\0\0WAVE");
+        assert_eq!(read_tags(&path).unwrap(), None);
+    }
+}
@@ -29,10 +29,21 @@ impl FileCategory {
             FileCategory::Other => "Other",
         }
     }
+
+    /// Looks a category up by its folder name, ignoring case.
+    pub fn from_name(name: &str) -> Option<FileCategory> {
+        CategoryMapper::new()
+            .all_categories()
+            .into_iter()
+            .find(|category| category.folder_name().eq_ignore_ascii_case(name))
+    }
 }
 
 pub struct CategoryMapper {
     extension_map: HashMap<String, FileCategory>,
+    /// Second-level folder inside the category, e.g. `Spreadsheets`
+    subcategory_map: HashMap<String, &'static str>,
+    subcategories: bool,
 }
 
 impl CategoryMapper {
@@ -40,12 +51,12 @@ impl CategoryMapper {
         let mut extension_map = HashMap::new();
 
         // Images
-        for ext in ["jpg", "jpeg", "png", "gif", "bmp", "svg", "webp", "ico", "tiff", "tif", "raw", "heic", "heif", "psd", "ai", "eps"] {
+        for ext in ["jpg", "jpeg", "png", "gif", "bmp", "svg", "webp", "ico", "tiff", "tif", "raw", "heic", "heif", "psd", "ai", "eps", "cr2", "cr3", "nef", "arw", "dng", "orf", "rw2", "raf", "srw", "pef"] {
             extension_map.insert(ext.to_string(), FileCategory::Images);
         }
 
         // Documents
-        for ext in ["pdf", "doc", "docx", "txt", "rtf", "odt", "xls", "xlsx", "ods", "ppt", "pptx", "odp", "md", "tex", "pages", "numbers", "keynote", "epub", "mobi"] {
+        for ext in ["pdf", "doc", "docx", "txt", "rtf", "odt", "xls", "xlsx", "ods", "ppt", "pptx", "odp", "md", "tex", "pages", "numbers", "keynote", "key", "epub", "mobi", "azw", "azw3", "fb2"] {
             extension_map.insert(ext.to_string(), FileCategory::Documents);
         }
 
@@ -60,7 +71,7 @@ impl CategoryMapper {
         }
 
         // Archives
-        for ext in ["zip", "rar", "7z", "tar", "gz", "bz2", "xz", "tgz", "tbz2", "cab", "iso", "dmg"] {
+        for ext in ["zip", "rar", "7z", "tar", "gz", "bz2", "xz", "tgz", "tbz2", "cab", "iso", "dmg", "img", "vhd", "vhdx", "vmdk", "qcow2"] {
             extension_map.insert(ext.to_string(), FileCategory::Archives);
         }
 
@@ -84,7 +95,34 @@ impl CategoryMapper {
             extension_map.insert(ext.to_string(), FileCategory::Fonts);
         }
 
-        Self { extension_map }
+        let mut subcategory_map = HashMap::new();
+        let subcategories: [(&'static str, &[&str]); 7] = [
+            ("RAW", &["raw", "cr2", "cr3", "nef", "arw", "dng", "orf", "rw2", "raf", "srw", "pef"]),
+            ("Vector", &["svg", "ai", "eps"]),
+            ("Spreadsheets", &["xls", "xlsx", "ods", "numbers"]),
+            ("Presentations", &["ppt", "pptx", "odp", "keynote", "key"]),
+            ("Ebooks", &["epub", "mobi", "azw", "azw3", "fb2"]),
+            ("Scripts", &["sh", "bash", "zsh", "fish", "ps1", "bat", "cmd"]),
+            ("DiskImages", &["iso", "dmg", "img", "vhd", "vhdx", "vmdk", "qcow2"]),
+        ];
+        for (folder, extensions) in subcategories {
+            for ext in extensions {
+                subcategory_map.insert(ext.to_string(), folder);
+            }
+        }
+
+        Self {
+            extension_map,
+            subcategory_map,
+            subcategories: true,
+        }
+    }
+
+    /// Sorts files into a second folder level such as `Documents/Spreadsheets`.
+    /// Turned off, every category is a single folder.
+    pub fn with_subcategories(mut self, subcategories: bool) -> Self {
+        self.subcategories = subcategories;
+        self
     }
 
     pub fn categorize(&self, extension: &str) -> FileCategory {
@@ -95,6 +133,13 @@ impl CategoryMapper {
             .unwrap_or(FileCategory::Other)
     }
 
+    pub fn subcategory(&self, extension: &str) -> Option<&'static str> {
+        if !self.subcategories {
+            return None;
+        }
+        self.subcategory_map.get(&extension.to_lowercase()).copied()
+    }
+
     pub fn all_categories(&self) -> Vec<FileCategory> {
         vec![
             FileCategory::Images,
@@ -143,6 +188,29 @@ mod tests {
         assert_eq!(mapper.categorize("xyz123"), FileCategory::Other);
     }
 
+    #[test]
+    fn test_subcategories() {
+        let mapper = CategoryMapper::new();
+        assert_eq!(mapper.categorize("NEF"), FileCategory::Images);
+        assert_eq!(mapper.subcategory("NEF"), Some("RAW"));
+        assert_eq!(mapper.subcategory("svg"), Some("Vector"));
+        assert_eq!(mapper.subcategory("xlsx"), Some("Spreadsheets"));
+        assert_eq!(mapper.subcategory("epub"), Some("Ebooks"));
+        assert_eq!(mapper.subcategory("iso"), Some("DiskImages"));
+        assert_eq!(mapper.subcategory("pdf"), None);
+
+        let flat = CategoryMapper::new().with_subcategories(false);
+        assert_eq!(flat.categorize("xlsx"), FileCategory::Documents);
+        assert_eq!(flat.subcategory("xlsx"), None);
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(FileCategory::from_name("images"), Some(FileCategory::Images));
+        assert_eq!(FileCategory::from_name("Audio"), Some(FileCategory::Audio));
+        assert_eq!(FileCategory::from_name("nope"), None);
+    }
+
     #[test]
     fn test_folder_names() {
         assert_eq!(FileCategory::Images.folder_name(), "Images");
@@ -0,0 +1,539 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use crate::copy::ReflinkMode;
+use crate::organizer::{ConflictStrategy, TransferMode};
+use crate::sanitize::{CaseStyle, SanitizeStep};
+use crate::scanner::SymlinkPolicy;
+use crate::shard::ShardScheme;
+
+/// `~/.config/file-organizer/config.toml`, or the same under
+/// `$XDG_CONFIG_HOME`.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").filter(|home| !home.is_empty())?).join(".config"),
+    };
+    Some(config_home.join("file-organizer/config.toml"))
+}
+
+/// A named set of options from a `[profiles.<name>]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub source: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub mode: Option<TransferMode>,
+    pub name_template: Option<String>,
+    /// `[CATEGORY=]TEMPLATE` specs, like `--layout`
+    pub layout: Vec<String>,
+    /// Rule specs, like `--rule`
+    pub rules: Vec<String>,
+    pub on_conflict: Option<ConflictStrategy>,
+    pub recursive: Option<bool>,
+    pub one_file_system: Option<bool>,
+    pub skip_fs_type: Vec<String>,
+    pub symlinks: Option<SymlinkPolicy>,
+    pub flatten: Option<bool>,
+    pub prune_empty: Option<bool>,
+    pub max_per_folder: Option<u64>,
+    pub shard_by: Option<ShardScheme>,
+    pub sanitize: Vec<SanitizeStep>,
+    pub case: Option<CaseStyle>,
+    pub reflink: Option<ReflinkMode>,
+}
+
+impl Profile {
+    /// One `key = value` line per setting, for `profiles list`.
+    pub fn settings(&self) -> Vec<String> {
+        let mut settings = Vec::new();
+        let mut add = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                settings.push(format!("{} = {}", key, value));
+            }
+        };
+        let list = |values: &[String]| (!values.is_empty()).then(|| values.join(", "));
+
+        add("source", self.source.as_ref().map(|path| path.display().to_string()));
+        add("output", self.output.as_ref().map(|path| path.display().to_string()));
+        add("mode", self.mode.map(|mode| value_name(&mode)));
+        add("name_template", self.name_template.clone());
+        add("layout", list(&self.layout));
+        add("rules", list(&self.rules));
+        add("on_conflict", self.on_conflict.map(|strategy| value_name(&strategy)));
+        add("recursive", self.recursive.map(|set| set.to_string()));
+        add("one_file_system", self.one_file_system.map(|set| set.to_string()));
+        add("skip_fs_type", list(&self.skip_fs_type));
+        add("symlinks", self.symlinks.map(|policy| value_name(&policy)));
+        add("flatten", self.flatten.map(|set| set.to_string()));
+        add("prune_empty", self.prune_empty.map(|set| set.to_string()));
+        add("max_per_folder", self.max_per_folder.map(|max| max.to_string()));
+        add("shard_by", self.shard_by.map(|scheme| value_name(&scheme)));
+        let steps: Vec<String> = self.sanitize.iter().map(value_name).collect();
+        add("sanitize", list(&steps));
+        add("case", self.case.map(|style| value_name(&style)));
+        add("reflink", self.reflink.map(|mode| value_name(&mode)));
+        settings
+    }
+}
+
+pub fn value_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// The config file: for now, just its profiles.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// In the order of the file
+    pub profiles: Vec<Profile>,
+}
+
+impl Config {
+    /// Reads `path`. A missing file is an empty config unless `required`.
+    pub fn load(path: &Path, required: bool) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(e) => Err(format!("cannot read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Parses the TOML subset the config uses: `[profiles.<name>]` tables
+    /// holding strings, whole numbers, booleans and arrays of strings.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut parser = Parser { text, position: 0, line: 1 };
+
+        loop {
+            parser.skip_blank(true);
+            match parser.peek() {
+                None => break,
+                Some('[') => {
+                    let line = parser.line;
+                    let path = parser.table_header()?;
+                    let name = match path.as_slice() {
+                        [table, name] if table == "profiles" => name.clone(),
+                        _ => return Err(format!("line {}: unknown section [{}]", line, path.join("."))),
+                    };
+                    if config.profile(&name).is_some() {
+                        return Err(format!("line {}: profile '{}' is defined twice", line, name));
+                    }
+                    config.profiles.push(Profile { name, ..Profile::default() });
+                }
+                Some(_) => {
+                    let line = parser.line;
+                    let (key, value) = parser.key_value()?;
+                    let profile = config
+                        .profiles
+                        .last_mut()
+                        .ok_or_else(|| format!("line {}: '{}' is outside a [profiles.<name>] section", line, key))?;
+                    set(profile, &key, value).map_err(|e| format!("line {}: {}", line, e))?;
+                }
+            }
+            parser.end_of_line()?;
+        }
+
+        Ok(config)
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Integer(u64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+fn set(profile: &mut Profile, key: &str, value: Value) -> Result<(), String> {
+    let key = key.replace('-', "_");
+    match key.as_str() {
+        "source" => profile.source = Some(expand_home(&string(&key, value)?)),
+        "output" => profile.output = Some(expand_home(&string(&key, value)?)),
+        "mode" => profile.mode = Some(choice(&key, value)?),
+        "name_template" => profile.name_template = Some(string(&key, value)?),
+        "layout" => profile.layout = strings(&key, value)?,
+        "rules" => profile.rules = strings(&key, value)?,
+        "on_conflict" => profile.on_conflict = Some(choice(&key, value)?),
+        "recursive" => profile.recursive = Some(boolean(&key, value)?),
+        "one_file_system" => profile.one_file_system = Some(boolean(&key, value)?),
+        "skip_fs_type" => profile.skip_fs_type = strings(&key, value)?,
+        "symlinks" => profile.symlinks = Some(choice(&key, value)?),
+        "flatten" => profile.flatten = Some(boolean(&key, value)?),
+        "prune_empty" => profile.prune_empty = Some(boolean(&key, value)?),
+        "max_per_folder" => profile.max_per_folder = Some(positive(&key, value)?),
+        "shard_by" => profile.shard_by = Some(choice(&key, value)?),
+        "sanitize" => {
+            profile.sanitize = strings(&key, value)?
+                .into_iter()
+                .map(|step| choice(&key, Value::String(step)))
+                .collect::<Result<_, _>>()?
+        }
+        "case" => profile.case = Some(choice(&key, value)?),
+        "reflink" => profile.reflink = Some(choice(&key, value)?),
+        _ => return Err(format!("unknown setting '{}'", key)),
+    }
+    Ok(())
+}
+
+fn string(key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::String(text) => Ok(text),
+        _ => Err(format!("'{}' must be a string", key)),
+    }
+}
+
+/// A single string or an array of them.
+fn strings(key: &str, value: Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::String(text) => Ok(vec![text]),
+        Value::Array(values) => values.into_iter().map(|value| string(key, value)).collect(),
+        _ => Err(format!("'{}' must be a string or an array of strings", key)),
+    }
+}
+
+fn positive(key: &str, value: Value) -> Result<u64, String> {
+    match value {
+        Value::Integer(number) if number > 0 => Ok(number),
+        _ => Err(format!("'{}' must be a number above 0", key)),
+    }
+}
+
+fn boolean(key: &str, value: Value) -> Result<bool, String> {
+    match value {
+        Value::Bool(set) => Ok(set),
+        _ => Err(format!("'{}' must be true or false", key)),
+    }
+}
+
+/// One of the values the matching command-line option takes.
+fn choice<T: ValueEnum>(key: &str, value: Value) -> Result<T, String> {
+    let text = string(key, value)?;
+    T::from_str(&text, true).map_err(|_| {
+        let names: Vec<String> = T::value_variants().iter().map(value_name).collect();
+        format!("'{}' must be one of {}, not '{}'", key, names.join(", "), text)
+    })
+}
+
+/// `~/Downloads` is under the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {}", self.line, message)
+    }
+
+    /// Skips spaces and comments, and line breaks too if `newlines`.
+    fn skip_blank(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => {}
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.next();
+                    }
+                    continue;
+                }
+                _ => return,
+            }
+            self.next();
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_blank(false);
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    fn table_header(&mut self) -> Result<Vec<String>, String> {
+        self.expect('[')?;
+        let mut path = Vec::new();
+        loop {
+            self.skip_blank(false);
+            path.push(self.key()?);
+            self.skip_blank(false);
+            match self.next() {
+                Some('.') => continue,
+                Some(']') => return Ok(path),
+                _ => return Err(self.error("malformed section header")),
+            }
+        }
+    }
+
+    fn key_value(&mut self) -> Result<(String, Value), String> {
+        let key = self.key()?;
+        self.skip_blank(false);
+        self.expect('=')?;
+        self.skip_blank(false);
+        Ok((key, self.value()?))
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let start = self.position;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                    self.next();
+                }
+                if start == self.position {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(self.text[start..self.position].to_string())
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            _ if self.text[self.position..].starts_with("true") => {
+                self.position += 4;
+                Ok(Value::Bool(true))
+            }
+            _ if self.text[self.position..].starts_with("false") => {
+                self.position += 5;
+                Ok(Value::Bool(false))
+            }
+            Some(c) if c.is_ascii_digit() => self.integer(),
+            _ => Err(self.error("expected a string, number, true, false or an array")),
+        }
+    }
+
+    fn integer(&mut self) -> Result<Value, String> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '_') {
+            self.next();
+        }
+        self.text[start..self.position]
+            .replace('_', "")
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| self.error("number out of range"))
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_blank(true);
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip_blank(true);
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('u') => {
+                        let start = self.position;
+                        for _ in 0..4 {
+                            self.next();
+                        }
+                        let c = u32::from_str_radix(&self.text[start..self.position], 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| self.error("invalid \\u escape"))?;
+                        text.push(c);
+                    }
+                    _ => return Err(self.error("invalid escape in string")),
+                },
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let start = self.position;
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(self.text[start..self.position - 1].to_string()),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+# Daily cleanups
+[profiles.downloads]
+source = "/home/me/Downloads"
+output = "/home/me/Organized"
+mode = "move"
+on_conflict = "rename"  # keep both
+recursive = true
+
+[profiles."camera imports"]
+source = '/media/card/DCIM'
+output = "/home/me/Photos"
+mode = "copy"
+layout = "Images={exif.date:%Y}/{exif.date:%m}"
+rules = [
+    "exif.model contains iPhone => Phones/{exif.date:%Y}",
+    "exif.model contains \"Canon\" => Canon",
+]
+skip-fs-type = ["nfs", "fuse"]
+symlinks = "skip"
+"#;
+
+    #[test]
+    fn test_parse_profiles() {
+        let config = Config::parse(EXAMPLE).unwrap();
+        assert_eq!(config.profiles.len(), 2);
+
+        let downloads = config.profile("downloads").unwrap();
+        assert_eq!(downloads.source, Some(PathBuf::from("/home/me/Downloads")));
+        assert_eq!(downloads.mode, Some(TransferMode::Move));
+        assert_eq!(downloads.on_conflict, Some(ConflictStrategy::Rename));
+        assert_eq!(downloads.recursive, Some(true));
+        assert_eq!(downloads.one_file_system, None);
+
+        let camera = config.profile("camera imports").unwrap();
+        assert_eq!(camera.source, Some(PathBuf::from("/media/card/DCIM")));
+        assert_eq!(camera.mode, Some(TransferMode::Copy));
+        assert_eq!(camera.layout, vec!["Images={exif.date:%Y}/{exif.date:%m}"]);
+        assert_eq!(camera.rules[1], "exif.model contains \"Canon\" => Canon");
+        assert_eq!(camera.skip_fs_type, vec!["nfs", "fuse"]);
+        assert_eq!(camera.symlinks, Some(SymlinkPolicy::Skip));
+    }
+
+    #[test]
+    fn test_errors_name_the_line() {
+        let cases = [
+            ("[profiles.a]\nmode = \"shred\"\n", "line 2: 'mode' must be one of move, copy, not 'shred'"),
+            ("[profiles.a]\ncolour = \"red\"\n", "line 2: unknown setting 'colour'"),
+            ("source = \"/x\"\n", "line 1: 'source' is outside a [profiles.<name>] section"),
+            ("[settings]\n", "line 1: unknown section [settings]"),
+            ("[profiles.a]\n[profiles.a]\n", "line 2: profile 'a' is defined twice"),
+            ("[profiles.a]\nsource = \"/x\" extra\n", "line 2: unexpected 'e'"),
+            ("[profiles.a]\nrecursive = yes\n", "line 2: expected a string, number, true, false or an array"),
+            ("[profiles.a]\nmax_per_folder = 0\n", "line 2: 'max_per_folder' must be a number above 0"),
+            ("[profiles.a]\nmax_per_folder = \"100\"\n", "line 2: 'max_per_folder' must be a number above 0"),
+        ];
+        for (text, error) in cases {
+            assert_eq!(Config::parse(text).unwrap_err(), error, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_folder_and_name_settings() {
+        let text = r#"
+[profiles.archive]
+flatten = true
+prune-empty = true
+max_per_folder = 1_000
+shard_by = "alpha"
+sanitize = ["nfc", "whitespace"]
+case = "kebab"
+reflink = "never"
+"#;
+        let config = Config::parse(text).unwrap();
+        let archive = config.profile("archive").unwrap();
+        assert_eq!(archive.flatten, Some(true));
+        assert_eq!(archive.prune_empty, Some(true));
+        assert_eq!(archive.max_per_folder, Some(1000));
+        assert_eq!(archive.shard_by, Some(ShardScheme::Alpha));
+        assert_eq!(archive.sanitize, vec![SanitizeStep::Nfc, SanitizeStep::Whitespace]);
+        assert_eq!(archive.case, Some(CaseStyle::Kebab));
+        assert_eq!(archive.reflink, Some(ReflinkMode::Never));
+        assert!(archive.settings().contains(&"sanitize = nfc, whitespace".to_string()));
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let path = Path::new("/nonexistent/file-organizer/config.toml");
+        assert_eq!(Config::load(path, false).unwrap(), Config::default());
+        assert!(Config::load(path, true).is_err());
+    }
+
+    #[test]
+    fn test_settings_listing() {
+        let config = Config::parse(EXAMPLE).unwrap();
+        let settings = config.profile("downloads").unwrap().settings();
+        assert_eq!(
+            settings,
+            vec![
+                "source = /home/me/Downloads",
+                "output = /home/me/Organized",
+                "mode = move",
+                "on_conflict = rename",
+                "recursive = true",
+            ]
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use std::fs::File;
+use std::io;
+
+use clap::ValueEnum;
+
+/// Whether copies may share data blocks with their source (copy-on-write).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ReflinkMode {
+    /// Clone when the filesystem supports it, copy otherwise
+    #[default]
+    Auto,
+    /// Fail files that cannot be cloned
+    Always,
+    /// Always write a full, independent copy
+    Never,
+}
+
+/// Bytes written by copies, split by how they got there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyStats {
+    /// Shared with the source through a reflink, using no new space
+    pub cloned_bytes: u64,
+    /// Duplicated on disk
+    pub copied_bytes: u64,
+    /// Holes in sparse files that were kept as holes instead of written out
+    pub sparse_bytes: u64,
+}
+
+impl CopyStats {
+    pub fn add(&mut self, other: CopyStats) {
+        self.cloned_bytes += other.cloned_bytes;
+        self.copied_bytes += other.copied_bytes;
+        self.sparse_bytes += other.sparse_bytes;
+    }
+}
+
+/// Copies the contents of `source` into the empty file `destination`.
+///
+/// On Linux this tries, in order, a `FICLONE` reflink (Btrfs, XFS and other
+/// copy-on-write filesystems), `copy_file_range` (which avoids moving the
+/// data through user space) and a plain read/write loop. `Never` skips both
+/// kernel paths, since `copy_file_range` may share extents as well.
+///
+/// Holes in sparse sources are found with `SEEK_DATA` / `SEEK_HOLE` and
+/// left as holes in the destination.
+pub fn copy_contents(source: &File, destination: &File, mode: ReflinkMode) -> io::Result<CopyStats> {
+    let metadata = source.metadata()?;
+    let length = metadata.len();
+
+    if mode != ReflinkMode::Never {
+        match clone_file(source, destination) {
+            Ok(()) => {
+                return Ok(CopyStats {
+                    cloned_bytes: length,
+                    ..CopyStats::default()
+                })
+            }
+            Err(e) if mode == ReflinkMode::Always => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("cannot clone file (--reflink always): {}", e),
+                ));
+            }
+            Err(_) => {}
+        }
+    }
+
+    if is_sparse(&metadata) {
+        if let Some(copied_bytes) = copy_sparse(source, destination, length, mode)? {
+            return Ok(CopyStats {
+                copied_bytes,
+                sparse_bytes: length - copied_bytes,
+                ..CopyStats::default()
+            });
+        }
+    }
+
+    let copied_bytes = if mode == ReflinkMode::Auto {
+        copy_range(source, destination)?
+    } else {
+        read_write(source, destination)?
+    };
+
+    Ok(CopyStats {
+        copied_bytes,
+        ..CopyStats::default()
+    })
+}
+
+/// Fewer blocks allocated than the size needs means the file has holes.
+#[cfg(unix)]
+fn is_sparse(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512 < metadata.len()
+}
+
+#[cfg(not(unix))]
+fn is_sparse(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// Copies only the data segments of `source` and sizes `destination` to
+/// match, so the gaps between them stay holes. Returns the bytes copied, or
+/// `None` when the filesystem cannot report where the data is.
+#[cfg(target_os = "linux")]
+fn copy_sparse(source: &File, destination: &File, length: u64, mode: ReflinkMode) -> io::Result<Option<u64>> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = source.as_raw_fd();
+    let mut offset = 0u64;
+    let mut copied = 0u64;
+
+    while offset < length {
+        let data = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            let error = io::Error::last_os_error();
+            match error.raw_os_error() {
+                // No data after `offset`: the rest is one hole
+                Some(libc::ENXIO) => break,
+                Some(libc::EINVAL | libc::EOPNOTSUPP) if offset == 0 => return Ok(None),
+                _ => return Err(error),
+            }
+        }
+        // Data past the size taken at the start was written since
+        if data as u64 >= length {
+            break;
+        }
+
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let end = (hole as u64).min(length);
+        let segment = end.saturating_sub(data as u64);
+        copy_segment(source, destination, data as u64, segment, mode)?;
+        copied += segment;
+        offset = end;
+    }
+
+    destination.set_len(length)?;
+    Ok(Some(copied))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_sparse(_source: &File, _destination: &File, _length: u64, _mode: ReflinkMode) -> io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Copies `length` bytes at `offset` to the same offset in `destination`.
+#[cfg(target_os = "linux")]
+fn copy_segment(source: &File, destination: &File, offset: u64, length: u64, mode: ReflinkMode) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::AsRawFd;
+
+    let mut done = 0u64;
+
+    if mode == ReflinkMode::Auto {
+        while done < length {
+            let mut in_offset = (offset + done) as libc::loff_t;
+            let mut out_offset = in_offset;
+            let result = unsafe {
+                libc::copy_file_range(
+                    source.as_raw_fd(),
+                    &mut in_offset,
+                    destination.as_raw_fd(),
+                    &mut out_offset,
+                    (length - done) as usize,
+                    0,
+                )
+            };
+            match result {
+                0 => break,
+                n if n > 0 => done += n as u64,
+                _ if io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) => continue,
+                // Finish with read/write below
+                _ => break,
+            }
+        }
+    }
+
+    let mut buffer = vec![0u8; 256 * 1024];
+    while done < length {
+        let want = buffer.len().min((length - done) as usize);
+        let read = source.read_at(&mut buffer[..want], offset + done)?;
+        if read == 0 {
+            break; // the file shrank while we copied
+        }
+        destination.write_all_at(&buffer[..read], offset + done)?;
+        done += read as u64;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn clone_file(source: &File, destination: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::ioctl(destination.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_file(_source: &File, _destination: &File) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "reflinks are only supported on Linux"))
+}
+
+/// Copies with `copy_file_range`, finishing with read/write if the kernel or
+/// filesystem turns it down (old kernels refuse to copy across filesystems).
+#[cfg(target_os = "linux")]
+fn copy_range(source: &File, destination: &File) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut copied = 0u64;
+    loop {
+        let result = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                std::ptr::null_mut(),
+                destination.as_raw_fd(),
+                std::ptr::null_mut(),
+                1 << 30,
+                0,
+            )
+        };
+
+        match result {
+            0 => return Ok(copied),
+            n if n > 0 => copied += n as u64,
+            _ => {
+                let error = io::Error::last_os_error();
+                match error.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    // Both files are positioned after what was copied so far
+                    Some(libc::ENOSYS | libc::EXDEV | libc::EOPNOTSUPP | libc::EINVAL | libc::EPERM) => {
+                        return Ok(copied + read_write(source, destination)?);
+                    }
+                    _ => return Err(error),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_range(source: &File, destination: &File) -> io::Result<u64> {
+    read_write(source, destination)
+}
+
+fn read_write(mut source: &File, mut destination: &File) -> io::Result<u64> {
+    io::copy(&mut source, &mut destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn copy_with(mode: ReflinkMode) -> io::Result<(CopyStats, String)> {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("source"), "hello, copy-on-write").unwrap();
+
+        let source = File::open(dir.path().join("source")).unwrap();
+        let destination = File::create(dir.path().join("destination")).unwrap();
+        let stats = copy_contents(&source, &destination, mode)?;
+        Ok((stats, fs::read_to_string(dir.path().join("destination")).unwrap()))
+    }
+
+    #[test]
+    fn test_auto_copies_everything() {
+        let (stats, contents) = copy_with(ReflinkMode::Auto).unwrap();
+
+        assert_eq!(contents, "hello, copy-on-write");
+        // Cloned on copy-on-write filesystems, copied everywhere else
+        assert_eq!(stats.cloned_bytes + stats.copied_bytes, 20);
+    }
+
+    #[test]
+    fn test_never_copies_physically() {
+        let (stats, contents) = copy_with(ReflinkMode::Never).unwrap();
+
+        assert_eq!(contents, "hello, copy-on-write");
+        assert_eq!(stats, CopyStats { copied_bytes: 20, ..CopyStats::default() });
+    }
+
+    #[test]
+    fn test_always_clones_or_fails() {
+        match copy_with(ReflinkMode::Always) {
+            Ok((stats, contents)) => {
+                assert_eq!(contents, "hello, copy-on-write");
+                assert_eq!(stats, CopyStats { cloned_bytes: 20, ..CopyStats::default() });
+            }
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sparse_file_keeps_holes() {
+        use std::os::unix::fs::{FileExt, MetadataExt};
+
+        let dir = tempdir().unwrap();
+        let source_path = dir.path().join("disk.img");
+        let source = File::create(&source_path).unwrap();
+        source.set_len(16 * 1024 * 1024).unwrap();
+        source.write_all_at(b"boot", 0).unwrap();
+        source.write_all_at(b"middle", 8 * 1024 * 1024).unwrap();
+        source.sync_all().unwrap();
+        drop(source);
+
+        // Nothing to test on filesystems that fill holes in
+        let source_meta = fs::metadata(&source_path).unwrap();
+        if source_meta.blocks() * 512 >= source_meta.len() {
+            return;
+        }
+
+        for mode in [ReflinkMode::Never, ReflinkMode::Auto] {
+            let destination_path = dir.path().join(format!("copy-{:?}.img", mode));
+            let source = File::open(&source_path).unwrap();
+            let destination = File::create(&destination_path).unwrap();
+            let stats = copy_contents(&source, &destination, mode).unwrap();
+            drop(destination);
+
+            let destination_meta = fs::metadata(&destination_path).unwrap();
+            assert_eq!(destination_meta.len(), 16 * 1024 * 1024);
+            assert!(destination_meta.blocks() * 512 < 1024 * 1024);
+            if stats.cloned_bytes == 0 {
+                assert!(stats.sparse_bytes > 15 * 1024 * 1024);
+                assert_eq!(stats.copied_bytes + stats.sparse_bytes, 16 * 1024 * 1024);
+            }
+            assert_eq!(fs::read(&destination_path).unwrap(), fs::read(&source_path).unwrap());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sparse_copy_stops_at_size_snapshot() {
+        use std::os::unix::fs::{FileExt, MetadataExt};
+
+        let dir = tempdir().unwrap();
+        let source_path = dir.path().join("growing.img");
+        let source = File::create(&source_path).unwrap();
+        source.set_len(16 * 1024 * 1024).unwrap();
+        source.write_all_at(b"appended", 12 * 1024 * 1024).unwrap();
+        drop(source);
+
+        // Nothing to test on filesystems that fill holes in
+        let source_meta = fs::metadata(&source_path).unwrap();
+        if source_meta.blocks() * 512 >= source_meta.len() {
+            return;
+        }
+
+        // As if the file had grown past 4 MiB after its size was taken
+        let source = File::open(&source_path).unwrap();
+        let destination = File::create(dir.path().join("copy.img")).unwrap();
+        if let Some(copied) = copy_sparse(&source, &destination, 4 * 1024 * 1024, ReflinkMode::Never).unwrap() {
+            assert_eq!(copied, 0);
+            assert_eq!(destination.metadata().unwrap().len(), 4 * 1024 * 1024);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MONTH_NAMES: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+/// Broken-down calendar time used when rendering dates into names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    /// Converts to local time where the platform can tell us the offset,
+    /// UTC otherwise.
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let seconds = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+        Self::from_unix_local(seconds)
+    }
+
+    #[cfg(unix)]
+    fn from_unix_local(seconds: i64) -> Self {
+        let time = seconds as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+            return Self::from_unix_utc(seconds);
+        }
+
+        Self {
+            year: tm.tm_year + 1900,
+            month: tm.tm_mon as u32 + 1,
+            day: tm.tm_mday as u32,
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+            second: tm.tm_sec as u32,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn from_unix_local(seconds: i64) -> Self {
+        Self::from_unix_utc(seconds)
+    }
+
+    pub fn from_unix_utc(seconds: i64) -> Self {
+        let days = seconds.div_euclid(86_400);
+        let secs_of_day = seconds.rem_euclid(86_400) as u32;
+
+        // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+
+        Self {
+            year,
+            month,
+            day,
+            hour: secs_of_day / 3600,
+            minute: secs_of_day % 3600 / 60,
+            second: secs_of_day % 60,
+        }
+    }
+
+    /// Parses the date and time of `2023-07-14T09:30:00Z` style timestamps.
+    /// Any time zone suffix is ignored; a missing time reads as midnight.
+    pub fn parse_iso8601(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (date, time) = match text.split_once(['T', ' ']) {
+            Some((date, time)) => (date, time),
+            None => (text, ""),
+        };
+
+        let mut date_parts = date.split('-');
+        let year = date_parts.next()?.parse::<i32>().ok()?;
+        let month = date_parts.next().map_or(Some(1), |part| part.parse::<u32>().ok())?;
+        let day = date_parts.next().map_or(Some(1), |part| part.parse::<u32>().ok())?;
+
+        let time: String = time.chars().take_while(|c| c.is_ascii_digit() || *c == ':').collect();
+        let mut time_parts = time.split(':').map(|part| part.parse::<u32>().unwrap_or(0));
+        let hour = time_parts.next().unwrap_or(0);
+        let minute = time_parts.next().unwrap_or(0);
+        let second = time_parts.next().unwrap_or(0);
+
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+
+        Some(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// Formats with a strftime-like subset: `%Y %y %m %d %H %M %S %B %b %%`.
+    pub fn format(&self, spec: &str) -> String {
+        let mut output = String::new();
+        let mut chars = spec.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('Y') => output.push_str(&format!("{:04}", self.year)),
+                Some('y') => output.push_str(&format!("{:02}", self.year.rem_euclid(100))),
+                Some('m') => output.push_str(&format!("{:02}", self.month)),
+                Some('d') => output.push_str(&format!("{:02}", self.day)),
+                Some('H') => output.push_str(&format!("{:02}", self.hour)),
+                Some('M') => output.push_str(&format!("{:02}", self.minute)),
+                Some('S') => output.push_str(&format!("{:02}", self.second)),
+                Some('B') => output.push_str(self.month_name()),
+                Some('b') => output.push_str(&self.month_name()[..3]),
+                Some('%') => output.push('%'),
+                Some(other) => {
+                    output.push('%');
+                    output.push(other);
+                }
+                None => output.push('%'),
+            }
+        }
+
+        output
+    }
+
+    fn month_name(&self) -> &'static str {
+        MONTH_NAMES[(self.month.clamp(1, 12) - 1) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_unix_utc() {
+        let epoch = DateTime::from_unix_utc(0);
+        assert_eq!((epoch.year, epoch.month, epoch.day), (1970, 1, 1));
+
+        // 2024-02-29 12:34:56 UTC
+        let leap = DateTime::from_unix_utc(1_709_210_096);
+        assert_eq!((leap.year, leap.month, leap.day), (2024, 2, 29));
+        assert_eq!((leap.hour, leap.minute, leap.second), (12, 34, 56));
+    }
+
+    #[test]
+    fn test_parse_iso8601() {
+        let time = DateTime::parse_iso8601("2023-07-14T09:30:15Z").unwrap();
+        assert_eq!(time.format("%Y-%m-%d %H:%M:%S"), "2023-07-14 09:30:15");
+
+        let date_only = DateTime::parse_iso8601("2021-02-03").unwrap();
+        assert_eq!((date_only.year, date_only.month, date_only.day, date_only.hour), (2021, 2, 3, 0));
+
+        assert!(DateTime::parse_iso8601("2023-13-01").is_none());
+        assert!(DateTime::parse_iso8601("not a date").is_none());
+    }
+
+    #[test]
+    fn test_format() {
+        let time = DateTime::from_unix_utc(1_709_210_096);
+        assert_eq!(time.format("%Y-%m-%d"), "2024-02-29");
+        assert_eq!(time.format("%y%m%d_%H%M%S"), "240229_123456");
+        assert_eq!(time.format("%B %b 100%%"), "February Feb 100%");
+    }
+}
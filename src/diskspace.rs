@@ -0,0 +1,197 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::logging;
+
+/// Space kept free on the destination filesystem on top of the bytes to copy.
+pub const SAFETY_MARGIN_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Projected disk usage for the files that cannot simply be renamed into place.
+#[derive(Debug, Clone, Default)]
+pub struct SpaceEstimate {
+    pub cross_device_files: usize,
+    pub cross_device_bytes: u64,
+    pub available_bytes: u64,
+    pub margin_bytes: u64,
+}
+
+impl SpaceEstimate {
+    pub fn required_bytes(&self) -> u64 {
+        if self.cross_device_bytes == 0 {
+            0
+        } else {
+            self.cross_device_bytes + self.margin_bytes
+        }
+    }
+
+    pub fn is_sufficient(&self) -> bool {
+        self.required_bytes() <= self.available_bytes
+    }
+
+    pub fn print_report(&self) {
+        logging::print(&format!(
+            "  Files to copy: {} file(s), {}",
+            self.cross_device_files,
+            format_bytes(self.cross_device_bytes)
+        ));
+        logging::print(&format!(
+            "  Free space on destination: {} (safety margin {})",
+            format_bytes(self.available_bytes),
+            format_bytes(self.margin_bytes)
+        ));
+        if self.cross_device_bytes > 0 {
+            let remaining = self.available_bytes.saturating_sub(self.cross_device_bytes);
+            logging::print(&format!("  Free space after copying: {}", format_bytes(remaining)));
+        }
+    }
+}
+
+/// Returns the closest ancestor of `path` (including itself) that exists.
+///
+/// The output directory may not have been created yet, but the filesystem it
+/// will live on is the one holding its nearest existing parent.
+pub fn existing_ancestor(path: &Path) -> &Path {
+    let mut current = path;
+    while !current.exists() {
+        match current.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => current = parent,
+            _ => return Path::new("."),
+        }
+    }
+    current
+}
+
+/// Device the file lives on, or `None` where the platform does not expose it.
+#[cfg(unix)]
+pub fn metadata_device(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+pub fn metadata_device(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Bytes the file occupies on disk, which for sparse files is less than its
+/// length. Sparse copies keep their holes, so this is what a copy needs.
+#[cfg(unix)]
+pub fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+pub fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+pub fn device_id(path: &Path) -> io::Result<Option<u64>> {
+    Ok(metadata_device(&fs::metadata(existing_ancestor(path))?))
+}
+
+/// Bytes available to an unprivileged user on the filesystem holding `path`.
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let target = existing_ancestor(path);
+    let c_path = CString::new(target.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Free space is not measured on this platform, so the check never blocks.
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> io::Result<u64> {
+    Ok(u64::MAX)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_existing_ancestor_of_missing_path() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("a/b/c");
+        assert_eq!(existing_ancestor(&missing), dir.path());
+    }
+
+    #[test]
+    fn test_available_space_reports_value() {
+        let dir = tempdir().unwrap();
+        assert!(available_space(dir.path()).is_ok());
+    }
+
+    #[test]
+    fn test_estimate_sufficiency() {
+        let mut estimate = SpaceEstimate {
+            cross_device_files: 1,
+            cross_device_bytes: 100,
+            available_bytes: 200,
+            margin_bytes: 50,
+        };
+        assert!(estimate.is_sufficient());
+
+        estimate.available_bytes = 120;
+        assert!(!estimate.is_sufficient());
+    }
+
+    #[test]
+    fn test_no_copies_needs_no_space() {
+        let estimate = SpaceEstimate::default();
+        assert_eq!(estimate.required_bytes(), 0);
+        assert!(estimate.is_sufficient());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_allocated_size_of_sparse_file() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sparse.img");
+        fs::File::create(&path).unwrap().set_len(64 * 1024 * 1024).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.len(), 64 * 1024 * 1024);
+        // Nothing to test on filesystems that fill holes in
+        if metadata.blocks() * 512 >= metadata.len() {
+            return;
+        }
+        assert!(allocated_size(&metadata) < metadata.len());
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
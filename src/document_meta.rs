@@ -0,0 +1,680 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::datetime::DateTime;
+
+/// How much of a PDF is searched from each end for metadata.
+const PDF_WINDOW_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Largest zip member we are willing to inflate.
+const MAX_ZIP_MEMBER_BYTES: usize = 4 * 1024 * 1024;
+
+/// Metadata used to route documents.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub created: Option<DateTime>,
+    pub pages: Option<u32>,
+}
+
+impl DocumentInfo {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Reads metadata from PDF files and from DOCX, XLSX and PPTX containers.
+///
+/// Returns `Ok(None)` for other formats or when nothing useful was found.
+pub fn read_document_info(path: &Path) -> io::Result<Option<DocumentInfo>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 5];
+    let read = file.read(&mut magic)?;
+    let magic = &magic[..read];
+
+    let info = if magic.starts_with(b"%PDF-") {
+        Some(read_pdf(&mut file)?)
+    } else if magic.starts_with(b"PK\x03\x04") {
+        read_ooxml(&mut file)?
+    } else {
+        None
+    };
+
+    Ok(info.filter(|info| !info.is_empty()))
+}
+
+/// Loads the head and tail of a PDF: the trailer and xref sit at the end,
+/// while linearized files and most producers put the Info dictionary and the
+/// XMP stream near the start.
+fn read_pdf(file: &mut File) -> io::Result<DocumentInfo> {
+    let length = file.metadata()?.len();
+    let mut data = Vec::new();
+
+    if length <= PDF_WINDOW_BYTES * 2 {
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut data)?;
+    } else {
+        file.seek(SeekFrom::Start(0))?;
+        file.by_ref().take(PDF_WINDOW_BYTES).read_to_end(&mut data)?;
+        file.seek(SeekFrom::End(-(PDF_WINDOW_BYTES as i64)))?;
+        file.read_to_end(&mut data)?;
+    }
+
+    Ok(parse_pdf(&data))
+}
+
+pub fn parse_pdf(data: &[u8]) -> DocumentInfo {
+    let mut info = DocumentInfo::default();
+
+    if let Some(dictionary) = pdf_info_dictionary(data) {
+        info.title = pdf_string_entry(dictionary, b"/Title");
+        info.author = pdf_string_entry(dictionary, b"/Author");
+        info.created = pdf_string_entry(dictionary, b"/CreationDate").and_then(|date| parse_pdf_date(&date));
+    }
+
+    // XMP fills in whatever the Info dictionary did not have
+    if let Some(xmp) = find_between(data, b"<x:xmpmeta", b"</x:xmpmeta>") {
+        let xmp = String::from_utf8_lossy(xmp);
+        if info.title.is_none() {
+            info.title = xml_text(&xmp, "dc:title");
+        }
+        if info.author.is_none() {
+            info.author = xml_text(&xmp, "dc:creator");
+        }
+        if info.created.is_none() {
+            info.created = xml_text(&xmp, "xmp:CreateDate").and_then(|date| DateTime::parse_iso8601(&date));
+        }
+    }
+
+    info.pages = pdf_page_count(data);
+    info
+}
+
+/// Finds the Info dictionary referenced from the (last) trailer.
+fn pdf_info_dictionary(data: &[u8]) -> Option<&[u8]> {
+    let position = rfind(data, b"/Info")?;
+    let rest = skip_whitespace(&data[position + 5..]);
+
+    if rest.starts_with(b"<<") {
+        return dictionary_at(rest);
+    }
+
+    // Indirect reference: "/Info 12 0 R"
+    let reference: Vec<&[u8]> = rest.split(|b| b.is_ascii_whitespace()).filter(|part| !part.is_empty()).take(2).collect();
+    if reference.len() < 2 {
+        return None;
+    }
+    let mut marker = reference[0].to_vec();
+    marker.push(b' ');
+    marker.extend_from_slice(reference[1]);
+    marker.extend_from_slice(b" obj");
+
+    let mut search = 0;
+    while let Some(found) = find(&data[search..], &marker) {
+        let start = search + found;
+        // Make sure "2 0 obj" did not match the tail of "12 0 obj"
+        if start == 0 || !data[start - 1].is_ascii_digit() {
+            let object = skip_whitespace(&data[start + marker.len()..]);
+            if object.starts_with(b"<<") {
+                return dictionary_at(object);
+            }
+        }
+        search = start + marker.len();
+    }
+    None
+}
+
+/// Returns the `<< ... >>` dictionary at the start of `data`, honoring nesting.
+fn dictionary_at(data: &[u8]) -> Option<&[u8]> {
+    let mut depth = 0;
+    let mut index = 0;
+    let mut in_string = 0;
+
+    while index + 1 < data.len() {
+        match (data[index], data[index + 1]) {
+            (b'\\', _) if in_string > 0 => index += 1,
+            (b'(', _) => in_string += 1,
+            (b')', _) if in_string > 0 => in_string -= 1,
+            (b'<', b'<') if in_string == 0 => {
+                depth += 1;
+                index += 1;
+            }
+            (b'>', b'>') if in_string == 0 => {
+                depth -= 1;
+                index += 1;
+                if depth == 0 {
+                    return Some(&data[..=index]);
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+fn pdf_string_entry(dictionary: &[u8], key: &[u8]) -> Option<String> {
+    let mut search = 0;
+    loop {
+        let position = search + find(&dictionary[search..], key)?;
+        let after = &dictionary[position + key.len()..];
+        // "/Title" must not match "/TitleSort"
+        if after.first().is_some_and(|b| b.is_ascii_alphanumeric()) {
+            search = position + key.len();
+            continue;
+        }
+
+        let value = skip_whitespace(after);
+        let bytes = if value.starts_with(b"(") {
+            pdf_literal_string(value)?
+        } else if value.starts_with(b"<") && !value.starts_with(b"<<") {
+            pdf_hex_string(value)?
+        } else {
+            return None;
+        };
+
+        let text = decode_pdf_text(&bytes);
+        let text = text.trim();
+        return if text.is_empty() { None } else { Some(text.to_string()) };
+    }
+}
+
+fn pdf_literal_string(data: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut depth = 0;
+    let mut index = 0;
+
+    while index < data.len() {
+        let byte = data[index];
+        match byte {
+            b'(' => {
+                if depth > 0 {
+                    output.push(byte);
+                }
+                depth += 1;
+            }
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(output);
+                }
+                output.push(byte);
+            }
+            b'\\' => {
+                index += 1;
+                let escaped = *data.get(index)?;
+                match escaped {
+                    b'n' => output.push(b'\n'),
+                    b'r' => output.push(b'\r'),
+                    b't' => output.push(b'\t'),
+                    b'b' => output.push(0x08),
+                    b'f' => output.push(0x0C),
+                    b'0'..=b'7' => {
+                        let mut value = (escaped - b'0') as u32;
+                        for _ in 0..2 {
+                            match data.get(index + 1) {
+                                Some(digit @ b'0'..=b'7') => {
+                                    value = value * 8 + (digit - b'0') as u32;
+                                    index += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        output.push(value as u8);
+                    }
+                    b'\r' | b'\n' => {} // line continuation
+                    other => output.push(other),
+                }
+            }
+            _ => output.push(byte),
+        }
+        index += 1;
+    }
+    None
+}
+
+fn pdf_hex_string(data: &[u8]) -> Option<Vec<u8>> {
+    let end = data.iter().position(|b| *b == b'>')?;
+    let digits: Vec<u8> = data[1..end].iter().copied().filter(|b| b.is_ascii_hexdigit()).collect();
+    let value = |b: u8| (b as char).to_digit(16).unwrap_or(0) as u8;
+
+    Some(
+        digits
+            .chunks(2)
+            .map(|pair| value(pair[0]) << 4 | pair.get(1).map_or(0, |b| value(*b)))
+            .collect(),
+    )
+}
+
+/// Text strings are UTF-16BE with a BOM or PDFDocEncoding (close to Latin-1).
+fn decode_pdf_text(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(utf8) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        String::from_utf8_lossy(utf8).to_string()
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
+
+/// Parses PDF dates such as `D:20230714093000+02'00'`.
+pub fn parse_pdf_date(text: &str) -> Option<DateTime> {
+    let digits: String = text
+        .trim()
+        .trim_start_matches("D:")
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    if digits.len() < 4 {
+        return None;
+    }
+
+    let field = |start: usize, default: u32| -> u32 {
+        digits.get(start..start + 2).and_then(|part| part.parse().ok()).unwrap_or(default)
+    };
+
+    let date = DateTime {
+        year: digits[..4].parse().ok()?,
+        month: field(4, 1),
+        day: field(6, 1),
+        hour: field(8, 0),
+        minute: field(10, 0),
+        second: field(12, 0),
+    };
+
+    if !(1..=12).contains(&date.month) || !(1..=31).contains(&date.day) {
+        return None;
+    }
+    Some(date)
+}
+
+/// Uses the `/Count` of the page tree root, or counts page objects when the
+/// tree is hidden in compressed object streams.
+fn pdf_page_count(data: &[u8]) -> Option<u32> {
+    let mut best: Option<u32> = None;
+    let mut page_objects = 0;
+    let mut search = 0;
+
+    while let Some(found) = find(&data[search..], b"/Type") {
+        let position = search + found;
+        let rest = skip_whitespace(&data[position + 5..]);
+
+        if rest.starts_with(b"/Pages") {
+            // The /Count belongs to the dictionary around this /Type
+            let start = rfind(&data[..position], b"<<").unwrap_or(0);
+            if let Some(dictionary) = dictionary_at(&data[start..]) {
+                if let Some(count) = pdf_number_entry(dictionary, b"/Count") {
+                    best = Some(best.map_or(count, |best| best.max(count)));
+                }
+            }
+        } else if rest.starts_with(b"/Page") {
+            page_objects += 1;
+        }
+        search = position + 5;
+    }
+
+    best.or(if page_objects > 0 { Some(page_objects) } else { None })
+}
+
+fn pdf_number_entry(dictionary: &[u8], key: &[u8]) -> Option<u32> {
+    let position = find(dictionary, key)?;
+    let value = skip_whitespace(&dictionary[position + key.len()..]);
+    let digits: String = value.iter().take_while(|b| b.is_ascii_digit()).map(|&b| b as char).collect();
+    digits.parse().ok()
+}
+
+/// Reads `docProps/core.xml` (and `docProps/app.xml` for page counts) from an
+/// Office Open XML zip container.
+fn read_ooxml(file: &mut File) -> io::Result<Option<DocumentInfo>> {
+    let entries = zip_central_directory(file)?;
+    let core = match entries.iter().find(|entry| entry.name == "docProps/core.xml") {
+        Some(entry) => zip_member(file, entry)?,
+        None => return Ok(None),
+    };
+    let core = String::from_utf8_lossy(&core);
+
+    let mut info = DocumentInfo {
+        title: xml_text(&core, "dc:title"),
+        author: xml_text(&core, "dc:creator"),
+        created: xml_text(&core, "dcterms:created").and_then(|date| DateTime::parse_iso8601(&date)),
+        pages: None,
+    };
+
+    if let Some(entry) = entries.iter().find(|entry| entry.name == "docProps/app.xml") {
+        let app = zip_member(file, entry)?;
+        let app = String::from_utf8_lossy(&app);
+        info.pages = ["Pages", "Slides"]
+            .iter()
+            .find_map(|tag| xml_text(&app, tag)?.parse().ok());
+    }
+
+    Ok(Some(info))
+}
+
+struct ZipEntry {
+    name: String,
+    method: u16,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    local_header_offset: u64,
+}
+
+fn zip_central_directory(file: &mut File) -> io::Result<Vec<ZipEntry>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed zip archive");
+    let length = file.metadata()?.len();
+
+    // The end-of-central-directory record is within the last 64 KiB + 22 bytes
+    let tail_len = length.min(65_557);
+    file.seek(SeekFrom::Start(length - tail_len))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+
+    let eocd = rfind(&tail, b"PK\x05\x06").ok_or_else(invalid)?;
+    let record = tail.get(eocd..eocd + 22).ok_or_else(invalid)?;
+    let directory_size = le_u32(record, 12) as u64;
+    let directory_offset = le_u32(record, 16) as u64;
+
+    if directory_size > MAX_ZIP_MEMBER_BYTES as u64 {
+        return Err(invalid());
+    }
+    file.seek(SeekFrom::Start(directory_offset))?;
+    let mut directory = vec![0u8; directory_size as usize];
+    file.read_exact(&mut directory)?;
+
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while directory.get(offset..offset + 4) == Some(b"PK\x01\x02") {
+        let header = directory.get(offset..offset + 46).ok_or_else(invalid)?;
+        let name_len = le_u16(header, 28) as usize;
+        let extra_len = le_u16(header, 30) as usize;
+        let comment_len = le_u16(header, 32) as usize;
+        let name = directory.get(offset + 46..offset + 46 + name_len).ok_or_else(invalid)?;
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).to_string(),
+            method: le_u16(header, 10),
+            compressed_size: le_u32(header, 20) as u64,
+            uncompressed_size: le_u32(header, 24) as u64,
+            local_header_offset: le_u32(header, 42) as u64,
+        });
+        offset += 46 + name_len + extra_len + comment_len;
+    }
+
+    Ok(entries)
+}
+
+fn zip_member(file: &mut File, entry: &ZipEntry) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if entry.compressed_size > MAX_ZIP_MEMBER_BYTES as u64 || entry.uncompressed_size > MAX_ZIP_MEMBER_BYTES as u64 {
+        return Err(invalid("zip member too large"));
+    }
+
+    let mut header = [0u8; 30];
+    file.seek(SeekFrom::Start(entry.local_header_offset))?;
+    file.read_exact(&mut header)?;
+    if &header[..4] != b"PK\x03\x04" {
+        return Err(invalid("malformed zip local header"));
+    }
+    let skip = le_u16(&header, 26) as i64 + le_u16(&header, 28) as i64;
+    file.seek(SeekFrom::Current(skip))?;
+
+    let mut compressed = vec![0u8; entry.compressed_size as usize];
+    file.read_exact(&mut compressed)?;
+
+    match entry.method {
+        0 => Ok(compressed),
+        8 => miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, MAX_ZIP_MEMBER_BYTES)
+            .map_err(|_| invalid("cannot inflate zip member")),
+        _ => Err(invalid("unsupported zip compression method")),
+    }
+}
+
+fn le_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Text of the first `<tag ...>...</tag>` element with entities decoded.
+/// XMP wraps titles and creators in `rdf:Alt` / `rdf:Seq` lists, in which
+/// case the first list item is used.
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let content = xml_element(xml, tag)?;
+    let content = if content.contains("<rdf:li") {
+        xml_element(content, "rdf:li")?
+    } else {
+        content
+    };
+
+    let text = decode_xml_entities(content);
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Raw content of the first `<tag ...>...</tag>` element.
+fn xml_element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}", tag);
+    let mut search = 0;
+
+    loop {
+        let start = search + xml[search..].find(&open)?;
+        let after_name = &xml[start + open.len()..];
+        // "<dc:title" must not match "<dc:titles"
+        if !after_name.starts_with(['>', ' ', '/', '\t', '\r', '\n']) {
+            search = start + open.len();
+            continue;
+        }
+
+        let tag_end = start + open.len() + after_name.find('>')?;
+        if xml[..tag_end].ends_with('/') {
+            return None; // self-closing, empty
+        }
+
+        let close = format!("</{}>", tag);
+        let content_end = tag_end + 1 + xml[tag_end + 1..].find(&close)?;
+        return Some(xml[tag_end + 1..content_end].trim());
+    }
+}
+
+fn decode_xml_entities(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        let entity_end = match rest[start..].find(';') {
+            Some(end) => start + end,
+            None => break,
+        };
+        output.push_str(&rest[..start]);
+        let entity = &rest[start + 1..entity_end];
+
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+
+        match decoded {
+            Some(c) => output.push(c),
+            None => output.push_str(&rest[start..=entity_end]),
+        }
+        rest = &rest[entity_end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn skip_whitespace(data: &[u8]) -> &[u8] {
+    let start = data.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(data.len());
+    &data[start..]
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|window| window == needle)
+}
+
+fn find_between<'a>(data: &'a [u8], start: &[u8], end: &[u8]) -> Option<&'a [u8]> {
+    let from = find(data, start)?;
+    let to = from + find(&data[from..], end)? + end.len();
+    Some(&data[from..to])
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    pub(crate) fn build_pdf(title: &str, author: &str) -> Vec<u8> {
+        format!(
+            "%PDF-1.4\n\
+             1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+             2 0 obj\n<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>\nendobj\n\
+             3 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+             4 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+             12 0 obj\n<< /Title ({}) /Author ({}) /CreationDate (D:20230714093000+02'00') >>\nendobj\n\
+             trailer\n<< /Root 1 0 R /Info 12 0 R /Size 13 >>\n%%EOF\n",
+            title, author
+        )
+        .into_bytes()
+    }
+
+    /// Builds a zip holding the given members, deflating each one.
+    pub(crate) fn build_zip(members: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+
+        for (name, content) in members {
+            let compressed = miniz_oxide::deflate::compress_to_vec(content.as_bytes(), 6);
+            let offset = data.len() as u32;
+
+            let mut common = Vec::new();
+            common.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            common.extend_from_slice(&0u16.to_le_bytes()); // flags
+            common.extend_from_slice(&8u16.to_le_bytes()); // deflate
+            common.extend_from_slice(&[0u8; 4]); // time and date
+            common.extend_from_slice(&[0u8; 4]); // crc (not checked)
+            common.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            common.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            common.extend_from_slice(&0u16.to_le_bytes()); // extra length
+
+            data.extend_from_slice(b"PK\x03\x04");
+            data.extend_from_slice(&common);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&compressed);
+
+            directory.extend_from_slice(b"PK\x01\x02");
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            directory.extend_from_slice(&common);
+            directory.extend_from_slice(&[0u8; 6]); // comment length, disk, internal attributes
+            directory.extend_from_slice(&[0u8; 4]); // external attributes
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let directory_offset = data.len() as u32;
+        data.extend_from_slice(&directory);
+        data.extend_from_slice(b"PK\x05\x06");
+        data.extend_from_slice(&[0u8; 4]);
+        data.extend_from_slice(&(members.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(members.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        data.extend_from_slice(&directory_offset.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data
+    }
+
+    pub(crate) fn build_docx(title: &str, author: &str) -> Vec<u8> {
+        let core = format!(
+            "<?xml version=\"1.0\"?><cp:coreProperties xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\
+             <dc:title>{}</dc:title><dc:creator>{}</dc:creator>\
+             <dcterms:created xsi:type=\"dcterms:W3CDTF\">2022-03-04T05:06:07Z</dcterms:created>\
+             </cp:coreProperties>",
+            title, author
+        );
+        let app = "<Properties><Pages>7</Pages></Properties>";
+        build_zip(&[("[Content_Types].xml", "<Types/>"), ("docProps/core.xml", &core), ("docProps/app.xml", app)])
+    }
+
+    #[test]
+    fn test_parse_pdf_info() {
+        let info = parse_pdf(&build_pdf("Quarterly \\(Q3\\) Report", "ACME Corp"));
+
+        assert_eq!(info.title.as_deref(), Some("Quarterly (Q3) Report"));
+        assert_eq!(info.author.as_deref(), Some("ACME Corp"));
+        assert_eq!(info.pages, Some(2));
+        let created = info.created.unwrap();
+        assert_eq!((created.year, created.month, created.day, created.hour), (2023, 7, 14, 9));
+    }
+
+    #[test]
+    fn test_parse_pdf_hex_and_utf16_strings() {
+        // "Hi" in UTF-16BE with a byte order mark
+        let pdf = b"%PDF-1.7\n5 0 obj\n<< /Title <FEFF00480069> /Author <41 42> >>\nendobj\ntrailer << /Info 5 0 R >>";
+        let info = parse_pdf(pdf);
+
+        assert_eq!(info.title.as_deref(), Some("Hi"));
+        assert_eq!(info.author.as_deref(), Some("AB"));
+    }
+
+    #[test]
+    fn test_parse_pdf_xmp_fallback() {
+        let pdf = b"%PDF-1.7\n<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF>\
+            <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">Annual &amp; Final</rdf:li></rdf:Alt></dc:title>\
+            <dc:creator><rdf:Seq><rdf:li>Jane Doe</rdf:li></rdf:Seq></dc:creator>\
+            <xmp:CreateDate>2020-01-02T03:04:05Z</xmp:CreateDate>\
+            </rdf:RDF></x:xmpmeta>";
+        let info = parse_pdf(pdf);
+
+        assert_eq!(info.title.as_deref(), Some("Annual & Final"));
+        assert_eq!(info.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(info.created.unwrap().year, 2020);
+    }
+
+    #[test]
+    fn test_read_docx() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("letter.docx");
+        std::fs::write(&path, build_docx("Offer Letter", "ACME HR")).unwrap();
+
+        let info = read_document_info(&path).unwrap().unwrap();
+        assert_eq!(info.title.as_deref(), Some("Offer Letter"));
+        assert_eq!(info.author.as_deref(), Some("ACME HR"));
+        assert_eq!(info.pages, Some(7));
+        assert_eq!(info.created.unwrap().year, 2022);
+    }
+
+    #[test]
+    fn test_plain_text_has_no_info() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "just text").unwrap();
+
+        assert_eq!(read_document_info(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_pdf_date() {
+        let date = parse_pdf_date("D:2019").unwrap();
+        assert_eq!((date.year, date.month, date.day), (2019, 1, 1));
+        assert!(parse_pdf_date("garbage").is_none());
+    }
+}
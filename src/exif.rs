@@ -0,0 +1,578 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::datetime::DateTime;
+use crate::isobmff::{self, child_box, read_up_to};
+
+/// Largest metadata block we are willing to load into memory.
+const MAX_BLOCK_BYTES: u64 = 16 * 1024 * 1024;
+
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+
+/// The handful of EXIF fields used to organize photos.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExifData {
+    pub date_taken: Option<DateTime>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub has_gps: bool,
+}
+
+impl ExifData {
+    /// Make and model joined for folder names, e.g. "Canon EOS R6".
+    ///
+    /// Many cameras repeat the make at the start of the model string, so it
+    /// is only added when missing.
+    pub fn camera(&self) -> Option<String> {
+        match (&self.make, &self.model) {
+            (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => {
+                Some(model.clone())
+            }
+            (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+            (Some(make), None) => Some(make.clone()),
+            (None, Some(model)) => Some(model.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Reads EXIF from a JPEG, TIFF or HEIC file.
+///
+/// Returns `Ok(None)` when the file is not one of those formats or carries
+/// no EXIF block.
+pub fn read_exif(path: &Path) -> io::Result<Option<ExifData>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 12];
+    let read = read_up_to(&mut file, &mut magic)?;
+    let magic = &magic[..read];
+
+    let tiff = if magic.starts_with(&[0xFF, 0xD8]) {
+        jpeg_exif_block(&mut file)?
+    } else if magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*") {
+        file.seek(SeekFrom::Start(0))?;
+        let mut data = Vec::new();
+        file.take(MAX_BLOCK_BYTES).read_to_end(&mut data)?;
+        Some(data)
+    } else if magic.len() >= 12 && &magic[4..8] == b"ftyp" && is_heif_brand(&magic[8..12]) {
+        heif_exif_block(&mut file)?
+    } else {
+        None
+    };
+
+    Ok(tiff.and_then(|data| parse_tiff(&data)))
+}
+
+fn read_block(file: &mut File, offset: u64, length: u64) -> io::Result<Vec<u8>> {
+    if length > MAX_BLOCK_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "metadata block too large"));
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = vec![0u8; length as usize];
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Walks JPEG markers until the APP1 segment holding "Exif\0\0".
+fn jpeg_exif_block(file: &mut File) -> io::Result<Option<Vec<u8>>> {
+    let mut offset = 2u64;
+
+    loop {
+        let header = match read_block(file, offset, 4) {
+            Ok(header) => header,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if header[0] != 0xFF {
+            return Ok(None);
+        }
+
+        let marker = header[1];
+        // Start of scan or end of image: no metadata past this point
+        if marker == 0xDA || marker == 0xD9 {
+            return Ok(None);
+        }
+
+        let length = u16::from_be_bytes([header[2], header[3]]) as u64;
+        if length < 2 {
+            return Ok(None);
+        }
+
+        if marker == 0xE1 {
+            let segment = read_block(file, offset + 4, length - 2)?;
+            if segment.starts_with(b"Exif\0\0") {
+                return Ok(Some(segment[6..].to_vec()));
+            }
+        }
+
+        offset += 2 + length;
+    }
+}
+
+fn is_heif_brand(brand: &[u8]) -> bool {
+    matches!(brand, b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1" | b"avif")
+}
+
+/// Locates the "Exif" item of a HEIF container through its `meta` box.
+fn heif_exif_block(file: &mut File) -> io::Result<Option<Vec<u8>>> {
+    let file_len = file.metadata()?.len();
+    let meta = match isobmff::find_box(file, 0, file_len, b"meta")? {
+        Some((start, end)) => read_block(file, start, end - start)?,
+        None => return Ok(None),
+    };
+
+    // `meta` is a full box: skip version and flags
+    let children = meta.get(4..).unwrap_or_default();
+    let exif_item = match child_box(children, b"iinf").and_then(exif_item_id) {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    let (offset, length) = match child_box(children, b"iloc").and_then(|iloc| item_location(iloc, exif_item)) {
+        Some(location) => location,
+        None => return Ok(None),
+    };
+
+    let item = read_block(file, offset, length)?;
+    if item.len() < 4 {
+        return Ok(None);
+    }
+    // The item starts with the offset of the TIFF header past these 4 bytes
+    let header_offset = 4 + u32::from_be_bytes([item[0], item[1], item[2], item[3]]) as usize;
+    Ok(item.get(header_offset..).map(<[u8]>::to_vec))
+}
+
+fn exif_item_id(iinf: &[u8]) -> Option<u32> {
+    let version = *iinf.first()?;
+    let entries = iinf.get(if version == 0 { 6 } else { 8 }..)?;
+
+    for (kind, infe) in isobmff::boxes(entries) {
+        if kind != b"infe" {
+            continue;
+        }
+        let (id, type_offset) = match *infe.first()? {
+            2 => (be_u16(infe, 4)? as u32, 8),
+            3 => (be_u32(infe, 4)?, 10),
+            _ => continue,
+        };
+        if infe.get(type_offset..type_offset + 4) == Some(b"Exif") {
+            return Some(id);
+        }
+    }
+    None
+}
+
+fn item_location(iloc: &[u8], item_id: u32) -> Option<(u64, u64)> {
+    let version = *iloc.first()?;
+    let sizes = be_u16(iloc, 4)?;
+    let offset_size = (sizes >> 12) as usize;
+    let length_size = ((sizes >> 8) & 0xF) as usize;
+    let base_offset_size = ((sizes >> 4) & 0xF) as usize;
+    let index_size = if version >= 1 { (sizes & 0xF) as usize } else { 0 };
+
+    let mut pos = 6;
+    let item_count = if version < 2 {
+        let count = be_u16(iloc, pos)? as u32;
+        pos += 2;
+        count
+    } else {
+        let count = be_u32(iloc, pos)?;
+        pos += 4;
+        count
+    };
+
+    for _ in 0..item_count {
+        let id = if version < 2 {
+            let id = be_u16(iloc, pos)? as u32;
+            pos += 2;
+            id
+        } else {
+            let id = be_u32(iloc, pos)?;
+            pos += 4;
+            id
+        };
+        if version >= 1 {
+            pos += 2; // construction method
+        }
+        pos += 2; // data reference index
+        let base_offset = be_uint(iloc, pos, base_offset_size)?;
+        pos += base_offset_size;
+        let extent_count = be_u16(iloc, pos)?;
+        pos += 2;
+
+        let mut first_extent = None;
+        for _ in 0..extent_count {
+            pos += index_size;
+            let extent_offset = be_uint(iloc, pos, offset_size)?;
+            pos += offset_size;
+            let extent_length = be_uint(iloc, pos, length_size)?;
+            pos += length_size;
+            // A corrupt base offset must not wrap around
+            first_extent.get_or_insert((base_offset.checked_add(extent_offset)?, extent_length));
+        }
+
+        if id == item_id {
+            return first_extent;
+        }
+    }
+    None
+}
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn be_uint(data: &[u8], offset: usize, size: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + size)?;
+    Some(bytes.iter().fold(0u64, |value, byte| (value << 8) | *byte as u64))
+}
+
+/// Byte-order aware reader over a TIFF structure.
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    /// Returns (tag, type, count, offset of the value field) for each entry.
+    fn entries(&self, ifd_offset: usize) -> Vec<(u16, u16, u32, usize)> {
+        let count = match self.u16(ifd_offset) {
+            Some(count) => count as usize,
+            None => return Vec::new(),
+        };
+
+        (0..count)
+            .filter_map(|i| {
+                let entry = ifd_offset + 2 + i * 12;
+                Some((self.u16(entry)?, self.u16(entry + 2)?, self.u32(entry + 4)?, entry + 8))
+            })
+            .collect()
+    }
+
+    fn ascii(&self, value_type: u16, count: u32, value_offset: usize) -> Option<String> {
+        if value_type != 2 {
+            return None;
+        }
+        let count = count as usize;
+        let start = if count <= 4 { value_offset } else { self.u32(value_offset)? as usize };
+        let bytes = self.data.get(start..start.checked_add(count)?)?;
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_end_matches('\0').trim();
+        if text.is_empty() {
+            None
+        } else {
+            Some(text.to_string())
+        }
+    }
+}
+
+/// Parses a TIFF header and the IFDs that hold the fields we care about.
+pub fn parse_tiff(data: &[u8]) -> Option<ExifData> {
+    let little_endian = match data.get(0..4)? {
+        b"II*\0" => true,
+        b"MM\0*" => false,
+        _ => return None,
+    };
+    let tiff = Tiff { data, little_endian };
+    let ifd0 = tiff.u32(4)? as usize;
+
+    let mut exif = ExifData::default();
+    let mut modify_date = None;
+    let mut exif_ifd = None;
+
+    for (tag, value_type, count, value_offset) in tiff.entries(ifd0) {
+        match tag {
+            TAG_MAKE => exif.make = tiff.ascii(value_type, count, value_offset),
+            TAG_MODEL => exif.model = tiff.ascii(value_type, count, value_offset),
+            TAG_DATE_TIME => modify_date = tiff.ascii(value_type, count, value_offset),
+            TAG_EXIF_IFD => exif_ifd = tiff.u32(value_offset),
+            TAG_GPS_IFD => {
+                exif.has_gps = tiff
+                    .u32(value_offset)
+                    .is_some_and(|offset| !tiff.entries(offset as usize).is_empty());
+            }
+            _ => {}
+        }
+    }
+
+    let mut original = None;
+    let mut digitized = None;
+    if let Some(offset) = exif_ifd {
+        for (tag, value_type, count, value_offset) in tiff.entries(offset as usize) {
+            match tag {
+                TAG_DATE_TIME_ORIGINAL => original = tiff.ascii(value_type, count, value_offset),
+                TAG_DATE_TIME_DIGITIZED => digitized = tiff.ascii(value_type, count, value_offset),
+                _ => {}
+            }
+        }
+    }
+
+    exif.date_taken = [original, digitized, modify_date]
+        .iter()
+        .flatten()
+        .find_map(|text| parse_exif_date(text));
+
+    Some(exif)
+}
+
+/// Parses the EXIF "YYYY:MM:DD HH:MM:SS" format.
+pub fn parse_exif_date(text: &str) -> Option<DateTime> {
+    let (date, time) = text.trim().split_once(' ')?;
+    let mut date_parts = date.split(':').map(|part| part.parse::<u32>().ok());
+    let mut time_parts = time.split(':').map(|part| part.parse::<u32>().ok());
+
+    let year = date_parts.next()?? as i32;
+    let month = date_parts.next()??;
+    let day = date_parts.next()??;
+    let hour = time_parts.next()??;
+    let minute = time_parts.next()??;
+    let second = time_parts.next().flatten().unwrap_or(0);
+
+    // Cameras without a clock write all zeros
+    if year == 0 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    Some(DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Builds a little-endian TIFF block with Make, Model, DateTimeOriginal
+    /// and optionally a GPS IFD.
+    pub(crate) fn build_tiff(make: &str, model: &str, date: &str, gps: bool) -> Vec<u8> {
+        let ascii = |text: &str| {
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.push(0);
+            bytes
+        };
+        let make = ascii(make);
+        let model = ascii(model);
+        let date = ascii(date);
+
+        let ifd0_entries = if gps { 4 } else { 3 };
+        let ifd0_len = 2 + ifd0_entries * 12 + 4;
+        let exif_ifd = 8 + ifd0_len;
+        let exif_ifd_len = 2 + 12 + 4;
+        let gps_ifd = exif_ifd + exif_ifd_len;
+        let gps_ifd_len = 2 + 12 + 4;
+        let strings = gps_ifd + gps_ifd_len;
+
+        let mut data = b"II*\0".to_vec();
+        data.extend_from_slice(&8u32.to_le_bytes());
+
+        let entry = |data: &mut Vec<u8>, tag: u16, value_type: u16, count: u32, value: u32| {
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&value_type.to_le_bytes());
+            data.extend_from_slice(&count.to_le_bytes());
+            data.extend_from_slice(&value.to_le_bytes());
+        };
+
+        data.extend_from_slice(&(ifd0_entries as u16).to_le_bytes());
+        entry(&mut data, TAG_MAKE, 2, make.len() as u32, strings as u32);
+        entry(&mut data, TAG_MODEL, 2, model.len() as u32, (strings + make.len()) as u32);
+        entry(&mut data, TAG_EXIF_IFD, 4, 1, exif_ifd as u32);
+        if gps {
+            entry(&mut data, TAG_GPS_IFD, 4, 1, gps_ifd as u32);
+        }
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        data.extend_from_slice(&1u16.to_le_bytes());
+        entry(
+            &mut data,
+            TAG_DATE_TIME_ORIGINAL,
+            2,
+            date.len() as u32,
+            (strings + make.len() + model.len()) as u32,
+        );
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        // GPS IFD with a single GPSLatitudeRef entry ("N")
+        data.extend_from_slice(&1u16.to_le_bytes());
+        entry(&mut data, 0x0001, 2, 2, u32::from_le_bytes([b'N', 0, 0, 0]));
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        data.extend_from_slice(&make);
+        data.extend_from_slice(&model);
+        data.extend_from_slice(&date);
+        data
+    }
+
+    /// Wraps a TIFF block in a minimal JPEG.
+    pub(crate) fn build_jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        // An unrelated APP0 segment first
+        data.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00]);
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(tiff);
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+        data
+    }
+
+    fn full_box(kind: &[u8; 4], version: u8, payload: &[u8]) -> Vec<u8> {
+        let mut body = vec![version, 0, 0, 0];
+        body.extend_from_slice(payload);
+        plain_box(kind, &body)
+    }
+
+    fn plain_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn build_heic(tiff: &[u8]) -> Vec<u8> {
+        let ftyp = plain_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+
+        let mut infe = 1u16.to_be_bytes().to_vec();
+        infe.extend_from_slice(&[0, 0]);
+        infe.extend_from_slice(b"Exif");
+        let mut iinf_payload = 1u16.to_be_bytes().to_vec();
+        iinf_payload.extend_from_slice(&full_box(b"infe", 2, &infe));
+        let iinf = full_box(b"iinf", 0, &iinf_payload);
+
+        let mut item = 0u32.to_be_bytes().to_vec();
+        item.extend_from_slice(tiff);
+
+        // iloc v0 with 4-byte offsets and lengths; the offset is patched below
+        let iloc_len = 8 + 4 + 2 + 2 + 2 + 2 + 2 + 4 + 4;
+        let meta_len = 8 + 4 + iinf.len() + iloc_len;
+        let item_offset = (ftyp.len() + meta_len) as u32;
+
+        let mut iloc_payload = vec![0x44, 0x00];
+        iloc_payload.extend_from_slice(&1u16.to_be_bytes());
+        iloc_payload.extend_from_slice(&1u16.to_be_bytes());
+        iloc_payload.extend_from_slice(&0u16.to_be_bytes());
+        iloc_payload.extend_from_slice(&1u16.to_be_bytes());
+        iloc_payload.extend_from_slice(&item_offset.to_be_bytes());
+        iloc_payload.extend_from_slice(&(item.len() as u32).to_be_bytes());
+        let iloc = full_box(b"iloc", 0, &iloc_payload);
+
+        let mut meta_payload = iinf;
+        meta_payload.extend_from_slice(&iloc);
+        let meta = full_box(b"meta", 0, &meta_payload);
+        assert_eq!(meta.len(), meta_len);
+
+        let mut data = ftyp;
+        data.extend_from_slice(&meta);
+        data.extend_from_slice(&item);
+        data
+    }
+
+    #[test]
+    fn test_parse_tiff_fields() {
+        let exif = parse_tiff(&build_tiff("Canon", "Canon EOS R6", "2023:07:14 09:30:00", true)).unwrap();
+
+        assert_eq!(exif.make.as_deref(), Some("Canon"));
+        assert_eq!(exif.camera().as_deref(), Some("Canon EOS R6"));
+        assert!(exif.has_gps);
+        let date = exif.date_taken.unwrap();
+        assert_eq!((date.year, date.month, date.day, date.hour), (2023, 7, 14, 9));
+    }
+
+    #[test]
+    fn test_read_jpeg() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        std::fs::write(&path, build_jpeg(&build_tiff("Apple", "iPhone 14", "2022:12:25 18:00:00", false))).unwrap();
+
+        let exif = read_exif(&path).unwrap().unwrap();
+        assert_eq!(exif.camera().as_deref(), Some("Apple iPhone 14"));
+        assert!(!exif.has_gps);
+        assert_eq!(exif.date_taken.unwrap().year, 2022);
+    }
+
+    #[test]
+    fn test_read_tiff_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("scan.tif");
+        std::fs::write(&path, build_tiff("Nikon", "D750", "2019:01:02 03:04:05", false)).unwrap();
+
+        let exif = read_exif(&path).unwrap().unwrap();
+        assert_eq!(exif.model.as_deref(), Some("D750"));
+    }
+
+    #[test]
+    fn test_read_heic() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("photo.heic");
+        std::fs::write(&path, build_heic(&build_tiff("Apple", "iPhone 15", "2024:03:01 10:00:00", true))).unwrap();
+
+        let exif = read_exif(&path).unwrap().unwrap();
+        assert_eq!(exif.model.as_deref(), Some("iPhone 15"));
+        assert!(exif.has_gps);
+        assert_eq!(exif.date_taken.unwrap().month, 3);
+    }
+
+    #[test]
+    fn test_corrupt_heic_sizes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("photo.heic");
+        // A box whose 64-bit size runs past the end of the file
+        let mut data = plain_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(&(u64::MAX - 23).to_be_bytes());
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(read_exif(&path).unwrap(), None);
+
+        // iloc v0 with 8-byte offsets whose sum overflows
+        let mut iloc = vec![0, 0, 0, 0, 0x88, 0x80];
+        iloc.extend_from_slice(&1u16.to_be_bytes());
+        iloc.extend_from_slice(&1u16.to_be_bytes());
+        iloc.extend_from_slice(&0u16.to_be_bytes());
+        iloc.extend_from_slice(&u64::MAX.to_be_bytes());
+        iloc.extend_from_slice(&1u16.to_be_bytes());
+        iloc.extend_from_slice(&1u64.to_be_bytes());
+        iloc.extend_from_slice(&4u64.to_be_bytes());
+        assert_eq!(item_location(&iloc, 1), None);
+    }
+
+    #[test]
+    fn test_non_image_has_no_exif() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("photo.png");
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\n").unwrap();
+
+        assert_eq!(read_exif(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_exif_date() {
+        assert!(parse_exif_date("2023:07:14 09:30:00").is_some());
+        assert_eq!(parse_exif_date("0000:00:00 00:00:00"), None);
+        assert_eq!(parse_exif_date("garbage"), None);
+    }
+}
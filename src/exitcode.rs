@@ -0,0 +1,42 @@
+//! Exit statuses of a run, so scripts and timers can tell outcomes apart.
+//! Usage errors exit with 2, as clap reports them.
+
+/// Every file was moved (or would be, in a dry run)
+pub const SUCCESS: i32 = 0;
+/// The run could not start or was aborted, e.g. by a bad option or an
+/// unreadable source directory
+pub const FATAL: i32 = 1;
+/// There were no files to organize
+pub const NOTHING_TO_DO: i32 = 3;
+/// Some files were left in place, e.g. because their destination was taken
+pub const SKIPPED: i32 = 4;
+/// Some files could not be moved, or the run was interrupted
+pub const FAILED: i32 = 5;
+
+/// Status of a run that moved `done` files, skipped `skipped` and failed on
+/// `failed`. Failures outweigh skips, and skips outweigh success.
+pub fn outcome(done: usize, skipped: usize, failed: usize) -> i32 {
+    if failed > 0 {
+        FAILED
+    } else if skipped > 0 {
+        SKIPPED
+    } else if done > 0 {
+        SUCCESS
+    } else {
+        NOTHING_TO_DO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcome() {
+        assert_eq!(outcome(3, 0, 0), SUCCESS);
+        assert_eq!(outcome(0, 0, 0), NOTHING_TO_DO);
+        assert_eq!(outcome(3, 1, 0), SKIPPED);
+        assert_eq!(outcome(0, 2, 0), SKIPPED);
+        assert_eq!(outcome(3, 1, 1), FAILED);
+    }
+}
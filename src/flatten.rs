@@ -0,0 +1,84 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::journal;
+
+/// Name of the file in the output directory mapping flattened files back to
+/// where they came from.
+pub const FLATTEN_MAP_FILE_NAME: &str = ".file-organizer-flatten.tsv";
+
+/// Separator between the folders encoded into a flattened file name.
+pub const PATH_SEPARATOR: &str = "__";
+
+/// `a/b` and `file.txt` become `a__b__file.txt`.
+pub fn encode_name(relative_dir: &Path, name: &str) -> String {
+    let mut parts: Vec<String> = relative_dir
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    parts.push(name.to_string());
+    parts.join(PATH_SEPARATOR)
+}
+
+/// Append-only record of flattened files, one `<organized>\t<original>`
+/// line each with both paths absolute, escaped like the progress log.
+/// Together with the progress log it lets `--undo`, or a script, rebuild
+/// the original tree.
+pub struct FlattenMap {
+    path: PathBuf,
+    file: File,
+}
+
+impl FlattenMap {
+    pub fn open(output_dir: &Path) -> io::Result<Self> {
+        let path = output_dir.join(FLATTEN_MAP_FILE_NAME);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, organized: &Path, original: &Path) -> io::Result<()> {
+        let mut line = journal::escape_absolute(organized)?;
+        line.push(b'\t');
+        line.extend(journal::escape_absolute(original)?);
+        line.push(b'\n');
+        (&self.file).write_all(&line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_encode_name() {
+        assert_eq!(encode_name(Path::new("a/b"), "file.txt"), "a__b__file.txt");
+        assert_eq!(encode_name(Path::new(""), "file.txt"), "file.txt");
+    }
+
+    #[test]
+    fn test_map_appends() {
+        let dir = tempdir().unwrap();
+        FlattenMap::open(dir.path())
+            .unwrap()
+            .record(Path::new("/out/Images/a__b__p.jpg"), Path::new("/src/a/b/p.jpg"))
+            .unwrap();
+        let map = FlattenMap::open(dir.path()).unwrap();
+        map.record(Path::new("Images/q.jpg"), Path::new("/src/c/q.jpg")).unwrap();
+
+        let contents = std::fs::read_to_string(map.path()).unwrap();
+        let relative = std::env::current_dir().unwrap().join("Images/q.jpg");
+        assert_eq!(
+            contents,
+            format!("/out/Images/a__b__p.jpg\t/src/a/b/p.jpg\n{}\t/src/c/q.jpg\n", relative.display())
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the file in the source directory listing directories to leave alone.
+pub const IGNORE_FILE_NAME: &str = ".file-organizer-ignore";
+
+/// Directories that recursive scans do not enter and pruning never removes.
+///
+/// One entry per line; blank lines and lines starting with `#` are skipped.
+/// A bare name such as `node_modules` matches a directory of that name at
+/// any depth, an entry with a slash such as `projects/website` only that
+/// path relative to the source directory.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreList {
+    names: Vec<String>,
+    paths: Vec<PathBuf>,
+}
+
+impl IgnoreList {
+    /// Reads the ignore file in `source_dir`; a missing file ignores nothing.
+    pub fn load(source_dir: &Path) -> io::Result<Self> {
+        match fs::read_to_string(source_dir.join(IGNORE_FILE_NAME)) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut list = Self::default();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = line.trim_matches('/');
+            if entry.contains('/') {
+                list.paths.push(PathBuf::from(entry));
+            } else if !entry.is_empty() {
+                list.names.push(entry.to_string());
+            }
+        }
+        list
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.paths.is_empty()
+    }
+
+    /// Whether the directory at `relative` (to the source directory) is ignored.
+    pub fn is_ignored(&self, relative: &Path) -> bool {
+        let named = relative.components().next_back().is_some_and(|last| match last {
+            Component::Normal(name) => self.names.iter().any(|ignored| name == ignored.as_str()),
+            _ => false,
+        });
+        named || self.paths.iter().any(|path| path == relative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_names_and_paths() {
+        let list = IgnoreList::parse("# build output\nnode_modules\n\n/projects/website/\n");
+
+        assert!(list.is_ignored(Path::new("node_modules")));
+        assert!(list.is_ignored(Path::new("a/b/node_modules")));
+        assert!(list.is_ignored(Path::new("projects/website")));
+        assert!(!list.is_ignored(Path::new("other/projects/website")));
+        assert!(!list.is_ignored(Path::new("projects")));
+    }
+
+    #[test]
+    fn test_missing_file_ignores_nothing() {
+        let dir = tempdir().unwrap();
+        assert!(IgnoreList::load(dir.path()).unwrap().is_empty());
+
+        fs::write(dir.path().join(IGNORE_FILE_NAME), "keep\n").unwrap();
+        assert!(IgnoreList::load(dir.path()).unwrap().is_ignored(Path::new("keep")));
+    }
+}
@@ -0,0 +1,286 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::categories::{CategoryMapper, FileCategory};
+use crate::organizer::{MoveOperation, Organizer};
+use crate::scanner::{FileInfo, ScanResult};
+
+/// Prompts need someone to answer them: both stdin and stdout must be a TTY.
+pub fn has_terminal() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// The plan after the user went through it.
+#[derive(Debug, Default)]
+pub struct Review {
+    /// Operations to carry out, edited as the user asked
+    pub accepted: Vec<MoveOperation>,
+    pub skipped: Vec<(MoveOperation, String)>,
+    /// Files that could not be planned at all
+    pub failed: Vec<(MoveOperation, String)>,
+}
+
+enum Answer {
+    Char(char),
+    Quit,
+}
+
+/// Line-based review of the plan, per category and then per file.
+pub struct Session<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Session<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    /// Shows the plan grouped by category, then asks for each category
+    /// whether to accept it, skip it or go through it file by file. Returns
+    /// `None` if the user quits; nothing has been moved at that point.
+    pub fn review(&mut self, organizer: &Organizer, scan_result: &ScanResult) -> io::Result<Option<Review>> {
+        let mut review = Review::default();
+        let mut groups: Vec<(FileCategory, Vec<(&FileInfo, MoveOperation)>)> = Vec::new();
+
+        for category in CategoryMapper::new().all_categories() {
+            let mut planned = Vec::new();
+            for file in scan_result.files_in(&category) {
+                match organizer.plan(file) {
+                    Ok(operation) => planned.push((file, operation)),
+                    Err(reason) => {
+                        let operation = MoveOperation {
+                            source: file.path.clone(),
+                            destination: organizer.get_target_path(&file.category, &file.name),
+                            file_name: file.name.clone(),
+                        };
+                        review.failed.push((operation, reason));
+                    }
+                }
+            }
+            if !planned.is_empty() {
+                groups.push((category, planned));
+            }
+        }
+
+        writeln!(self.output, "Plan:")?;
+        for (category, planned) in &groups {
+            writeln!(self.output, "  {} ({} file(s))", category.folder_name(), planned.len())?;
+            for (_, operation) in planned {
+                writeln!(self.output, "    {} -> {}", operation.file_name, organizer.destination_label(operation))?;
+            }
+        }
+        writeln!(self.output)?;
+
+        for (category, planned) in groups {
+            let question = format!(
+                "{}: {} file(s) - [a]ccept, [s]kip, [r]eview one by one, [q]uit? ",
+                category.folder_name(),
+                planned.len()
+            );
+            match self.ask(&question, "asrq")? {
+                Answer::Char('a') => review.accepted.extend(planned.into_iter().map(|(_, operation)| operation)),
+                Answer::Char('s') => {
+                    for (_, operation) in planned {
+                        organizer.release(&operation);
+                        review.skipped.push((operation, "skipped interactively".to_string()));
+                    }
+                }
+                Answer::Char(_) => {
+                    for (file, operation) in planned {
+                        match self.review_file(organizer, file, operation)? {
+                            Some(Ok(operation)) => review.accepted.push(operation),
+                            Some(Err(operation)) => {
+                                organizer.release(&operation);
+                                review.skipped.push((operation, "skipped interactively".to_string()));
+                            }
+                            None => return Ok(None),
+                        }
+                    }
+                }
+                Answer::Quit => return Ok(None),
+            }
+        }
+
+        Ok(Some(review))
+    }
+
+    /// Asks about one file until it is moved (`Ok`) or skipped (`Err`).
+    /// Changing its category or name asks again with the new destination.
+    fn review_file(
+        &mut self,
+        organizer: &Organizer,
+        file: &FileInfo,
+        mut operation: MoveOperation,
+    ) -> io::Result<Option<Result<MoveOperation, MoveOperation>>> {
+        loop {
+            let question = format!(
+                "  {} -> {} - [m]ove, [s]kip, [c]hange category, [r]ename, [q]uit? ",
+                operation.file_name,
+                organizer.destination_label(&operation)
+            );
+            match self.ask(&question, "mscrq")? {
+                Answer::Char('m') => return Ok(Some(Ok(operation))),
+                Answer::Char('s') => return Ok(Some(Err(operation))),
+                Answer::Char('c') => {
+                    let names: Vec<&str> = CategoryMapper::new()
+                        .all_categories()
+                        .iter()
+                        .map(|category| category.folder_name())
+                        .collect();
+                    let name = match self.read_line(&format!("  New category ({}): ", names.join(", ")))? {
+                        Some(name) => name,
+                        None => return Ok(None),
+                    };
+                    let category = match FileCategory::from_name(name.trim()) {
+                        Some(category) => category,
+                        None => {
+                            writeln!(self.output, "  Unknown category '{}'", name.trim())?;
+                            continue;
+                        }
+                    };
+
+                    let mut recategorized = file.clone();
+                    recategorized.category = category;
+                    recategorized.subcategory = None;
+                    organizer.release(&operation);
+                    match organizer.plan(&recategorized) {
+                        Ok(replanned) => operation = replanned,
+                        Err(reason) => {
+                            // The file keeps its original destination
+                            organizer.reserve(&operation);
+                            writeln!(self.output, "  Cannot move it there: {}", reason)?;
+                        }
+                    }
+                }
+                Answer::Char('r') => {
+                    let name = match self.read_line("  New name: ")? {
+                        Some(name) => name,
+                        None => return Ok(None),
+                    };
+                    if let Err(reason) = organizer.rename_planned(&mut operation, &name) {
+                        writeln!(self.output, "  {}", reason)?;
+                    }
+                }
+                Answer::Char(_) | Answer::Quit => return Ok(None),
+            }
+        }
+    }
+
+    /// Asks until the first letter of the answer is one of `choices`.
+    /// End of input counts as quitting.
+    fn ask(&mut self, question: &str, choices: &str) -> io::Result<Answer> {
+        loop {
+            let line = match self.read_line(question)? {
+                Some(line) => line,
+                None => return Ok(Answer::Quit),
+            };
+            match line.trim().chars().next().map(|c| c.to_ascii_lowercase()) {
+                Some('q') => return Ok(Answer::Quit),
+                Some(c) if choices.contains(c) => return Ok(Answer::Char(c)),
+                _ => writeln!(self.output, "  Please answer one of: {}", choices)?,
+            }
+        }
+    }
+
+    fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        write!(self.output, "{}", prompt)?;
+        self.output.flush()?;
+
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::DirectoryScanner;
+    use crate::template::Layout;
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn review_with(answers: &str) -> (Option<Review>, PathBuf) {
+        let source = tempdir().unwrap();
+        let output = tempdir().unwrap();
+        for name in ["a.jpg", "b.jpg", "notes.txt"] {
+            fs::write(source.path().join(name), name).unwrap();
+        }
+
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source.path()).unwrap();
+        let organizer = Organizer::new(output.path().to_path_buf(), true, false);
+        let mut transcript = Vec::new();
+        let review = Session::new(Cursor::new(answers.to_string()), &mut transcript)
+            .review(&organizer, &scan_result)
+            .unwrap();
+
+        assert!(String::from_utf8(transcript).unwrap().starts_with("Plan:\n  Images (2 file(s))"));
+        (review, output.path().to_path_buf())
+    }
+
+    #[test]
+    fn test_accept_and_skip_categories() {
+        let (review, _) = review_with("a\nskip\n");
+        let review = review.unwrap();
+
+        assert_eq!(review.accepted.len(), 2);
+        assert_eq!(review.skipped.len(), 1);
+        assert_eq!(review.skipped[0].0.file_name, "notes.txt");
+    }
+
+    #[test]
+    fn test_review_files_one_by_one() {
+        // Images: review; the first image is renamed then moved, the second
+        // goes to Documents; Documents: accept
+        let (review, output) = review_with("r\nr\ncover.jpg\nm\nc\nnope\nc\nDocuments\nm\na\n");
+        let review = review.unwrap();
+
+        let mut destinations: Vec<PathBuf> = review.accepted.iter().map(|op| op.destination.clone()).collect();
+        destinations.sort();
+        assert_eq!(destinations.len(), 3);
+        assert_eq!(destinations[0].parent().unwrap(), output.join("Documents"));
+        assert_eq!(destinations[0].extension().unwrap(), "jpg");
+        assert_eq!(destinations[1], output.join("Documents/notes.txt"));
+        assert_eq!(destinations[2], output.join("Images/cover.jpg"));
+        assert!(review.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_failed_change_keeps_destination() {
+        let source = tempdir().unwrap();
+        let output = tempdir().unwrap();
+        for name in ["a.jpg", "b.jpg"] {
+            fs::write(source.path().join(name), name).unwrap();
+        }
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source.path()).unwrap();
+        // No file can be placed in Documents
+        let organizer = Organizer::new(output.path().to_path_buf(), true, false)
+            .with_layout(Some(FileCategory::Documents), Layout::parse("..").unwrap());
+
+        // The first image fails to change category and is moved as planned;
+        // the second then cannot take its name, whichever image came first
+        let answers = "r\nc\nDocuments\nm\nr\na.jpg\nr\nb.jpg\nm\n";
+        let mut transcript = Vec::new();
+        let review = Session::new(Cursor::new(answers), &mut transcript)
+            .review(&organizer, &scan_result)
+            .unwrap()
+            .unwrap();
+
+        let transcript = String::from_utf8(transcript).unwrap();
+        assert!(transcript.contains("Cannot move it there: layout '..' must stay inside the output directory"));
+        assert!(transcript.contains("is already taken"));
+        let mut destinations: Vec<PathBuf> = review.accepted.iter().map(|op| op.destination.clone()).collect();
+        destinations.sort();
+        assert_eq!(destinations, vec![output.path().join("Images/a.jpg"), output.path().join("Images/b.jpg")]);
+    }
+
+    #[test]
+    fn test_quit_and_end_of_input() {
+        assert!(review_with("q\n").0.is_none());
+        assert!(review_with("r\nm\n").0.is_none());
+    }
+}
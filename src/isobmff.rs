@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Fills as much of `buffer` as the file has, returning the bytes read.
+pub fn read_up_to(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        let read = file.read(&mut buffer[total..])?;
+        if read == 0 {
+            break;
+        }
+        total += read;
+    }
+    Ok(total)
+}
+
+/// Finds a box of `kind` among the boxes between `offset` and `end` in the
+/// file and returns its payload range. A box whose size does not fit in
+/// what is left ends the search, so corrupt sizes can neither wrap the
+/// offset around nor loop.
+pub fn find_box(file: &mut File, mut offset: u64, end: u64, kind: &[u8; 4]) -> io::Result<Option<(u64, u64)>> {
+    while end.checked_sub(offset).is_some_and(|left| left >= 8) {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            if end - offset < 16 {
+                return Ok(None);
+            }
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = end - offset;
+        }
+        if size < header_len || size > end - offset {
+            return Ok(None);
+        }
+
+        if &header[4..8] == kind {
+            return Ok(Some((offset + header_len, offset + size)));
+        }
+        offset = match offset.checked_add(size) {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+    }
+    Ok(None)
+}
+
+/// Returns the payload of the first child box of `kind` in `data`.
+pub fn child_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data).find(|(found, _)| *found == kind).map(|(_, payload)| payload)
+}
+
+/// The (type, payload) of each box in `data`, stopping at the first box
+/// whose size does not fit.
+pub fn boxes(data: &[u8]) -> Boxes<'_> {
+    Boxes { data, offset: 0 }
+}
+
+pub struct Boxes<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = (&'a [u8; 4], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.data.get(self.offset..)?;
+        let size = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let kind: &[u8; 4] = rest.get(4..8)?.try_into().ok()?;
+        if size < 8 || size > rest.len() {
+            self.offset = self.data.len();
+            return None;
+        }
+        self.offset = self.offset.checked_add(size)?;
+        Some((kind, &rest[8..size]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_boxes_stop_at_bad_size() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&12u32.to_be_bytes());
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(b"abcd");
+        data.extend_from_slice(&u32::MAX.to_be_bytes());
+        data.extend_from_slice(b"moov");
+
+        let found: Vec<_> = boxes(&data).collect();
+        assert_eq!(found, vec![(b"free", &b"abcd"[..])]);
+        assert_eq!(child_box(&data, b"moov"), None);
+    }
+
+    #[test]
+    fn test_find_box_rejects_oversized_largesize() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("broken.mp4");
+        let mut data = Vec::new();
+        data.extend_from_slice(&16u32.to_be_bytes());
+        data.extend_from_slice(b"ftypisom\0\0\0\0");
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(&(u64::MAX - 15).to_be_bytes());
+        std::fs::write(&path, &data).unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(find_box(&mut file, 0, data.len() as u64, b"moov").unwrap(), None);
+    }
+}
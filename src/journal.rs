@@ -0,0 +1,389 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::datetime::DateTime;
+use crate::staging;
+
+/// Name of the progress log kept in the output directory.
+pub const JOURNAL_FILE_NAME: &str = ".file-organizer.journal";
+
+/// Append-only record of a run, one tab-separated record per line:
+///
+/// - `RUN <pid> <started>` / `RESUME <pid> <started>` when a run begins
+/// - `START <source> <destination>` before a file is moved
+/// - `DONE <source> <destination>` once it is in place
+/// - `END complete` or `END interrupted` when the run stops cleanly
+/// - `RMDIR <directory>` for each source directory the run left empty and
+///   removed (`--prune-empty`)
+/// - `MKDIR <directory>` for each output directory the run created
+/// - `COPY` when the run copies files instead of moving them
+///
+/// A `START` without its `DONE` is a move that was cut short.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    /// Starts a fresh journal for a new run, replacing any previous one.
+    pub fn create(output_dir: &Path) -> io::Result<Self> {
+        let path = output_dir.join(JOURNAL_FILE_NAME);
+        let file = File::create(&path)?;
+        let journal = Self { path, file };
+        journal.record_run("RUN")?;
+        Ok(journal)
+    }
+
+    /// Continues the journal of an interrupted run.
+    pub fn resume(output_dir: &Path) -> io::Result<Self> {
+        let path = output_dir.join(JOURNAL_FILE_NAME);
+        let file = OpenOptions::new().append(true).open(&path)?;
+        let journal = Self { path, file };
+        journal.record_run("RESUME")?;
+        Ok(journal)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record_start(&self, source: &Path, destination: &Path) -> io::Result<()> {
+        self.write_record(&[b"START", &escape_absolute(source)?, &escape_absolute(destination)?])
+    }
+
+    pub fn record_done(&self, source: &Path, destination: &Path) -> io::Result<()> {
+        self.write_record(&[b"DONE", &escape_absolute(source)?, &escape_absolute(destination)?])
+    }
+
+    pub fn record_rmdir(&self, dir: &Path) -> io::Result<()> {
+        self.write_record(&[b"RMDIR", &escape_absolute(dir)?])
+    }
+
+    pub fn record_mkdir(&self, dir: &Path) -> io::Result<()> {
+        self.write_record(&[b"MKDIR", &escape_absolute(dir)?])
+    }
+
+    pub fn record_copy_mode(&self) -> io::Result<()> {
+        self.write_record(&[b"COPY"])
+    }
+
+    pub fn finish(&self, interrupted: bool) -> io::Result<()> {
+        let status: &[u8] = if interrupted { b"interrupted" } else { b"complete" };
+        self.write_record(&[b"END", status])?;
+        self.file.sync_all()
+    }
+
+    fn record_run(&self, kind: &str) -> io::Result<()> {
+        let started = DateTime::from_system_time(SystemTime::now()).format("%Y-%m-%d %H:%M:%S");
+        self.write_record(&[kind.as_bytes(), std::process::id().to_string().as_bytes(), started.as_bytes()])?;
+        self.file.sync_all()
+    }
+
+    /// Each record goes out in a single write so a killed process leaves at
+    /// most one truncated line behind.
+    fn write_record(&self, fields: &[&[u8]]) -> io::Result<()> {
+        let mut line = fields.join(&b'\t');
+        line.push(b'\n');
+        (&self.file).write_all(&line)
+    }
+}
+
+/// What the journal says about the last run.
+#[derive(Debug, Default)]
+pub struct JournalState {
+    /// The last run reached its `END complete` record
+    pub complete: bool,
+    /// Files moved, in order
+    pub done: Vec<(PathBuf, PathBuf)>,
+    /// Moves that were started but never finished
+    pub in_flight: Vec<(PathBuf, PathBuf)>,
+    /// Emptied source directories that were removed, in order
+    pub removed_dirs: Vec<PathBuf>,
+    /// Output directories the run created, parents first
+    pub created_dirs: Vec<PathBuf>,
+    /// The run copied its files, leaving the sources in place
+    pub copied: bool,
+}
+
+impl JournalState {
+    pub fn is_interrupted(&self) -> bool {
+        !self.complete
+    }
+}
+
+/// Reads the journal in `output_dir`, if there is one.
+pub fn read_state(output_dir: &Path) -> io::Result<Option<JournalState>> {
+    let mut contents = Vec::new();
+    match File::open(output_dir.join(JOURNAL_FILE_NAME)) {
+        Ok(mut file) => file.read_to_end(&mut contents)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut state = JournalState::default();
+    // Only complete lines count; a trailing partial line is a torn write
+    let complete_lines = match contents.iter().rposition(|b| *b == b'\n') {
+        Some(end) => &contents[..end],
+        None => &contents[..0],
+    };
+
+    for line in complete_lines.split(|b| *b == b'\n') {
+        let fields: Vec<&[u8]> = line.split(|b| *b == b'\t').collect();
+        match fields.as_slice() {
+            [b"RUN" | b"RESUME", ..] => state.complete = false,
+            [b"START", source, destination] => {
+                state.in_flight.push((unescape_path(source), unescape_path(destination)));
+            }
+            [b"DONE", source, destination] => {
+                let entry = (unescape_path(source), unescape_path(destination));
+                state.in_flight.retain(|started| *started != entry);
+                state.done.push(entry);
+            }
+            [b"END", status] => state.complete = *status == b"complete",
+            [b"RMDIR", dir] => state.removed_dirs.push(unescape_path(dir)),
+            [b"MKDIR", dir] => state.created_dirs.push(unescape_path(dir)),
+            [b"COPY"] => state.copied = true,
+            _ => {}
+        }
+    }
+
+    Ok(Some(state))
+}
+
+/// Deletes the staging files of copies the interrupted run was making.
+/// They can only be in the directories of moves that never finished.
+pub fn remove_staged_copies(state: &JournalState) -> io::Result<Vec<PathBuf>> {
+    let mut dirs: Vec<&Path> = state.in_flight.iter().filter_map(|(_, destination)| destination.parent()).collect();
+    dirs.sort();
+    dirs.dedup();
+
+    let mut removed = Vec::new();
+    for dir in dirs {
+        removed.extend(staging::remove_stale(dir)?);
+    }
+    Ok(removed)
+}
+
+/// Outcome of cleaning up after an interrupted run.
+#[derive(Debug, Default)]
+pub struct Recovery {
+    /// Copies whose source was never removed; deleted so the sources are
+    /// moved again
+    pub removed_partials: Vec<PathBuf>,
+    /// Moves that had finished before the journal could record them
+    pub completed: Vec<(PathBuf, PathBuf)>,
+    /// Moves where neither the source nor the destination exists anymore
+    pub missing: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Settles every move the interrupted run left in flight.
+///
+/// Destinations are never overwritten, so when both files exist the
+/// destination is a copy whose source was not removed yet. The source stays
+/// authoritative: the copy is deleted and the file moved again. A rename is
+/// atomic and leaves exactly one of them.
+pub fn recover(state: &JournalState) -> io::Result<Recovery> {
+    let mut recovery = Recovery::default();
+
+    for (source, destination) in &state.in_flight {
+        match (source.exists(), destination.exists()) {
+            (true, true) => {
+                fs::remove_file(destination)?;
+                recovery.removed_partials.push(destination.clone());
+            }
+            (false, true) => recovery.completed.push((source.clone(), destination.clone())),
+            (true, false) => {} // never started, moved again like any other file
+            (false, false) => recovery.missing.push((source.clone(), destination.clone())),
+        }
+    }
+
+    Ok(recovery)
+}
+
+/// Paths are recorded absolute, so `--undo` and `--resume` find the files
+/// whatever directory they are started from.
+pub(crate) fn escape_absolute(path: &Path) -> io::Result<Vec<u8>> {
+    Ok(escape_path(&std::path::absolute(path)?))
+}
+
+fn escape_path(path: &Path) -> Vec<u8> {
+    let mut escaped = Vec::new();
+    for &byte in path_bytes(path).iter() {
+        match byte {
+            b'\\' => escaped.extend_from_slice(b"\\\\"),
+            b'\t' => escaped.extend_from_slice(b"\\t"),
+            b'\n' => escaped.extend_from_slice(b"\\n"),
+            b'\r' => escaped.extend_from_slice(b"\\r"),
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+fn unescape_path(escaped: &[u8]) -> PathBuf {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut iter = escaped.iter();
+    while let Some(&byte) = iter.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match iter.next() {
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(&other) => bytes.push(other),
+            None => bytes.push(b'\\'),
+        }
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    std::borrow::Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    std::borrow::Cow::Owned(path.to_string_lossy().into_owned().into_bytes())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_state_tracks_in_flight_moves() {
+        let dir = tempdir().unwrap();
+        let journal = Journal::create(dir.path()).unwrap();
+
+        journal.record_start(Path::new("/src/a.jpg"), Path::new("/out/Images/a.jpg")).unwrap();
+        journal.record_done(Path::new("/src/a.jpg"), Path::new("/out/Images/a.jpg")).unwrap();
+        journal.record_start(Path::new("/src/b\tc.jpg"), Path::new("/out/Images/b\tc.jpg")).unwrap();
+
+        let state = read_state(dir.path()).unwrap().unwrap();
+        assert!(state.is_interrupted());
+        assert_eq!(state.done.len(), 1);
+        assert_eq!(state.in_flight, vec![(PathBuf::from("/src/b\tc.jpg"), PathBuf::from("/out/Images/b\tc.jpg"))]);
+
+        journal.finish(false).unwrap();
+        journal.record_rmdir(Path::new("/src/empty")).unwrap();
+        let state = read_state(dir.path()).unwrap().unwrap();
+        assert!(!state.is_interrupted());
+        assert_eq!(state.removed_dirs, vec![PathBuf::from("/src/empty")]);
+        assert!(state.created_dirs.is_empty());
+        assert!(!state.copied);
+    }
+
+    #[test]
+    fn test_read_state_created_dirs_and_copy_mode() {
+        let dir = tempdir().unwrap();
+        let journal = Journal::create(dir.path()).unwrap();
+        journal.record_copy_mode().unwrap();
+        journal.record_mkdir(Path::new("/out/Images")).unwrap();
+        journal.record_mkdir(Path::new("/out/Images/2024")).unwrap();
+        journal.finish(false).unwrap();
+
+        let state = read_state(dir.path()).unwrap().unwrap();
+        assert!(state.copied);
+        assert_eq!(state.created_dirs, vec![PathBuf::from("/out/Images"), PathBuf::from("/out/Images/2024")]);
+    }
+
+    #[test]
+    fn test_resume_appends() {
+        let dir = tempdir().unwrap();
+        Journal::create(dir.path()).unwrap().finish(true).unwrap();
+        assert!(read_state(dir.path()).unwrap().unwrap().is_interrupted());
+
+        let journal = Journal::resume(dir.path()).unwrap();
+        journal.finish(false).unwrap();
+
+        let contents = fs::read_to_string(journal.path()).unwrap();
+        assert!(contents.starts_with("RUN\t"));
+        assert!(contents.contains("\nRESUME\t"));
+        assert!(contents.ends_with("END\tcomplete\n"));
+    }
+
+    #[test]
+    fn test_torn_last_line_is_ignored() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(JOURNAL_FILE_NAME), "RUN\t1\tx\nSTART\t/a\t/b\nDONE\t/a").unwrap();
+
+        let state = read_state(dir.path()).unwrap().unwrap();
+        assert_eq!(state.in_flight.len(), 1);
+        assert!(state.done.is_empty());
+    }
+
+    #[test]
+    fn test_recover_removes_partial_copies() {
+        let dir = tempdir().unwrap();
+        let partial_source = dir.path().join("partial.bin");
+        let partial_destination = dir.path().join("out-partial.bin");
+        let moved_source = dir.path().join("moved.bin");
+        let moved_destination = dir.path().join("out-moved.bin");
+        fs::write(&partial_source, "full").unwrap();
+        fs::write(&partial_destination, "fu").unwrap();
+        fs::write(&moved_destination, "full").unwrap();
+
+        let state = JournalState {
+            complete: false,
+            done: Vec::new(),
+            removed_dirs: Vec::new(),
+            created_dirs: Vec::new(),
+            copied: false,
+            in_flight: vec![
+                (partial_source.clone(), partial_destination.clone()),
+                (moved_source.clone(), moved_destination.clone()),
+                (dir.path().join("gone"), dir.path().join("out-gone")),
+            ],
+        };
+
+        let recovery = recover(&state).unwrap();
+        assert_eq!(recovery.removed_partials, vec![partial_destination.clone()]);
+        assert_eq!(recovery.completed, vec![(moved_source, moved_destination)]);
+        assert_eq!(recovery.missing.len(), 1);
+        assert!(partial_source.exists());
+        assert!(!partial_destination.exists());
+    }
+
+    #[test]
+    fn test_remove_staged_copies() {
+        let dir = tempdir().unwrap();
+        let staged = dir.path().join(format!("{}99.tmp", staging::STAGING_PREFIX));
+        fs::write(&staged, "partial").unwrap();
+
+        let state = JournalState {
+            complete: false,
+            done: Vec::new(),
+            removed_dirs: Vec::new(),
+            created_dirs: Vec::new(),
+            copied: false,
+            in_flight: vec![(PathBuf::from("/src/a.jpg"), dir.path().join("a.jpg"))],
+        };
+
+        assert_eq!(remove_staged_copies(&state).unwrap(), vec![staged.clone()]);
+        assert!(!staged.exists());
+    }
+
+    #[test]
+    fn test_no_journal() {
+        let dir = tempdir().unwrap();
+        assert!(read_state(dir.path()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::datetime::DateTime;
+
+/// Name of the lock file created in the source and output directories.
+/// The leading dot keeps it out of scans.
+pub const LOCK_FILE_NAME: &str = ".file-organizer.lock";
+
+/// Advisory locks on the directories a run works on, released when dropped.
+///
+/// Each lock file records the PID and start time of the run holding it, so
+/// a second instance can say who it is waiting for.
+#[derive(Debug)]
+pub struct RunLock {
+    files: Vec<(PathBuf, File)>,
+}
+
+impl RunLock {
+    /// Locks every directory in `dirs`. Without `wait`, fails with
+    /// `WouldBlock` as soon as one of them is held by another run.
+    pub fn acquire(dirs: &[&Path], wait: bool) -> io::Result<Self> {
+        // A fixed order keeps two waiting runs from deadlocking each other
+        let mut paths = Vec::new();
+        for dir in dirs {
+            let path = fs::canonicalize(dir)?.join(LOCK_FILE_NAME);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut lock = Self { files: Vec::new() };
+        for path in paths {
+            let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+
+            if !try_lock(&file)? {
+                let holder = describe_holder(&path);
+                if !wait {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        format!(
+                            "another organizer is already running on '{}' ({}); use --wait to wait for it",
+                            path.parent().unwrap_or(&path).display(),
+                            holder
+                        ),
+                    ));
+                }
+                println!("Waiting for the organizer holding {} ({})...", path.display(), holder);
+                lock_blocking(&file)?;
+            }
+
+            write_holder(&file)?;
+            lock.files.push((path, file));
+        }
+
+        Ok(lock)
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // The lock file stays behind: deleting it would let a run that is
+        // blocked on the old inode and a new run both believe they hold it
+        for (_, file) in &self.files {
+            let _ = file.set_len(0);
+            unlock(file);
+        }
+    }
+}
+
+fn write_holder(mut file: &File) -> io::Result<()> {
+    let started = DateTime::from_system_time(SystemTime::now()).format("%Y-%m-%d %H:%M:%S");
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    writeln!(file, "pid {}", std::process::id())?;
+    writeln!(file, "started {}", started)?;
+    file.sync_data()
+}
+
+/// "pid 1234, started 2024-02-29 12:34:56" from the lock file contents.
+fn describe_holder(path: &Path) -> String {
+    let mut contents = String::new();
+    let _ = File::open(path).and_then(|mut file| file.read_to_string(&mut contents));
+
+    let fields: Vec<&str> = contents.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    if fields.is_empty() {
+        "holder unknown".to_string()
+    } else {
+        fields.join(", ")
+    }
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let error = io::Error::last_os_error();
+    if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        Ok(false)
+    } else {
+        Err(error)
+    }
+}
+
+#[cfg(unix)]
+fn lock_blocking(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn unlock(file: &File) {
+    use std::os::unix::io::AsRawFd;
+
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) };
+}
+
+// Without flock the lock file is informational only
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn lock_blocking(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn unlock(_file: &File) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_second_lock_fails_fast() {
+        let dir = tempdir().unwrap();
+
+        let first = RunLock::acquire(&[dir.path()], false).unwrap();
+        let contents = fs::read_to_string(dir.path().join(LOCK_FILE_NAME)).unwrap();
+        assert!(contents.contains(&format!("pid {}", std::process::id())));
+        assert!(contents.contains("started "));
+
+        let error = RunLock::acquire(&[dir.path()], false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        assert!(error.to_string().contains(&format!("pid {}", std::process::id())));
+
+        drop(first);
+        assert!(RunLock::acquire(&[dir.path()], false).is_ok());
+    }
+
+    #[test]
+    fn test_same_directory_locked_once() {
+        let dir = tempdir().unwrap();
+
+        let lock = RunLock::acquire(&[dir.path(), dir.path()], false).unwrap();
+        assert_eq!(lock.paths().count(), 1);
+    }
+
+    #[test]
+    fn test_wait_blocks_until_released() {
+        let dir = tempdir().unwrap();
+        let first = RunLock::acquire(&[dir.path()], false).unwrap();
+
+        let path = dir.path().to_path_buf();
+        let waiter = std::thread::spawn(move || RunLock::acquire(&[path.as_path()], true).map(|_| ()));
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!waiter.is_finished());
+        drop(first);
+
+        assert!(waiter.join().unwrap().is_ok());
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use clap::ValueEnum;
+
+use crate::datetime::DateTime;
+
+/// How much goes into the log file; each level includes the ones above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Level {
+    /// Fatal errors and files that could not be moved
+    Error,
+    /// Skipped files and directories
+    Warn,
+    /// The run itself and every file moved
+    Info,
+    /// Category, rule and layout decisions for every file
+    Debug,
+    /// Rules that did not match and entries the scan passed over
+    Trace,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+/// Layout of the records in the log file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogFormat {
+    /// One line per record: time, level, message and key=value fields
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// A value attached to a record, such as `("file", &path.display())`.
+pub type Field<'a> = (&'a str, &'a dyn fmt::Display);
+
+struct Logger {
+    file: Mutex<File>,
+    format: LogFormat,
+    level: Level,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+static OUTPUT: OnceLock<LogFormat> = OnceLock::new();
+
+/// Appends records up to `level` to `path` for the rest of the process.
+/// Until this is called nothing is logged.
+pub fn init(path: &Path, format: LogFormat, level: Level) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let logger = Logger {
+        file: Mutex::new(file),
+        format,
+        level,
+    };
+    LOGGER
+        .set(logger)
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "the log file is already open"))
+}
+
+/// Whether records of `level` are written, to skip work for ones that
+/// are not.
+pub fn enabled(level: Level) -> bool {
+    LOGGER.get().is_some_and(|logger| level <= logger.level)
+}
+
+pub fn log(level: Level, message: &str, fields: &[Field]) {
+    let logger = match LOGGER.get() {
+        Some(logger) if level <= logger.level => logger,
+        _ => return,
+    };
+
+    let time = DateTime::from_system_time(SystemTime::now()).format("%Y-%m-%dT%H:%M:%S");
+    let line = format_record(logger.format, &time, level, message, fields);
+    // Logging must never stop a run; a full disk shows up in the moves
+    if let Ok(mut file) = logger.file.lock() {
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+pub fn error(message: &str, fields: &[Field]) {
+    log(Level::Error, message, fields);
+}
+
+pub fn warn(message: &str, fields: &[Field]) {
+    log(Level::Warn, message, fields);
+}
+
+pub fn info(message: &str, fields: &[Field]) {
+    log(Level::Info, message, fields);
+}
+
+pub fn debug(message: &str, fields: &[Field]) {
+    log(Level::Debug, message, fields);
+}
+
+pub fn trace(message: &str, fields: &[Field]) {
+    log(Level::Trace, message, fields);
+}
+
+/// Writes the run's progress and summary to stdout as `format`: plain
+/// lines, or one JSON record per line that a script can parse.
+pub fn init_output(format: LogFormat) {
+    let _ = OUTPUT.set(format);
+}
+
+/// Whether stdout carries JSON records rather than plain lines.
+pub fn json_output() -> bool {
+    OUTPUT.get() == Some(&LogFormat::Json)
+}
+
+/// One line of run progress on stdout. As JSON it becomes an `info`
+/// record; blank lines and `===` rules only lay out the text and are left
+/// out.
+pub fn print(text: &str) {
+    if !json_output() {
+        println!("{}", text);
+        return;
+    }
+    let message = text.trim();
+    if !message.chars().all(|c| c == '=') {
+        print_record(Level::Info, message, &[]);
+    }
+}
+
+/// A record with fields on stdout, for output that is laid out by hand as
+/// text and only goes through here as JSON.
+pub fn print_record(level: Level, message: &str, fields: &[Field]) {
+    let time = DateTime::from_system_time(SystemTime::now()).format("%Y-%m-%dT%H:%M:%S");
+    let line = format_record(LogFormat::Json, &time, level, message, fields);
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(line.as_bytes());
+    let _ = stdout.flush();
+}
+
+/// One record, ending in a newline.
+fn format_record(format: LogFormat, time: &str, level: Level, message: &str, fields: &[Field]) -> String {
+    match format {
+        LogFormat::Text => {
+            let mut line = format!("{} {:<5} {}", time, level.as_str().to_uppercase(), message);
+            for (key, value) in fields {
+                line.push_str(&format!(" {}={}", key, text_value(&value.to_string())));
+            }
+            line.push('\n');
+            line
+        }
+        LogFormat::Json => {
+            let mut line = format!(
+                "{{\"time\":{},\"level\":{},\"message\":{}",
+                json_string(time),
+                json_string(level.as_str()),
+                json_string(message)
+            );
+            for (key, value) in fields {
+                line.push_str(&format!(",{}:{}", json_string(key), json_string(&value.to_string())));
+            }
+            line.push_str("}\n");
+            line
+        }
+    }
+}
+
+/// `text` as a quoted JSON string, for the log records and the `stats`
+/// report.
+pub fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Values with spaces, quotes or `=` are quoted so a line splits back into
+/// its fields.
+fn text_value(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c.is_control() || matches!(c, '"' | '=')) {
+        return value.to_string();
+    }
+    format!("{:?}", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_record() {
+        let line = format_record(
+            LogFormat::Text,
+            "2024-05-01T10:00:00",
+            Level::Info,
+            "moved",
+            &[("source", &"/in/a.jpg"), ("destination", &"/out/My Images/a.jpg")],
+        );
+        assert_eq!(
+            line,
+            "2024-05-01T10:00:00 INFO  moved source=/in/a.jpg destination=\"/out/My Images/a.jpg\"\n"
+        );
+    }
+
+    #[test]
+    fn test_json_record() {
+        let line = format_record(
+            LogFormat::Json,
+            "2024-05-01T10:00:00",
+            Level::Warn,
+            "skipped",
+            &[("reason", &"name \"a\"\tb"), ("count", &3)],
+        );
+        assert_eq!(
+            line,
+            "{\"time\":\"2024-05-01T10:00:00\",\"level\":\"warn\",\"message\":\"skipped\",\"reason\":\"name \\\"a\\\"\\tb\",\"count\":\"3\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_json_string_escapes() {
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+
+    #[test]
+    fn test_levels_are_ordered() {
+        assert!(Level::Error < Level::Warn);
+        assert!(Level::Info < Level::Debug);
+        assert!(Level::Debug < Level::Trace);
+        // Nothing is logged before init
+        assert!(!enabled(Level::Error));
+    }
+}
@@ -1,129 +1,1340 @@
-use clap::Parser;
-use std::path::PathBuf;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
 
+mod audio_tags;
 mod categories;
+mod config;
+mod copy;
+mod datetime;
+mod diskspace;
+mod document_meta;
+mod exif;
+mod exitcode;
+mod flatten;
+mod ignore;
+mod interactive;
+mod isobmff;
+mod journal;
+mod lock;
+mod logging;
+mod mounts;
 mod organizer;
+mod prune;
+mod rules;
+mod runlog;
+mod sanitize;
 mod scanner;
+mod schedule;
+mod shard;
+mod signals;
+mod staging;
+mod stats;
+mod template;
+mod tui;
+mod undo;
 
-use categories::CategoryMapper;
-use organizer::Organizer;
-use scanner::DirectoryScanner;
+use categories::{CategoryMapper, FileCategory};
+use config::{Config, Profile};
+use flatten::FlattenMap;
+use ignore::IgnoreList;
+use interactive::Session;
+use journal::Journal;
+use lock::RunLock;
+use logging::{Level, LogFormat};
+use copy::ReflinkMode;
+use organizer::{ConflictStrategy, MoveResult, Organizer, TransferMode};
+use rules::Rule;
+use sanitize::{CaseStyle, NameSanitizer, SanitizeStep};
+use scanner::{DirectoryScanner, ScanResult, SymlinkPolicy};
+use schedule::{Job, JobLog, LogTarget};
+use shard::ShardScheme;
+use stats::{Stats, StatsFormat};
+use template::{Layout, Template, AUDIO_PLACEHOLDERS, PLACEHOLDERS};
 
 /// A CLI tool to organize files by their extensions into categorized folders
 #[derive(Parser, Debug)]
 #[command(name = "file-organizer")]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Source directory containing files to organize
-    #[arg(short, long, default_value = ".")]
+    #[arg(short, long, default_value = ".", global = true)]
     pub source: PathBuf,
 
     /// Output directory for organized files (defaults to source directory)
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub output: Option<PathBuf>,
 
     /// Preview changes without actually moving files
-    #[arg(short, long, default_value_t = false)]
+    #[arg(short, long, default_value_t = false, global = true)]
     pub dry_run: bool,
 
     /// Show verbose output
-    #[arg(short, long, default_value_t = false)]
+    #[arg(short, long, default_value_t = false, global = true)]
     pub verbose: bool,
+
+    /// Print nothing but errors; the exit status tells how the run went
+    #[arg(short, long, default_value_t = false, conflicts_with = "verbose", global = true)]
+    pub quiet: bool,
+
+    /// Also organize files in subdirectories, keeping their relative folders
+    #[arg(short, long, default_value_t = false, global = true)]
+    pub recursive: bool,
+
+    /// Config file with [profiles.<name>] sections (default: ~/.config/file-organizer/config.toml)
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Do not descend into directories on other filesystems (like find -xdev)
+    #[arg(short = 'x', long, default_value_t = false, requires = "recursive", global = true)]
+    pub one_file_system: bool,
+
+    /// Do not descend into filesystems of these types, e.g. "nfs,fuse.sshfs,tmpfs"
+    #[arg(long, value_name = "TYPE", value_delimiter = ',', requires = "recursive", global = true)]
+    pub skip_fs_type: Vec<String>,
+
+    /// What to do with symbolic links: leave them, move them as links, or organize their targets
+    #[arg(long, value_enum, default_value_t = SymlinkPolicy::MoveLink, global = true)]
+    pub symlinks: SymlinkPolicy,
+
+    /// Put files from every depth directly into their category folder
+    #[arg(long, default_value_t = false, requires = "recursive", global = true)]
+    pub flatten: bool,
+
+    /// Remove source subdirectories left empty by the run (recorded for --undo)
+    #[arg(long, default_value_t = false, requires = "recursive", global = true)]
+    pub prune_empty: bool,
+
+    /// Move the files of the last run back and recreate pruned directories
+    #[arg(long, default_value_t = false, conflicts_with_all = ["resume", "stream"], global = true)]
+    pub undo: bool,
+
+    /// Review the plan per category and per file before anything is moved
+    #[arg(short, long, default_value_t = false, conflicts_with_all = ["stream", "undo"], global = true)]
+    pub interactive: bool,
+
+    /// Organize files while scanning instead of listing them first (for very
+    /// large directories; skips the category overview and free-space check)
+    #[arg(long, default_value_t = false, global = true)]
+    pub stream: bool,
+
+    /// Move files, or copy them and leave the source untouched
+    #[arg(long, value_enum, default_value_t = TransferMode::Move, global = true)]
+    pub mode: TransferMode,
+
+    /// What to do when a destination is taken: skip the file or rename it "name (1).ext"
+    #[arg(long, value_enum, default_value_t = ConflictStrategy::Skip, global = true)]
+    pub on_conflict: ConflictStrategy,
+
+    /// Use copy-on-write clones for copies: when supported, always, or never
+    #[arg(long, value_enum, default_value_t = ReflinkMode::Auto, global = true)]
+    pub reflink: ReflinkMode,
+
+    /// Append all output to this file, rotating it as it grows (for scheduled runs)
+    #[arg(long, value_name = "PATH", global = true)]
+    pub run_log: Option<PathBuf>,
+
+    /// Record every file decision and its reason in this file, appending to it
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Format of the --log-file records and of the run's output on stdout
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    pub log_format: LogFormat,
+
+    /// Most detailed records written to --log-file
+    #[arg(long, value_enum, default_value_t = Level::Info, global = true)]
+    pub log_level: Level,
+
+    /// Wait for another organizer working on the same directories instead of failing
+    #[arg(long, default_value_t = false, global = true)]
+    pub wait: bool,
+
+    /// Clean up after an interrupted run and continue where it stopped
+    #[arg(long, default_value_t = false, conflicts_with = "dry_run", global = true)]
+    pub resume: bool,
+
+    /// Keep one folder per category instead of subfolders like Documents/Spreadsheets
+    #[arg(long, default_value_t = false, global = true)]
+    pub flat: bool,
+
+    /// Cap the entries per folder; further files go into shard subfolders
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), global = true)]
+    pub max_per_folder: Option<u64>,
+
+    /// Shard folder names: numbered (0001, 0002, ...) or by first letter (A-C, D-F, ...)
+    #[arg(long, value_enum, default_value_t = ShardScheme::Number, requires = "max_per_folder", global = true)]
+    pub shard_by: ShardScheme,
+
+    /// Clean up file names while moving (comma-separated steps)
+    #[arg(long, value_enum, value_delimiter = ',', global = true)]
+    pub sanitize: Vec<SanitizeStep>,
+
+    /// Rewrite file names in the given case style while moving
+    #[arg(long, value_enum, global = true)]
+    pub case: Option<CaseStyle>,
+
+    /// Template for destination file names, e.g. "{date:%Y-%m-%d}_{stem}{ext}"
+    #[arg(long, global = true)]
+    pub name_template: Option<String>,
+
+    /// Folder layout, optionally for one category, e.g. "images=Images/{exif.year}/{exif.month}"
+    #[arg(long, value_name = "[CATEGORY=]TEMPLATE", global = true)]
+    pub layout: Vec<String>,
+
+    /// Routing rule tried before the layouts, e.g. "doc.author contains ACME => Clients/ACME"
+    #[arg(long, value_name = "FIELD OPERATOR VALUE => LAYOUT", global = true)]
+    pub rule: Vec<String>,
+
+    /// Folder name for music without an artist tag
+    #[arg(long, default_value = "Unknown Artist", global = true)]
+    pub unknown_artist: String,
+
+    /// Folder name for music without an album tag
+    #[arg(long, default_value = "Unknown Album", global = true)]
+    pub unknown_album: String,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Review and edit the plan in a full-screen terminal UI before applying it
+    Tui,
+    /// Show what the source contains: files, sizes and dates per category
+    Stats(StatsArgs),
+    /// Organize with the settings of a profile from the config file
+    Run {
+        /// Name of a [profiles.<name>] section
+        profile: String,
+    },
+    /// Work with the profiles in the config file
+    Profiles {
+        #[command(subcommand)]
+        action: ProfilesAction,
+    },
+    /// Run the organizer unattended from a systemd user timer
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleAction,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum ProfilesAction {
+    /// Show the profiles and their settings
+    List,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleAction {
+    /// Write a service and timer into ~/.config/systemd/user
+    Install(InstallArgs),
+    /// Show the installed jobs
+    List,
+    /// Delete the service and timer of a job
+    Remove {
+        /// Name the job was installed with
+        profile: String,
+    },
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+pub struct InstallArgs {
+    /// Name of the job, used in the unit names. If the config file has a
+    /// profile of that name, the job runs it
+    #[arg(long)]
+    pub profile: String,
+
+    #[command(flatten)]
+    pub when: When,
+
+    /// Where scheduled runs write their output
+    #[arg(long, value_enum, default_value_t = LogTarget::Journal)]
+    pub log: LogTarget,
+
+    /// Log file for --log file (default: ~/.local/state/file-organizer/<profile>.log)
+    #[arg(long, value_name = "PATH")]
+    pub log_path: Option<PathBuf>,
+
+    /// Further options for the scheduled runs, after `--`
+    #[arg(last = true, value_name = "OPTIONS")]
+    pub options: Vec<String>,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+#[group(required = true, multiple = false)]
+pub struct When {
+    #[arg(long)]
+    pub hourly: bool,
+
+    #[arg(long)]
+    pub daily: bool,
+
+    #[arg(long)]
+    pub weekly: bool,
+
+    /// Any systemd calendar expression, e.g. "Mon..Fri 02:00"
+    #[arg(long, value_name = "SPEC")]
+    pub on_calendar: Option<String>,
+}
+
+impl When {
+    fn on_calendar(&self) -> String {
+        match &self.on_calendar {
+            Some(spec) => spec.clone(),
+            None if self.hourly => "hourly".to_string(),
+            None if self.weekly => "weekly".to_string(),
+            None => "daily".to_string(),
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+pub struct StatsArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = StatsFormat::Table)]
+    pub format: StatsFormat,
+
+    /// How many of the largest files and unmapped extensions to list
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub top: usize,
 }
 
 fn main() {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let explicit = explicit_args(&matches);
 
-    println!("File Organizer");
-    println!("==============");
-    println!("Source directory: {}", args.source.display());
+    match &args.command {
+        Some(Command::Stats(stats_args)) => {
+            show_stats(&args, stats_args);
+            return;
+        }
+        Some(Command::Schedule { action }) => {
+            if let Err(e) = schedule_command(&args, action, &matches, &explicit) {
+                fatal(&e.to_string());
+            }
+            return;
+        }
+        Some(Command::Profiles { action: ProfilesAction::List }) => {
+            let (path, config) = load_config(&args);
+            if config.profiles.is_empty() {
+                println!("No profiles in {}", path.display());
+            }
+            for profile in &config.profiles {
+                println!("{}", profile.name);
+                for setting in profile.settings() {
+                    println!("  {}", setting);
+                }
+            }
+            return;
+        }
+        Some(Command::Run { profile }) => {
+            let (path, config) = load_config(&args);
+            match config.profile(profile) {
+                Some(profile) => apply_profile(&mut args, profile, &explicit),
+                None => {
+                    let names: Vec<&str> = config.profiles.iter().map(|profile| profile.name.as_str()).collect();
+                    eprintln!("Error: no profile '{}' in {}", profile, path.display());
+                    if !names.is_empty() {
+                        eprintln!("Profiles: {}", names.join(", "));
+                    }
+                    std::process::exit(exitcode::FATAL);
+                }
+            }
+        }
+        _ => {}
+    }
+
+    logging::init_output(args.log_format);
+
+    if let Some(path) = &args.run_log {
+        if let Err(e) = runlog::redirect_output(path, runlog::MAX_LOG_BYTES, runlog::KEPT_LOGS) {
+            fatal(&format!("cannot write run log {}: {}", path.display(), e));
+        }
+        let started = datetime::DateTime::from_system_time(std::time::SystemTime::now());
+        logging::print(&format!("\n--- Run started {} ---", started.format("%Y-%m-%d %H:%M:%S")));
+    }
+
+    if let Some(path) = &args.log_file {
+        if let Err(e) = logging::init(path, args.log_format, args.log_level) {
+            fatal(&format!("cannot write log file {}: {}", path.display(), e));
+        }
+    }
+
+    if args.interactive && !interactive::has_terminal() {
+        fatal("--interactive needs a terminal to ask questions on");
+    }
+
+    if args.command == Some(Command::Tui) {
+        if args.stream || args.undo || args.interactive {
+            fatal("the tui command cannot be combined with --stream, --undo or --interactive");
+        }
+        if !interactive::has_terminal() {
+            fatal("the tui command needs a terminal");
+        }
+    }
 
     let output_dir = args.output.as_ref().unwrap_or(&args.source);
-    println!("Output directory: {}", output_dir.display());
 
-    if args.dry_run {
-        println!("Mode: Dry run (no files will be moved)");
+    let name_template = match args.name_template.as_deref().map(parse_name_template) {
+        Some(Ok(template)) => Some(template),
+        Some(Err(e)) => {
+            fatal(&e.to_string());
+        }
+        None => None,
+    };
+
+    let mut layouts = Vec::new();
+    for spec in &args.layout {
+        match parse_layout(spec) {
+            Ok(layout) => layouts.push(layout),
+            Err(e) => {
+                fatal(&e.to_string());
+            }
+        }
     }
 
-    if args.verbose {
-        println!("Verbose mode: enabled");
+    let mut rules = Vec::new();
+    for spec in &args.rule {
+        match Rule::parse(spec) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => {
+                fatal(&e.to_string());
+            }
+        }
     }
 
-    let mapper = CategoryMapper::new();
+    if !args.quiet {
+        print_banner(&args, output_dir, name_template.as_ref(), &layouts, &rules);
+    }
+    logging::info(
+        if args.undo { "undo started" } else { "run started" },
+        &[
+            ("source", &args.source.display()),
+            ("output", &output_dir.display()),
+            ("mode", &config::value_name(&args.mode)),
+            ("dry_run", &args.dry_run),
+        ],
+    );
+
+    // File metadata is only parsed when a template or rule asks for it
+    let templates: Vec<&Template> = name_template
+        .iter()
+        .chain(layouts.iter().flat_map(|(_, layout)| layout.templates()))
+        .chain(rules.iter().flat_map(|rule| rule.layout.templates()))
+        .collect();
+    let fields: Vec<&str> = templates
+        .iter()
+        .flat_map(|template| template.placeholders())
+        .chain(rules.iter().map(|rule| rule.field.as_str()))
+        .collect();
+    let read_exif = fields.iter().any(|name| name.starts_with("exif."));
+    let read_audio_tags = fields.iter().any(|name| AUDIO_PLACEHOLDERS.contains(name));
+    let read_documents = fields.iter().any(|name| name.starts_with("doc."));
+
+    let mapper = CategoryMapper::new().with_subcategories(!args.flat);
 
     if args.verbose {
-        println!("\nSupported categories:");
+        logging::print("\nSupported categories:");
         for category in mapper.all_categories() {
-            println!("  - {}", category.folder_name());
+            logging::print(&format!("  - {}", category.folder_name()));
+        }
+    }
+
+    // Keep other runs off the same trees until we are done. A dry run
+    // changes nothing, so it neither takes nor waits for the lock.
+    let _lock = if args.dry_run {
+        None
+    } else {
+        match acquire_lock(&args, output_dir) {
+            Ok(lock) => {
+                if args.verbose {
+                    for path in lock.paths() {
+                        logging::print(&format!("Holding lock: {}", path.display()));
+                    }
+                }
+                Some(lock)
+            }
+            Err(e) => {
+                fatal(&e.to_string());
+            }
+        }
+    };
+
+    if args.undo {
+        if !args.quiet {
+            logging::print("\nUndoing the last run...");
+        }
+        match undo::undo(output_dir, args.dry_run, args.verbose, args.reflink) {
+            Ok(result) => {
+                if args.quiet {
+                    result.print_errors();
+                } else {
+                    result.print_summary(args.dry_run);
+                }
+                let exit_code = result.exit_code();
+                logging::info(
+                    "undo finished",
+                    &[
+                        ("restored", &result.restored.len()),
+                        ("skipped", &result.skipped.len()),
+                        ("failed", &result.failed.len()),
+                        ("exit_status", &exit_code),
+                    ],
+                );
+                std::process::exit(exit_code);
+            }
+            Err(e) => {
+                fatal(&e.to_string());
+            }
         }
     }
 
+    if !args.dry_run {
+        if let Err(e) = prepare_run(output_dir, args.resume, args.quiet) {
+            fatal(&e.to_string());
+        }
+        // Ctrl-C at the --interactive prompts quits at once; the handlers
+        // would only note it until the next answer
+        if !args.interactive {
+            install_stop_handlers();
+        }
+    }
+
+    let ignored = if args.recursive {
+        match IgnoreList::load(&args.source) {
+            Ok(ignored) => ignored,
+            Err(e) => {
+                fatal(&format!("cannot read {}: {}", ignore::IGNORE_FILE_NAME, e));
+            }
+        }
+    } else {
+        IgnoreList::default()
+    };
+    if args.verbose && !ignored.is_empty() {
+        logging::print(&format!("Leaving alone the directories listed in {}", ignore::IGNORE_FILE_NAME));
+    }
+
     // Scan the source directory
-    println!("\nScanning directory...");
-    let scanner = DirectoryScanner::new(mapper);
+    if !args.quiet {
+        logging::print("\nScanning directory...");
+    }
+    // Never descend into what an earlier run already organized
+    let mut excluded_dirs = vec![output_dir.clone()];
+    excluded_dirs.extend(
+        mapper
+            .all_categories()
+            .iter()
+            .map(|category| output_dir.join(category.folder_name())),
+    );
+    let scanner = DirectoryScanner::new(mapper)
+        .with_recursive(args.recursive)
+        .with_symlinks(args.symlinks)
+        .with_excluded_dirs(excluded_dirs)
+        .with_one_file_system(args.one_file_system)
+        .with_skipped_fs_types(args.skip_fs_type.clone())
+        .with_ignored(ignored.clone())
+        .with_exif(read_exif)
+        .with_audio_tags(read_audio_tags)
+        .with_documents(read_documents);
+
+    if args.stream {
+        let organizer = build_organizer(&args, output_dir, name_template, layouts, rules);
+        let mut files = match scanner.scan_iter(&args.source) {
+            Ok(files) => files,
+            Err(e) => {
+                fatal(&format!("cannot scan directory: {}", e));
+            }
+        };
+        let organizer = start_journal(&args, organizer);
+
+        if !args.quiet {
+            match args.mode {
+                TransferMode::Move => logging::print("Moving files as they are found..."),
+                TransferMode::Copy => logging::print("Copying files as they are found..."),
+            }
+        }
+        let move_result = organizer.organize_stream(files.by_ref());
+        print_scan_problems(&args, files.dangling_links(), files.skipped_dirs());
+        prune_sources(&args, &organizer, &move_result, &ignored);
+        finish(&args, &move_result);
+    }
 
     match scanner.scan(&args.source) {
         Ok(result) => {
-            println!("Found {} files to organize\n", result.total_count);
+            if !args.quiet {
+                logging::print(&format!("Found {} files to organize\n", result.total_count));
+            }
+            print_scan_problems(&args, &result.dangling_links, &result.skipped_dirs);
+
+            if signals::stop_requested() {
+                if !args.quiet {
+                    logging::print("Interrupted before any file was moved.");
+                }
+                logging::error("interrupted before any file was moved", &[]);
+                std::process::exit(exitcode::FATAL);
+            }
 
             if result.total_count == 0 {
-                println!("No files to organize.");
-                return;
+                logging::info("run finished", &[("moved", &0), ("exit_status", &exitcode::NOTHING_TO_DO)]);
+                if !args.quiet {
+                    logging::print("No files to organize.");
+                }
+                if args.resume {
+                    // Everything was in place already; close the old run
+                    if let Err(e) = Journal::resume(output_dir).and_then(|journal| journal.finish(false)) {
+                        eprintln!("Warning: cannot update progress log: {}", e);
+                    }
+                }
+                std::process::exit(exitcode::NOTHING_TO_DO);
             }
 
-            if args.verbose {
-                println!("Files by category:");
-                for category in CategoryMapper::new().all_categories() {
-                    let count = result.category_count(&category);
-                    if count > 0 {
-                        println!("  {}: {} file(s)", category.folder_name(), count);
-                        if let Some(files) = result.categorized.get(&category) {
-                            for file in files {
-                                println!("    - {}", file.name);
+            if !args.quiet {
+                if args.verbose {
+                    logging::print("Files by category:");
+                    for category in CategoryMapper::new().all_categories() {
+                        let count = result.category_count(&category);
+                        if count > 0 {
+                            logging::print(&format!("  {}: {} file(s)", category.folder_name(), count));
+                            for file in result.files_in(&category) {
+                                match file.subcategory {
+                                    Some(subcategory) => logging::print(&format!("    - {}/{}", subcategory, file.name)),
+                                    None => logging::print(&format!("    - {}", file.name)),
+                                }
                             }
                         }
                     }
+                } else {
+                    logging::print("Files by category:");
+                    for category in CategoryMapper::new().all_categories() {
+                        let count = result.category_count(&category);
+                        if count > 0 {
+                            logging::print(&format!("  {}: {} file(s)", category.folder_name(), count));
+                        }
+                    }
                 }
-            } else {
-                println!("Files by category:");
-                for category in CategoryMapper::new().all_categories() {
-                    let count = result.category_count(&category);
-                    if count > 0 {
-                        println!("  {}: {} file(s)", category.folder_name(), count);
+            }
+
+            let organizer = build_organizer(&args, output_dir, name_template, layouts, rules);
+
+            // Make sure cross-device copies fit before touching anything
+            match organizer.estimate_space(&result) {
+                Ok(estimate) => {
+                    if args.dry_run && !args.quiet {
+                        logging::print("\nProjected disk usage:");
+                        estimate.print_report();
+                    } else if args.verbose && estimate.cross_device_files > 0 {
+                        logging::print("\nDisk space check:");
+                        estimate.print_report();
                     }
+
+                    if !args.dry_run {
+                        if let Err(e) = organizer.check_space(&estimate) {
+                            fatal(&e.to_string());
+                        }
+                    }
+                }
+                Err(e) => {
+                    fatal(&format!("cannot check free space in {}: {}", organizer.output_dir().display(), e));
                 }
             }
 
+            if args.interactive {
+                review_and_organize(&args, organizer, &result, &ignored);
+                return;
+            }
+
+            if args.command == Some(Command::Tui) {
+                let review = match tui::run(&organizer, &result) {
+                    Ok(Some(review)) => review,
+                    Ok(None) => {
+                        if !args.quiet {
+                            logging::print("Quit; nothing was moved.");
+                        }
+                        std::process::exit(exitcode::NOTHING_TO_DO);
+                    }
+                    Err(e) => {
+                        fatal(&e.to_string());
+                    }
+                };
+                let organizer = start_journal(&args, organizer);
+                let move_result = tui::apply(&organizer, review);
+                prune_sources(&args, &organizer, &move_result, &ignored);
+                finish(&args, &move_result);
+            }
+
             // Create directory structure
-            println!("\nCreating directory structure...");
-            let organizer = Organizer::new(output_dir.clone(), args.dry_run, args.verbose);
+            if !args.quiet {
+                logging::print("\nCreating directory structure...");
+            }
 
             match organizer.create_category_directories(&result) {
+                Ok(_) if args.quiet => {}
                 Ok(created) => {
                     if args.dry_run {
-                        println!("Would create {} directories", created.len());
+                        logging::print(&format!("Would create {} directories", created.len()));
                     } else if created.is_empty() {
-                        println!("All directories already exist");
+                        logging::print("All directories already exist");
                     } else {
-                        println!("Created {} directories", created.len());
+                        logging::print(&format!("Created {} directories", created.len()));
                     }
                 }
                 Err(e) => {
-                    eprintln!("Error creating directories: {}", e);
-                    std::process::exit(1);
+                    fatal(&format!("cannot create directories: {}", e));
                 }
             }
 
             // Move files to their categories
-            println!("\nMoving files...");
+            if !args.quiet {
+                match args.mode {
+                    TransferMode::Move => logging::print("\nMoving files..."),
+                    TransferMode::Copy => logging::print("\nCopying files..."),
+                }
+            }
+            let organizer = start_journal(&args, organizer);
             let move_result = organizer.move_files(&result);
+            prune_sources(&args, &organizer, &move_result, &ignored);
 
             // Print final summary
-            move_result.print_summary(args.dry_run);
+            finish(&args, &move_result);
+        }
+        Err(e) => {
+            fatal(&format!("cannot scan directory: {}", e));
+        }
+    }
+}
+
+/// Ids of the options given on the command line, rather than defaulted.
+fn explicit_args(matches: &ArgMatches) -> HashSet<String> {
+    let mut explicit: HashSet<String> = matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .map(|id| id.to_string())
+        .collect();
+    if let Some((_, subcommand)) = matches.subcommand() {
+        explicit.extend(explicit_args(subcommand));
+    }
+    explicit
+}
+
+/// The config file from `--config` or its default place; only a file named
+/// with `--config` has to exist.
+fn load_config(args: &Args) -> (PathBuf, Config) {
+    let path = match args.config.clone().or_else(config::default_path) {
+        Some(path) => path,
+        None => {
+            fatal("HOME is not set; name the config file with --config");
         }
+    };
+    match Config::load(&path, args.config.is_some()) {
+        Ok(config) => (path, config),
         Err(e) => {
-            eprintln!("Error scanning directory: {}", e);
-            std::process::exit(1);
+            fatal(&e.to_string());
         }
     }
 }
+
+/// Takes every setting of `profile` that was not given on the command line.
+fn apply_profile(args: &mut Args, profile: &Profile, explicit: &HashSet<String>) {
+    let unset = |id: &str| !explicit.contains(id);
+
+    if let Some(source) = profile.source.clone().filter(|_| unset("source")) {
+        args.source = source;
+    }
+    if let Some(output) = profile.output.clone().filter(|_| unset("output")) {
+        args.output = Some(output);
+    }
+    if let Some(mode) = profile.mode.filter(|_| unset("mode")) {
+        args.mode = mode;
+    }
+    if let Some(template) = profile.name_template.clone().filter(|_| unset("name_template")) {
+        args.name_template = Some(template);
+    }
+    if !profile.layout.is_empty() && unset("layout") {
+        args.layout = profile.layout.clone();
+    }
+    if !profile.rules.is_empty() && unset("rule") {
+        args.rule = profile.rules.clone();
+    }
+    if let Some(on_conflict) = profile.on_conflict.filter(|_| unset("on_conflict")) {
+        args.on_conflict = on_conflict;
+    }
+    if let Some(recursive) = profile.recursive.filter(|_| unset("recursive")) {
+        args.recursive = recursive;
+    }
+    if let Some(one_file_system) = profile.one_file_system.filter(|_| unset("one_file_system")) {
+        args.one_file_system = one_file_system;
+    }
+    if !profile.skip_fs_type.is_empty() && unset("skip_fs_type") {
+        args.skip_fs_type = profile.skip_fs_type.clone();
+    }
+    if let Some(symlinks) = profile.symlinks.filter(|_| unset("symlinks")) {
+        args.symlinks = symlinks;
+    }
+    if let Some(flatten) = profile.flatten.filter(|_| unset("flatten")) {
+        args.flatten = flatten;
+    }
+    if let Some(prune_empty) = profile.prune_empty.filter(|_| unset("prune_empty")) {
+        args.prune_empty = prune_empty;
+    }
+    if let Some(max_entries) = profile.max_per_folder.filter(|_| unset("max_per_folder")) {
+        args.max_per_folder = Some(max_entries);
+    }
+    if let Some(scheme) = profile.shard_by.filter(|_| unset("shard_by")) {
+        args.shard_by = scheme;
+    }
+    if !profile.sanitize.is_empty() && unset("sanitize") {
+        args.sanitize = profile.sanitize.clone();
+    }
+    if let Some(case) = profile.case.filter(|_| unset("case")) {
+        args.case = Some(case);
+    }
+    if let Some(reflink) = profile.reflink.filter(|_| unset("reflink")) {
+        args.reflink = reflink;
+    }
+
+    // What clap checks for the command line
+    if (args.flatten || args.prune_empty) && !args.recursive {
+        fatal(&format!("profile '{}' uses flatten or prune_empty, which need recursive = true", profile.name));
+    }
+}
+
+/// Installs, lists or removes scheduled jobs.
+fn schedule_command(
+    args: &Args,
+    action: &ScheduleAction,
+    matches: &ArgMatches,
+    explicit: &HashSet<String>,
+) -> io::Result<()> {
+    let unit_dir = schedule::user_unit_dir()?;
+    match action {
+        ScheduleAction::Install(install) => {
+            schedule::validate_name(&install.profile).map_err(io::Error::other)?;
+
+            // A job named after a profile runs that profile
+            let (_, config) = load_config(args);
+            let runs_profile = config.profile(&install.profile).is_some();
+
+            let current_dir = std::env::current_dir()?;
+            let mut command = vec![std::env::current_exe()?.display().to_string()];
+            if !runs_profile && !explicit.contains("source") {
+                command.extend(["--source".to_string(), current_dir.join(&args.source).display().to_string()]);
+            }
+            command.extend(scheduled_options(matches, explicit, &current_dir)?);
+            let log = match install.log {
+                LogTarget::Journal => JobLog::Journal,
+                LogTarget::File => {
+                    let path = match &install.log_path {
+                        Some(path) => current_dir.join(path),
+                        None => schedule::default_log_path(&install.profile)?,
+                    };
+                    command.extend(["--run-log".to_string(), path.display().to_string()]);
+                    JobLog::File(path)
+                }
+            };
+            command.extend(install.options.iter().cloned());
+            if runs_profile {
+                command.extend(["run".to_string(), install.profile.clone()]);
+            }
+
+            let job = Job {
+                name: install.profile.clone(),
+                on_calendar: install.when.on_calendar(),
+                command,
+                log,
+            };
+            let (service, timer) = job.install(&unit_dir)?;
+            println!("Wrote {}", service.display());
+            println!("Wrote {}", timer.display());
+            match &job.log {
+                JobLog::Journal => println!("Runs log to the journal: journalctl --user -u {}", service.file_name().unwrap_or_default().to_string_lossy()),
+                JobLog::File(path) => println!("Runs log to {}", path.display()),
+            }
+            println!("\nStart the timer with:");
+            println!("  systemctl --user daemon-reload");
+            println!("  systemctl --user enable --now {}", schedule::timer_name(&job.name));
+        }
+        ScheduleAction::List => {
+            let jobs = schedule::list(&unit_dir)?;
+            if jobs.is_empty() {
+                println!("No scheduled jobs in {}", unit_dir.display());
+            }
+            for job in jobs {
+                println!("{} ({})", job.name, job.on_calendar);
+                println!("  {}", job.command);
+            }
+        }
+        ScheduleAction::Remove { profile } => {
+            for path in schedule::remove(&unit_dir, profile)? {
+                println!("Removed {}", path.display());
+            }
+            println!("\nStop a timer that is still running with:");
+            println!("  systemctl --user stop {}", schedule::timer_name(profile));
+            println!("  systemctl --user daemon-reload");
+        }
+    }
+    Ok(())
+}
+
+/// Every top-level option given on the command line, to repeat in the
+/// scheduled runs. Paths are made absolute, as the job runs elsewhere;
+/// options that cannot work unattended are an error.
+fn scheduled_options(matches: &ArgMatches, explicit: &HashSet<String>, current_dir: &Path) -> io::Result<Vec<String>> {
+    const PATH_OPTIONS: [&str; 4] = ["source", "output", "config", "log_file"];
+    // --run-log is written by the job itself (--log file --log-path)
+    const UNSCHEDULABLE: [&str; 4] = ["interactive", "undo", "resume", "run_log"];
+
+    let command = Args::command();
+    let given: Vec<_> = command
+        .get_arguments()
+        .filter(|arg| explicit.contains(arg.get_id().as_str()))
+        .collect();
+
+    let refused: Vec<String> = given
+        .iter()
+        .filter(|arg| UNSCHEDULABLE.contains(&arg.get_id().as_str()))
+        .filter_map(|arg| arg.get_long().map(|long| format!("--{}", long)))
+        .collect();
+    if !refused.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} cannot be used in a scheduled run", refused.join(", ")),
+        ));
+    }
+
+    let mut options = Vec::new();
+    for arg in given {
+        let id = arg.get_id().as_str();
+        let long = match arg.get_long() {
+            Some(long) => long,
+            None => continue,
+        };
+        if !arg.get_action().takes_values() {
+            options.push(format!("--{}", long));
+            continue;
+        }
+        for value in matches.get_raw(id).into_iter().flatten() {
+            let value = if PATH_OPTIONS.contains(&id) {
+                current_dir.join(value).display().to_string()
+            } else {
+                value.to_string_lossy().into_owned()
+            };
+            options.extend([format!("--{}", long), value]);
+        }
+    }
+    Ok(options)
+}
+
+/// Prints the `stats` report for the source directory. Only the report goes
+/// to stdout, so JSON and CSV output can be piped straight on.
+fn show_stats(args: &Args, stats_args: &StatsArgs) {
+    let ignored = if args.recursive {
+        match IgnoreList::load(&args.source) {
+            Ok(ignored) => ignored,
+            Err(e) => {
+                fatal(&format!("cannot read {}: {}", ignore::IGNORE_FILE_NAME, e));
+            }
+        }
+    } else {
+        IgnoreList::default()
+    };
+
+    let scanner = DirectoryScanner::new(CategoryMapper::new())
+        .with_recursive(args.recursive)
+        .with_symlinks(args.symlinks)
+        .with_one_file_system(args.one_file_system)
+        .with_skipped_fs_types(args.skip_fs_type.clone())
+        .with_ignored(ignored);
+    let mut files = match scanner.scan_iter(&args.source) {
+        Ok(files) => files,
+        Err(e) => {
+            fatal(&format!("cannot scan directory: {}", e));
+        }
+    };
+
+    let stats = Stats::collect(files.by_ref(), stats_args.top);
+    print!("{}", stats.render(stats_args.format));
+
+    for (dir, reason) in files.skipped_dirs() {
+        eprintln!("Skipped directory {}: {}", dir.display(), reason);
+    }
+}
+
+/// Prints the settings of the run before it starts.
+fn print_banner(
+    args: &Args,
+    output_dir: &Path,
+    name_template: Option<&Template>,
+    layouts: &[(Option<FileCategory>, Layout)],
+    rules: &[Rule],
+) {
+    logging::print("File Organizer");
+    logging::print("==============");
+    logging::print(&format!("Source directory: {}", args.source.display()));
+    logging::print(&format!("Output directory: {}", output_dir.display()));
+
+    if args.dry_run {
+        logging::print("Mode: Dry run (no files will be moved)");
+    }
+
+    if args.mode == TransferMode::Copy {
+        logging::print("Copying files (source is left untouched)");
+    }
+
+    if args.recursive {
+        logging::print("Recursive: including subdirectories");
+        if args.flatten {
+            logging::print("Flattening: all files go directly into their category folder");
+        }
+        if args.one_file_system {
+            logging::print("Staying on the source filesystem");
+        }
+        if !args.skip_fs_type.is_empty() {
+            logging::print(&format!("Skipping filesystem types: {}", args.skip_fs_type.join(", ")));
+        }
+    }
+
+    if let Some(max_entries) = args.max_per_folder {
+        logging::print(&format!("At most {} entries per folder, then shards", max_entries));
+    }
+
+    if args.verbose {
+        logging::print("Verbose mode: enabled");
+    }
+
+    if let Some(template) = name_template {
+        logging::print(&format!("Name template: {}", template.as_str()));
+    }
+
+    for (category, layout) in layouts {
+        match category {
+            Some(category) => logging::print(&format!("Layout for {}: {}", category.folder_name(), layout.as_str())),
+            None => logging::print(&format!("Layout: {}", layout.as_str())),
+        }
+    }
+
+    for rule in rules {
+        logging::print(&format!("Rule: {}", rule.as_str()));
+    }
+}
+
+/// Asks the user about the plan, then carries out what they accepted.
+fn review_and_organize(args: &Args, organizer: Organizer, result: &ScanResult, ignored: &IgnoreList) {
+    if !args.quiet {
+        logging::print("");
+    }
+    let stdin = io::stdin();
+    let review = match Session::new(stdin.lock(), io::stdout()).review(&organizer, result) {
+        Ok(Some(review)) => review,
+        Ok(None) => {
+            if !args.quiet {
+                logging::print("\nCancelled; nothing was moved.");
+            }
+            std::process::exit(exitcode::NOTHING_TO_DO);
+        }
+        Err(e) => {
+            fatal(&e.to_string());
+        }
+    };
+
+    if !args.dry_run {
+        install_stop_handlers();
+    }
+    if !args.quiet {
+        match args.mode {
+            TransferMode::Move => logging::print("\nMoving files..."),
+            TransferMode::Copy => logging::print("\nCopying files..."),
+        }
+    }
+    let organizer = start_journal(args, organizer);
+    let mut move_result = organizer.execute_plan(review.accepted);
+    move_result.add_left_out(review.skipped, review.failed);
+    prune_sources(args, &organizer, &move_result, ignored);
+
+    finish(args, &move_result);
+}
+
+/// Sets up the organizer from the command line. The progress log is
+/// opened later by [`start_journal`].
+fn build_organizer(
+    args: &Args,
+    output_dir: &Path,
+    name_template: Option<Template>,
+    layouts: Vec<(Option<FileCategory>, Layout)>,
+    rules: Vec<Rule>,
+) -> Organizer {
+    let sanitizer = NameSanitizer::new(&args.sanitize, args.case);
+    let mut organizer = Organizer::new(output_dir.to_path_buf(), args.dry_run, args.verbose)
+        .with_sanitizer(sanitizer)
+        .with_quiet(args.quiet)
+        .with_mode(args.mode)
+        .with_conflict_strategy(args.on_conflict)
+        .with_reflink(args.reflink)
+        .with_symlinks(args.symlinks)
+        .with_flatten(args.flatten)
+        .with_unknown_buckets(args.unknown_artist.clone(), args.unknown_album.clone());
+    if let Some(template) = name_template {
+        organizer = organizer.with_name_template(template);
+    }
+    for (category, layout) in layouts {
+        organizer = organizer.with_layout(category, layout);
+    }
+    organizer = organizer.with_rules(rules);
+    if let Some(max_entries) = args.max_per_folder {
+        organizer = organizer.with_max_per_folder(max_entries as usize, args.shard_by);
+    }
+    organizer
+}
+
+/// Opens a fresh or resumed progress log, and the flatten map, right
+/// before the first move, so a run that stops earlier leaves no
+/// unfinished journal behind to demand `--resume`. Dry runs get neither.
+fn start_journal(args: &Args, mut organizer: Organizer) -> Organizer {
+    if args.dry_run {
+        return organizer;
+    }
+    let output_dir = organizer.output_dir().to_path_buf();
+
+    let journal = if args.resume {
+        Journal::resume(&output_dir)
+    } else {
+        Journal::create(&output_dir)
+    };
+    // Copies leave their sources in place, so --undo has nothing to put back
+    let journal = journal.and_then(|journal| match args.mode {
+        TransferMode::Copy => journal.record_copy_mode().map(|_| journal),
+        TransferMode::Move => Ok(journal),
+    });
+    let journal = match journal {
+        Ok(journal) => journal,
+        Err(e) => {
+            fatal(&format!("cannot write progress log in {}: {}", output_dir.display(), e));
+        }
+    };
+    organizer = organizer
+        .with_journal(journal)
+        .with_stop_flag(&signals::STOP_REQUESTED);
+
+    if args.flatten {
+        match FlattenMap::open(&output_dir) {
+            Ok(map) => organizer = organizer.with_flatten_map(map),
+            Err(e) => {
+                fatal(&format!("cannot write {}: {}", flatten::FLATTEN_MAP_FILE_NAME, e));
+            }
+        }
+    }
+    organizer
+}
+
+/// With `--prune-empty`, removes the source folders the moves emptied.
+fn prune_sources(args: &Args, organizer: &Organizer, move_result: &MoveResult, ignored: &IgnoreList) {
+    if !args.prune_empty || args.dry_run || args.mode == TransferMode::Copy {
+        return;
+    }
+
+    let moved_from: Vec<&Path> = move_result.moved.iter().map(|operation| operation.source.as_path()).collect();
+    match prune::prune_empty(&args.source, &moved_from, ignored, organizer.journal()) {
+        Ok(removed) => {
+            if args.verbose {
+                for dir in &removed {
+                    logging::print(&format!("  Removed empty directory: {}", dir.display()));
+                }
+            }
+            if !removed.is_empty() && !args.quiet {
+                logging::print(&format!("\nRemoved {} empty directories", removed.len()));
+            }
+        }
+        Err(e) => eprintln!("Warning: {}", e),
+    }
+}
+
+/// Lists dangling links and directories the scan could not enter. With
+/// `--quiet` only the directories are reported, on standard error.
+fn print_scan_problems(args: &Args, dangling_links: &[PathBuf], skipped_dirs: &[(PathBuf, String)]) {
+    for link in dangling_links {
+        logging::warn("dangling symlink", &[("path", &link.display())]);
+    }
+    for (dir, reason) in skipped_dirs {
+        logging::warn("skipped directory", &[("path", &dir.display()), ("reason", reason)]);
+    }
+
+    if args.quiet {
+        for (dir, reason) in skipped_dirs {
+            eprintln!("Skipped directory {}: {}", dir.display(), reason);
+        }
+        return;
+    }
+
+    if !dangling_links.is_empty() {
+        logging::print(&format!("Dangling symlinks ({}):", dangling_links.len()));
+        for link in dangling_links {
+            logging::print(&format!("  - {}", link.display()));
+        }
+        logging::print("");
+    }
+
+    for (dir, reason) in skipped_dirs {
+        logging::print(&format!("Skipped directory {}: {}", dir.display(), reason));
+    }
+}
+
+/// Lets Ctrl-C and SIGTERM finish the current file instead of killing the
+/// process mid-move.
+fn install_stop_handlers() {
+    if let Err(e) = signals::install_stop_handlers() {
+        eprintln!("Warning: cannot install signal handlers: {}", e);
+    }
+}
+
+/// Reports an error that ends the run, on standard error and in the log.
+fn fatal(message: &str) -> ! {
+    logging::error(message, &[]);
+    eprintln!("Error: {}", message);
+    std::process::exit(exitcode::FATAL);
+}
+
+/// Prints the summary, or only the failures with `--quiet`, and ends the
+/// process with the status of the run so cron and systemd can tell a
+/// partial run from a complete one.
+fn finish(args: &Args, move_result: &MoveResult) -> ! {
+    if args.quiet {
+        move_result.print_errors();
+    } else {
+        move_result.print_summary(args.dry_run, args.mode);
+    }
+    let exit_code = move_result.exit_code();
+    logging::info(
+        "run finished",
+        &[
+            ("moved", &move_result.moved.len()),
+            ("skipped", &move_result.skipped.len()),
+            ("failed", &move_result.failed.len()),
+            ("interrupted", &move_result.interrupted),
+            ("exit_status", &exit_code),
+        ],
+    );
+    std::process::exit(exit_code);
+}
+
+/// Locks the source and output directories, creating the output directory
+/// so it can hold its lock file. An undo only locks the output directory,
+/// as its files go back to wherever the progress log says they came from.
+fn acquire_lock(args: &Args, output_dir: &Path) -> io::Result<RunLock> {
+    if args.undo {
+        if !output_dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("nothing to undo: {} does not exist", output_dir.display()),
+            ));
+        }
+        return RunLock::acquire(&[output_dir], args.wait);
+    }
+
+    let source = args.source.as_path();
+    if !source.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            format!("{} is not a directory", source.display()),
+        ));
+    }
+    std::fs::create_dir_all(output_dir)?;
+    RunLock::acquire(&[source, output_dir], args.wait)
+}
+
+/// Refuses to start over an interrupted run unless resuming, and with
+/// `resume` removes the partial copies that run left behind.
+fn prepare_run(output_dir: &Path, resume: bool, quiet: bool) -> io::Result<()> {
+    let state = journal::read_state(output_dir)?;
+
+    // Staged copies are never valid, whether or not the run is resumed
+    if let Some(state) = state.as_ref().filter(|state| state.is_interrupted()) {
+        for staged in journal::remove_staged_copies(state)? {
+            if !quiet {
+                logging::print(&format!("Removed unfinished copy: {}", staged.display()));
+            }
+        }
+    }
+
+    if !resume {
+        return match state {
+            Some(state) if state.is_interrupted() => Err(io::Error::other(format!(
+                "the previous run into {} was interrupted; use --resume to clean up and continue",
+                output_dir.display()
+            ))),
+            _ => Ok(()),
+        };
+    }
+
+    let state = match state {
+        Some(state) => state,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("nothing to resume: no progress log in {}", output_dir.display()),
+            ))
+        }
+    };
+
+    if !quiet {
+        logging::print(&format!("\nResuming: {} file(s) were moved by the previous run", state.done.len()));
+    }
+    if !state.is_interrupted() {
+        if !quiet {
+            logging::print("  The previous run had completed");
+        }
+        return Ok(());
+    }
+
+    let recovery = journal::recover(&state)?;
+    if quiet {
+        return Ok(());
+    }
+    for partial in &recovery.removed_partials {
+        logging::print(&format!("  Removed partial copy: {}", partial.display()));
+    }
+    for (_, destination) in &recovery.completed {
+        logging::print(&format!("  Already in place: {}", destination.display()));
+    }
+    for (source, _) in &recovery.missing {
+        logging::print(&format!("  Warning: {} is missing from both source and destination", source.display()));
+    }
+    Ok(())
+}
+
+fn parse_name_template(source: &str) -> Result<Template, String> {
+    let template = Template::parse(source)?;
+    template.validate(&PLACEHOLDERS)?;
+    Ok(template)
+}
+
+/// Parses `--layout` values of the form `[CATEGORY=]TEMPLATE`.
+fn parse_layout(spec: &str) -> Result<(Option<FileCategory>, Layout), String> {
+    let (category, source) = match spec.split_once('=') {
+        Some((name, source)) => match FileCategory::from_name(name.trim()) {
+            Some(category) => (Some(category), source),
+            // A bare word before `=` is meant as a category, e.g. a typo
+            None if !name.contains(['/', '\\', '{']) => {
+                let names: Vec<&str> = CategoryMapper::new()
+                    .all_categories()
+                    .iter()
+                    .map(|category| category.folder_name())
+                    .collect();
+                return Err(format!(
+                    "unknown category '{}' in layout '{}' (categories: {})",
+                    name.trim(),
+                    spec,
+                    names.join(", ")
+                ));
+            }
+            None => (None, spec),
+        },
+        None => (None, spec),
+    };
+
+    Ok((category, Layout::parse(source)?))
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Mount table of the current process (Linux).
+pub const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+/// One line of the mount table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+}
+
+/// Reads the mount table from `/proc/self/mountinfo`.
+pub fn read_mounts() -> io::Result<Vec<Mount>> {
+    let contents = fs::read_to_string(MOUNTINFO_PATH)
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot read {}: {}", MOUNTINFO_PATH, e)))?;
+    Ok(parse_mountinfo(&contents))
+}
+
+/// Parses mountinfo lines:
+///
+/// `36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw`
+///
+/// The fifth field is the mount point; the first field after the `-`
+/// separator is the filesystem type. Malformed lines are skipped.
+pub fn parse_mountinfo(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            let mount_point = fields.get(4)?;
+            let separator = fields.iter().skip(6).position(|field| *field == "-")? + 6;
+            let fs_type = fields.get(separator + 1)?;
+            Some(Mount {
+                mount_point: PathBuf::from(unescape(mount_point)),
+                fs_type: fs_type.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Type of the filesystem `path` lives on: the longest mount point that
+/// contains it. Later lines win ties, as they are mounted on top.
+pub fn fs_type_of<'a>(mounts: &'a [Mount], path: &Path) -> Option<&'a str> {
+    let mut best: Option<&Mount> = None;
+    for mount in mounts {
+        if path.starts_with(&mount.mount_point)
+            && best.is_none_or(|best| mount.mount_point.components().count() >= best.mount_point.components().count())
+        {
+            best = Some(mount);
+        }
+    }
+    best.map(|mount| mount.fs_type.as_str())
+}
+
+/// Whether `fs_type` is one of `patterns`. A pattern without a subtype
+/// covers all of them, so `fuse` matches `fuse.sshfs`.
+pub fn matches_fs_type(fs_type: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        fs_type == pattern || fs_type.strip_prefix(pattern.as_str()).is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// Undoes the octal escapes (`\040` for a space) of mountinfo paths.
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() && bytes[i + 1..i + 4].iter().all(|b| (b'0'..=b'7').contains(b)) {
+            let value = bytes[i + 1..i + 4].iter().fold(0u32, |acc, b| acc * 8 + (b - b'0') as u32);
+            out.push(value as u8);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+23 22 0:21 / /proc rw,nosuid shared:12 - proc proc rw
+40 22 0:35 / /home/me/nas rw,relatime - nfs4 server:/export rw,vers=4.2
+41 22 0:36 / /home/me/My\\040Server rw,nosuid,nodev - fuse.sshfs me@host:/ rw
+42 22 0:37 / /tmp rw - tmpfs tmpfs rw
+broken line
+";
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        assert_eq!(mounts.len(), 5);
+        assert_eq!(mounts[2].fs_type, "nfs4");
+        assert_eq!(mounts[3].mount_point, PathBuf::from("/home/me/My Server"));
+        assert_eq!(mounts[3].fs_type, "fuse.sshfs");
+    }
+
+    #[test]
+    fn test_fs_type_of_longest_mount() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        assert_eq!(fs_type_of(&mounts, Path::new("/home/me/Downloads")), Some("ext4"));
+        assert_eq!(fs_type_of(&mounts, Path::new("/home/me/nas/photos")), Some("nfs4"));
+        assert_eq!(fs_type_of(&mounts, Path::new("/home/me/My Server/x")), Some("fuse.sshfs"));
+        // Components, not string prefixes
+        assert_eq!(fs_type_of(&mounts, Path::new("/tmpfiles")), Some("ext4"));
+    }
+
+    #[test]
+    fn test_matches_fs_type() {
+        let patterns = vec!["fuse".to_string(), "tmpfs".to_string()];
+        assert!(matches_fs_type("fuse.sshfs", &patterns));
+        assert!(matches_fs_type("tmpfs", &patterns));
+        assert!(!matches_fs_type("fuseblk2", &patterns));
+        assert!(!matches_fs_type("ext4", &patterns));
+    }
+}
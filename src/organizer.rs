@@ -1,10 +1,76 @@
-use std::collections::HashSet;
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::io;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::categories::FileCategory;
-use crate::scanner::{FileInfo, ScanResult};
+use crate::copy::{CopyStats, ReflinkMode};
+use crate::datetime::DateTime;
+use crate::diskspace::{self, SpaceEstimate, SAFETY_MARGIN_BYTES};
+use crate::exitcode;
+use crate::flatten::{self, FlattenMap};
+use crate::journal::Journal;
+use crate::logging;
+use crate::rules::Rule;
+use crate::sanitize::{self, NameSanitizer, MAX_NAME_BYTES};
+use crate::staging;
+use crate::scanner::{FileInfo, ScanResult, SymlinkPolicy};
+use crate::shard::{ShardScheme, Sharder};
+use crate::template::{self, Layout, Template};
+
+/// How many counter values a name template may try before the file is skipped.
+const MAX_COUNTER_TRIES: usize = 10_000;
+
+/// What happens when a file's destination is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ConflictStrategy {
+    /// Leave the file where it is and report it as skipped
+    #[default]
+    Skip,
+    /// Move it under a numbered name: `photo (1).jpg`, `photo (2).jpg`, ...
+    Rename,
+}
+
+/// Whether files leave the source directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TransferMode {
+    /// Move files, renaming where possible
+    #[default]
+    Move,
+    /// Copy files and leave the source untouched
+    Copy,
+}
+
+/// What chose the folder layout of a file.
+#[derive(Debug, Clone, Copy)]
+enum Route {
+    /// The rule at this index
+    Rule(usize),
+    /// The layout for the file's category
+    CategoryLayout,
+    /// The layout for every category
+    DefaultLayout,
+    /// No layout; the file goes into its category folder
+    CategoryFolder,
+}
+
+/// Metadata and content hash of a file, each read at most once however
+/// many rules and templates ask for them.
+#[derive(Debug, Default)]
+struct FileFacts {
+    metadata: OnceCell<fs::Metadata>,
+    hash: OnceCell<String>,
+}
+
+/// The route and folder worked out for a file.
+#[derive(Debug)]
+struct Placement {
+    route: Route,
+    folder: PathBuf,
+    facts: FileFacts,
+}
 
 #[derive(Debug, Clone)]
 pub struct MoveOperation {
@@ -18,10 +84,21 @@ pub struct MoveResult {
     pub moved: Vec<MoveOperation>,
     pub skipped: Vec<(MoveOperation, String)>,
     pub failed: Vec<(MoveOperation, String)>,
+    /// The run was stopped by a signal before every file was processed
+    pub interrupted: bool,
+    /// Files left untouched because of the interruption
+    pub remaining: usize,
+    /// Bytes that had to be copied rather than renamed
+    pub copy_stats: CopyStats,
 }
 
 impl MoveResult {
-    pub fn print_summary(&self, dry_run: bool) {
+    pub fn print_summary(&self, dry_run: bool, mode: TransferMode) {
+        if logging::json_output() {
+            self.print_summary_records(dry_run);
+            return;
+        }
+
         println!("\n{}", "=".repeat(50));
         if dry_run {
             println!("DRY RUN SUMMARY");
@@ -30,10 +107,11 @@ impl MoveResult {
         }
         println!("{}", "=".repeat(50));
 
-        if dry_run {
-            println!("Files that would be moved: {}", self.moved.len());
-        } else {
-            println!("Files successfully moved:  {}", self.moved.len());
+        match (dry_run, mode) {
+            (true, TransferMode::Move) => println!("Files that would be moved: {}", self.moved.len()),
+            (true, TransferMode::Copy) => println!("Files that would be copied: {}", self.moved.len()),
+            (false, TransferMode::Move) => println!("Files successfully moved:  {}", self.moved.len()),
+            (false, TransferMode::Copy) => println!("Files successfully copied: {}", self.moved.len()),
         }
 
         if !self.skipped.is_empty() {
@@ -44,9 +122,25 @@ impl MoveResult {
             println!("Files failed:              {}", self.failed.len());
         }
 
+        if self.interrupted && self.remaining > 0 {
+            println!("Files not processed:       {}", self.remaining);
+        }
+
+        if self.copy_stats != CopyStats::default() {
+            println!("Bytes cloned (reflink):    {}", diskspace::format_bytes(self.copy_stats.cloned_bytes));
+            println!("Bytes physically copied:   {}", diskspace::format_bytes(self.copy_stats.copied_bytes));
+            if self.copy_stats.sparse_bytes > 0 {
+                println!("Holes preserved (sparse):  {}", diskspace::format_bytes(self.copy_stats.sparse_bytes));
+            }
+        }
+
         let total = self.moved.len() + self.skipped.len() + self.failed.len();
         println!("Total files processed:     {}", total);
 
+        if self.interrupted {
+            println!("\nRun interrupted. Run again with --resume to continue.");
+        }
+
         // Show skipped files details
         if !self.skipped.is_empty() {
             println!("\nSkipped files:");
@@ -65,12 +159,106 @@ impl MoveResult {
 
         println!("{}", "=".repeat(50));
     }
+
+    /// The summary as JSON records: the counts, then each skipped and
+    /// failed file.
+    fn print_summary_records(&self, dry_run: bool) {
+        logging::print_record(
+            logging::Level::Info,
+            "summary",
+            &[
+                ("dry_run", &dry_run),
+                ("moved", &self.moved.len()),
+                ("skipped", &self.skipped.len()),
+                ("failed", &self.failed.len()),
+                ("remaining", &self.remaining),
+                ("interrupted", &self.interrupted),
+                ("cloned_bytes", &self.copy_stats.cloned_bytes),
+                ("copied_bytes", &self.copy_stats.copied_bytes),
+                ("sparse_bytes", &self.copy_stats.sparse_bytes),
+            ],
+        );
+        for (op, reason) in &self.skipped {
+            logging::print_record(logging::Level::Warn, "skipped", &[("source", &op.source.display()), ("reason", reason)]);
+        }
+        for (op, reason) in &self.failed {
+            logging::print_record(logging::Level::Error, "failed", &[("source", &op.source.display()), ("reason", reason)]);
+        }
+    }
+
+    /// What `--quiet` keeps of the summary: the failed files and whether the
+    /// run was cut short, on standard error.
+    pub fn print_errors(&self) {
+        for (op, reason) in &self.failed {
+            eprintln!("Failed: {} - {}", op.source.display(), reason);
+        }
+        if self.interrupted {
+            eprintln!("Run interrupted. Run again with --resume to continue.");
+        }
+    }
+
+    /// Adds files that were left out of the plan before anything was moved,
+    /// such as those skipped during a review, and logs them.
+    pub fn add_left_out(&mut self, skipped: Vec<(MoveOperation, String)>, failed: Vec<(MoveOperation, String)>) {
+        for (operation, reason) in &skipped {
+            log_skipped(operation, reason);
+        }
+        for (operation, reason) in &failed {
+            log_failed(operation, reason);
+        }
+        self.skipped.extend(skipped);
+        self.failed.extend(failed);
+    }
+
+    /// Exit status of the run, see [`exitcode`].
+    pub fn exit_code(&self) -> i32 {
+        if self.interrupted {
+            return exitcode::FAILED;
+        }
+        exitcode::outcome(self.moved.len(), self.skipped.len(), self.failed.len())
+    }
 }
 
 pub struct Organizer {
     output_dir: PathBuf,
     dry_run: bool,
     verbose: bool,
+    /// No progress lines, for `--quiet`
+    quiet: bool,
+    sanitizer: NameSanitizer,
+    name_template: Option<Template>,
+    /// Folder layouts for specific categories
+    layouts: HashMap<FileCategory, Layout>,
+    /// Folder layout for every other category
+    default_layout: Option<Layout>,
+    /// Rules tried in order before the category layouts
+    rules: Vec<Rule>,
+    unknown_artist: String,
+    unknown_album: String,
+    /// Next `{counter}` value per destination folder
+    counters: RefCell<HashMap<PathBuf, u64>>,
+    /// Destinations already handed out during this run
+    planned: RefCell<HashSet<PathBuf>>,
+    /// Placements worked out for the folders to create, kept until the
+    /// file is moved
+    placements: RefCell<HashMap<PathBuf, Placement>>,
+    /// Folders created before the journal was opened, waiting to be
+    /// recorded in it
+    created_dirs: RefCell<Vec<PathBuf>>,
+    /// Progress log of the moves, for resuming after an interruption
+    journal: Option<Journal>,
+    /// Checked before each file; once set the run stops
+    stop_flag: Option<&'static AtomicBool>,
+    mode: TransferMode,
+    on_conflict: ConflictStrategy,
+    reflink: ReflinkMode,
+    symlinks: SymlinkPolicy,
+    /// Collapse the source folders of a recursive scan into the category
+    flatten: bool,
+    /// Where flattened files came from; written only when moving for real
+    flatten_map: Option<FlattenMap>,
+    /// Splits folders that would hold too many entries
+    sharder: Option<RefCell<Sharder>>,
 }
 
 impl Organizer {
@@ -79,32 +267,226 @@ impl Organizer {
             output_dir,
             dry_run,
             verbose,
+            quiet: false,
+            sanitizer: NameSanitizer::default(),
+            name_template: None,
+            layouts: HashMap::new(),
+            default_layout: None,
+            rules: Vec::new(),
+            unknown_artist: "Unknown Artist".to_string(),
+            unknown_album: "Unknown Album".to_string(),
+            counters: RefCell::new(HashMap::new()),
+            planned: RefCell::new(HashSet::new()),
+            placements: RefCell::new(HashMap::new()),
+            created_dirs: RefCell::new(Vec::new()),
+            journal: None,
+            stop_flag: None,
+            mode: TransferMode::Move,
+            on_conflict: ConflictStrategy::Skip,
+            reflink: ReflinkMode::Auto,
+            symlinks: SymlinkPolicy::MoveLink,
+            flatten: false,
+            flatten_map: None,
+            sharder: None,
+        }
+    }
+
+    /// Renders destination file names from a template such as
+    /// `{date:%Y-%m-%d}_{stem}{ext}`.
+    pub fn with_name_template(mut self, template: Template) -> Self {
+        self.name_template = Some(template);
+        self
+    }
+
+    /// Leaves out progress and dry-run listings; failures are still
+    /// recorded in the result.
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Renames files on the way to their category folder.
+    pub fn with_sanitizer(mut self, sanitizer: NameSanitizer) -> Self {
+        self.sanitizer = sanitizer;
+        self
+    }
+
+    /// Renders the destination from a layout such as
+    /// `Images/{exif.year}/{exif.month}`, relative to the output directory.
+    /// Without a category the layout applies to every category that has no
+    /// layout of its own.
+    pub fn with_layout(mut self, category: Option<FileCategory>, layout: Layout) -> Self {
+        match category {
+            Some(category) => {
+                self.layouts.insert(category, layout);
+            }
+            None => self.default_layout = Some(layout),
+        }
+        self
+    }
+
+    /// Folder names used for music without artist or album tags.
+    pub fn with_unknown_buckets(mut self, artist: String, album: String) -> Self {
+        self.unknown_artist = artist;
+        self.unknown_album = album;
+        self
+    }
+
+    /// Routes matching files to the rule's layout. The first matching rule
+    /// wins over any category layout.
+    pub fn with_rules(mut self, rules: Vec<Rule>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Records every move in `journal` so an interrupted run can be resumed.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        let created = self.created_dirs.take();
+        self.record_created(created);
+        self
+    }
+
+    /// Stops after the current file once `flag` is set, e.g. by a signal.
+    pub fn with_stop_flag(mut self, flag: &'static AtomicBool) -> Self {
+        self.stop_flag = Some(flag);
+        self
+    }
+
+    /// Copies instead of moving, leaving the source directory as it is.
+    pub fn with_mode(mut self, mode: TransferMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_conflict_strategy(mut self, on_conflict: ConflictStrategy) -> Self {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    /// Whether copies may be reflinks sharing data with their source.
+    pub fn with_reflink(mut self, reflink: ReflinkMode) -> Self {
+        self.reflink = reflink;
+        self
+    }
+
+    /// How symlinks found by the scanner are transferred: recreated as
+    /// links (`MoveLink`) or replaced by a copy of their target (`Follow`).
+    pub fn with_symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Puts files from any depth straight into their category folder instead
+    /// of recreating the source folders. A name that is taken gets the
+    /// source folders prefixed, as in `a__b__file.txt`.
+    pub fn with_flatten(mut self, flatten: bool) -> Self {
+        self.flatten = flatten;
+        self
+    }
+
+    /// Records where each moved file came from, so a flattened tree can be
+    /// rebuilt.
+    pub fn with_flatten_map(mut self, map: FlattenMap) -> Self {
+        self.flatten_map = Some(map);
+        self
+    }
+
+    /// Caps every destination folder at `max_entries` entries; further files
+    /// go into shard subfolders named by `scheme`.
+    pub fn with_max_per_folder(mut self, max_entries: usize, scheme: ShardScheme) -> Self {
+        self.sharder = Some(RefCell::new(Sharder::new(max_entries, scheme)));
+        self
+    }
+
+    /// The route and folder of `file`. One already worked out for
+    /// [`create_category_directories`](Self::create_category_directories)
+    /// is taken rather than read again.
+    fn place(&self, file: &FileInfo) -> Result<Placement, String> {
+        if let Some(placement) = self.placements.borrow_mut().remove(&file.path) {
+            return Ok(placement);
+        }
+        let facts = FileFacts::default();
+        let route = self.route(file, &facts)?;
+        let folder = self.render_folder(file, self.route_layout(route, file), &facts)?;
+        Ok(Placement { route, folder, facts })
+    }
+
+    /// Which layout applies to `file`: the first matching rule, the
+    /// category layout, the default layout or just the category folder.
+    fn route(&self, file: &FileInfo, facts: &FileFacts) -> Result<Route, String> {
+        for (index, rule) in self.rules.iter().enumerate() {
+            // Other files would only ever offer the fallback, e.g. "Unknown Author"
+            if rule.field.starts_with("doc.") && file.category != FileCategory::Documents {
+                continue;
+            }
+            let value = self.resolve_placeholder(file, facts, 0, &rule.field, None)?;
+            if rule.matches(&value) {
+                return Ok(Route::Rule(index));
+            }
+            logging::trace(
+                "rule did not match",
+                &[("file", &file.path.display()), ("rule", &rule.as_str()), ("value", &value)],
+            );
+        }
+        if self.layouts.contains_key(&file.category) {
+            return Ok(Route::CategoryLayout);
+        }
+        match &self.default_layout {
+            Some(_) => Ok(Route::DefaultLayout),
+            None => Ok(Route::CategoryFolder),
+        }
+    }
+
+    fn route_layout(&self, route: Route, file: &FileInfo) -> Option<&Layout> {
+        match route {
+            Route::Rule(index) => Some(&self.rules[index].layout),
+            Route::CategoryLayout => self.layouts.get(&file.category),
+            Route::DefaultLayout => self.default_layout.as_ref(),
+            Route::CategoryFolder => None,
+        }
+    }
+
+    fn describe_route(&self, route: Route, file: &FileInfo) -> String {
+        match route {
+            Route::Rule(index) => format!("rule '{}'", self.rules[index].as_str()),
+            Route::CategoryLayout => format!("layout for {}", file.category.folder_name()),
+            Route::DefaultLayout => "default layout".to_string(),
+            Route::CategoryFolder => "category folder".to_string(),
         }
     }
 
     pub fn create_category_directories(&self, scan_result: &ScanResult) -> io::Result<Vec<PathBuf>> {
-        let categories_needed: HashSet<&FileCategory> = scan_result
-            .categorized
-            .keys()
-            .collect();
+        let mut folders_needed = BTreeSet::new();
+        for file in &scan_result.files {
+            // Files whose folder cannot be rendered are reported when moving
+            if let Ok(placement) = self.place(file) {
+                folders_needed.insert(placement.folder.clone());
+                self.placements.borrow_mut().insert(file.path.clone(), placement);
+            }
+        }
 
         let mut created_dirs = Vec::new();
 
-        for category in categories_needed {
-            let category_path = self.output_dir.join(category.folder_name());
+        for category_path in folders_needed {
 
             if category_path.exists() {
                 if self.verbose {
-                    println!("  Directory already exists: {}", category_path.display());
+                    logging::print(&format!("  Directory already exists: {}", category_path.display()));
                 }
             } else if self.dry_run {
-                println!("  [DRY RUN] Would create: {}", category_path.display());
+                if !self.quiet {
+                    logging::print(&format!("  [DRY RUN] Would create: {}", category_path.display()));
+                }
                 created_dirs.push(category_path);
             } else {
+                let missing = missing_dirs(&category_path);
                 fs::create_dir_all(&category_path)?;
+                self.record_created(missing);
                 if self.verbose {
-                    println!("  Created directory: {}", category_path.display());
+                    logging::print(&format!("  Created directory: {}", category_path.display()));
                 }
+                logging::debug("created directory", &[("path", &category_path.display())]);
                 created_dirs.push(category_path);
             }
         }
@@ -116,328 +498,1401 @@ impl Organizer {
         self.output_dir.join(category.folder_name()).join(file_name)
     }
 
+    /// `Category` or `Category/Subcategory` under the output directory,
+    /// followed by the folder the file was found in by a recursive scan.
+    fn category_folder(&self, file: &FileInfo) -> PathBuf {
+        let mut folder = self.output_dir.join(file.category.folder_name());
+        if let Some(subcategory) = file.subcategory {
+            folder.push(subcategory);
+        }
+        if self.flatten {
+            return folder;
+        }
+        folder.join(&file.relative_dir)
+    }
+
     pub fn output_dir(&self) -> &Path {
         &self.output_dir
     }
 
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Sums the bytes that will have to be copied because their source lives
+    /// on a different filesystem than the output directory.
+    pub fn estimate_space(&self, scan_result: &ScanResult) -> io::Result<SpaceEstimate> {
+        let destination_device = diskspace::device_id(&self.output_dir)?;
+        let mut estimate = SpaceEstimate {
+            available_bytes: diskspace::available_space(&self.output_dir)?,
+            margin_bytes: SAFETY_MARGIN_BYTES,
+            ..SpaceEstimate::default()
+        };
+
+        for file in &scan_result.files {
+            // A recreated link takes no space
+            if file.is_symlink && self.symlinks == SymlinkPolicy::MoveLink {
+                continue;
+            }
+
+            let metadata = match fs::metadata(&file.path) {
+                Ok(metadata) => metadata,
+                // Unreadable files are reported by the move itself
+                Err(_) => continue,
+            };
+
+            // Without device ids every move is assumed to be a rename.
+            // Copies may turn out to be reflinks, but cannot count on it.
+            let cross_device = destination_device.is_some()
+                && diskspace::metadata_device(&metadata) != destination_device;
+            let followed = file.is_symlink && self.symlinks == SymlinkPolicy::Follow;
+            if cross_device || followed || self.mode == TransferMode::Copy {
+                estimate.cross_device_files += 1;
+                estimate.cross_device_bytes += diskspace::allocated_size(&metadata);
+            }
+        }
+
+        Ok(estimate)
+    }
+
+    /// Fails before anything is moved if the cross-device copies would not fit.
+    pub fn check_space(&self, estimate: &SpaceEstimate) -> io::Result<()> {
+        if estimate.is_sufficient() {
+            return Ok(());
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::StorageFull,
+            format!(
+                "not enough free space in '{}': need {} ({} to copy plus {} safety margin), only {} available",
+                self.output_dir.display(),
+                diskspace::format_bytes(estimate.required_bytes()),
+                diskspace::format_bytes(estimate.cross_device_bytes),
+                diskspace::format_bytes(estimate.margin_bytes),
+                diskspace::format_bytes(estimate.available_bytes)
+            ),
+        ))
+    }
+
     pub fn move_files(&self, scan_result: &ScanResult) -> MoveResult {
         let mut result = MoveResult::default();
         let total_files = scan_result.files.len();
 
         for (index, file) in scan_result.files.iter().enumerate() {
+            if self.stop_requested() {
+                if !self.quiet {
+                    logging::print(&format!("  Stopping: {} file(s) left for the next run", total_files - index));
+                }
+                result.interrupted = true;
+                result.remaining = total_files - index;
+                break;
+            }
+
             let progress = index + 1;
-            let operation = self.create_move_operation(file);
+            self.organize_file(file, &format!("{}/{}", progress, total_files), false, &mut result);
 
-            if let Err(reason) = self.should_move(&operation) {
-                if self.verbose {
-                    println!("  [{}/{}] Skipping {}: {}", progress, total_files, file.name, reason);
-                }
-                result.skipped.push((operation, reason));
-                continue;
+            // Show progress every 10 files in non-verbose mode, or on last file
+            if !self.verbose && !self.quiet && (progress % 10 == 0 || progress == total_files) {
+                logging::print(&format!("  Processed {}/{} files...", progress, total_files));
             }
+        }
 
-            if self.dry_run {
-                if self.verbose {
-                    println!(
-                        "  [{}/{}] Would move: {} -> {}",
-                        progress,
-                        total_files,
-                        file.name,
-                        operation.destination.parent().unwrap().file_name().unwrap().to_string_lossy()
-                    );
-                }
-                result.moved.push(operation);
-            } else {
-                match self.execute_move(&operation) {
-                    Ok(()) => {
-                        if self.verbose {
-                            println!(
-                                "  [{}/{}] Moved: {} -> {}",
-                                progress,
-                                total_files,
-                                operation.file_name,
-                                operation.destination.parent().unwrap().file_name().unwrap().to_string_lossy()
-                            );
-                        }
-                        result.moved.push(operation);
-                    }
-                    Err(e) => {
-                        let reason = e.to_string();
-                        if self.verbose {
-                            println!("  [{}/{}] Failed to move {}: {}", progress, total_files, file.name, reason);
-                        }
-                        result.failed.push((operation, reason));
-                    }
+        self.finish_journal(&result);
+        result
+    }
+
+    /// Organizes files as a scan yields them, without collecting the scan
+    /// first. Folders are created when the first file needs them; there is
+    /// no total, so progress counts up.
+    pub fn organize_stream<I: IntoIterator<Item = FileInfo>>(&self, files: I) -> MoveResult {
+        let mut result = MoveResult::default();
+        let mut processed = 0;
+
+        for file in files {
+            if self.stop_requested() {
+                if !self.quiet {
+                    logging::print("  Stopping: the remaining files are left for the next run");
                 }
+                result.interrupted = true;
+                break;
             }
 
-            // Show progress every 10 files in non-verbose mode, or on last file
-            if !self.verbose && (progress % 10 == 0 || progress == total_files) {
-                println!("  Processed {}/{} files...", progress, total_files);
+            processed += 1;
+            self.organize_file(&file, &processed.to_string(), true, &mut result);
+
+            if !self.verbose && !self.quiet && processed % 10 == 0 {
+                logging::print(&format!("  Processed {} files...", processed));
             }
         }
 
+        if !self.verbose && !self.quiet && processed % 10 != 0 {
+            logging::print(&format!("  Processed {} files...", processed));
+        }
+
+        self.finish_journal(&result);
         result
     }
 
-    fn create_move_operation(&self, file: &FileInfo) -> MoveOperation {
-        let destination = self.get_target_path(&file.category, &file.name);
-        MoveOperation {
-            source: file.path.clone(),
-            destination,
-            file_name: file.name.clone(),
-        }
+    /// Where `file` would go, without moving anything. The destination stays
+    /// reserved for it until [`release`](Self::release)d.
+    pub fn plan(&self, file: &FileInfo) -> Result<MoveOperation, String> {
+        self.create_move_operation(file)
     }
 
-    fn should_move(&self, operation: &MoveOperation) -> Result<(), String> {
-        // Skip if source and destination are the same
-        if operation.source == operation.destination {
-            return Err("source and destination are the same".to_string());
-        }
+    /// Frees the destination of a planned operation that is dropped.
+    pub fn release(&self, operation: &MoveOperation) {
+        self.planned.borrow_mut().remove(&operation.destination);
+    }
 
-        // Skip if destination file already exists
-        if operation.destination.exists() {
-            return Err("destination file already exists".to_string());
-        }
+    /// Takes back the destination of an operation that was
+    /// [`release`](Self::release)d to plan another one that failed.
+    pub fn reserve(&self, operation: &MoveOperation) {
+        self.planned.borrow_mut().insert(operation.destination.clone());
+    }
 
-        // Skip detailed permission checks in dry-run mode since directories may not exist yet
-        if self.dry_run {
-            return Ok(());
+    /// Gives a planned operation another file name in the same folder.
+    pub fn rename_planned(&self, operation: &mut MoveOperation, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+            return Err(format!("invalid file name '{}'", name));
         }
 
-        // Check if source file is readable
-        if let Err(e) = self.check_source_readable(&operation.source) {
-            return Err(e);
+        let destination = operation.destination.with_file_name(name);
+        if destination != operation.destination && self.is_taken(&destination) {
+            return Err(format!("'{}' is already taken", name));
         }
+        self.release(operation);
+        self.planned.borrow_mut().insert(destination.clone());
+        operation.destination = destination;
+        Ok(())
+    }
 
-        // Check if destination directory is writable
-        if let Err(e) = self.check_destination_writable(&operation.destination) {
-            return Err(e);
+    /// Carries out operations from [`plan`](Self::plan), possibly edited
+    /// since. Destination folders are created as needed.
+    pub fn execute_plan(&self, operations: Vec<MoveOperation>) -> MoveResult {
+        let no_progress = self.verbose || self.quiet;
+        self.execute_plan_with_progress(operations, |progress, total_files, _| {
+            if !no_progress && (progress % 10 == 0 || progress == total_files) {
+                logging::print(&format!("  Processed {}/{} files...", progress, total_files));
+            }
+        })
+    }
+
+    /// Like [`execute_plan`](Self::execute_plan), but reports to
+    /// `on_progress` after each file instead of printing progress lines. It
+    /// gets the files done so far, the total and the result up to now.
+    pub fn execute_plan_with_progress<F>(&self, operations: Vec<MoveOperation>, mut on_progress: F) -> MoveResult
+    where
+        F: FnMut(usize, usize, &MoveResult),
+    {
+        let mut result = MoveResult::default();
+        let total_files = operations.len();
+
+        for (index, operation) in operations.into_iter().enumerate() {
+            if self.stop_requested() {
+                if !self.quiet {
+                    logging::print(&format!("  Stopping: {} file(s) left for the next run", total_files - index));
+                }
+                result.interrupted = true;
+                result.remaining = total_files - index;
+                break;
+            }
+
+            let progress = index + 1;
+            self.execute_operation(operation, &format!("{}/{}", progress, total_files), true, &mut result);
+            on_progress(progress, total_files, &result);
         }
 
-        Ok(())
+        self.finish_journal(&result);
+        result
     }
 
-    fn check_source_readable(&self, source: &Path) -> Result<(), String> {
-        // Try to open the file for reading to verify access
-        match fs::File::open(source) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(self.format_io_error("cannot read source file", &e)),
-        }
+    fn stop_requested(&self) -> bool {
+        self.stop_flag.is_some_and(|flag| flag.load(Ordering::SeqCst))
     }
 
-    fn check_destination_writable(&self, destination: &Path) -> Result<(), String> {
-        if let Some(parent) = destination.parent() {
-            if parent.exists() {
-                // Check if we can write to the directory by checking metadata
-                match fs::metadata(parent) {
-                    Ok(metadata) => {
-                        if metadata.permissions().readonly() {
-                            return Err("destination directory is read-only".to_string());
-                        }
-                        Ok(())
-                    }
-                    Err(e) => Err(self.format_io_error("cannot access destination directory", &e)),
-                }
-            } else {
-                Err("destination directory does not exist".to_string())
+    /// Notes folders the run created in the journal, so `--undo` removes
+    /// those and no others. Until the journal is open they are kept here.
+    fn record_created(&self, dirs: Vec<PathBuf>) {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => {
+                self.created_dirs.borrow_mut().extend(dirs);
+                return;
+            }
+        };
+        for dir in dirs {
+            if let Err(e) = journal.record_mkdir(&dir) {
+                eprintln!("Warning: cannot write {}: {}", journal.path().display(), e);
+                return;
             }
-        } else {
-            Err("invalid destination path".to_string())
         }
     }
 
-    fn execute_move(&self, operation: &MoveOperation) -> io::Result<()> {
-        // Try rename first (fastest, works on same filesystem)
-        match fs::rename(&operation.source, &operation.destination) {
-            Ok(()) => Ok(()),
-            Err(e) => {
-                // Handle cross-device link error by copying and deleting
-                if e.raw_os_error() == Some(libc::EXDEV) {
-                    self.copy_and_delete(operation)
-                } else {
-                    Err(self.enhance_io_error(e, operation))
-                }
+    fn finish_journal(&self, result: &MoveResult) {
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.finish(result.interrupted) {
+                eprintln!("Warning: cannot write {}: {}", journal.path().display(), e);
             }
         }
     }
 
-    fn copy_and_delete(&self, operation: &MoveOperation) -> io::Result<()> {
-        // Copy the file first
-        fs::copy(&operation.source, &operation.destination)?;
+    /// Names, checks and moves one file, recording the outcome in `result`.
+    /// With `create_folders` the destination folder is created first.
+    fn organize_file(&self, file: &FileInfo, progress: &str, create_folders: bool, result: &mut MoveResult) {
+        let operation = match self.create_move_operation(file) {
+            Ok(operation) => operation,
+            Err(reason) => {
+                if self.verbose {
+                    logging::print(&format!("  [{}] Failed to name {}: {}", progress, file.name, reason));
+                }
+                logging::error("cannot name file", &[("file", &file.path.display()), ("reason", &reason)]);
+                let operation = MoveOperation {
+                    source: file.path.clone(),
+                    destination: self.get_target_path(&file.category, &file.name),
+                    file_name: file.name.clone(),
+                };
+                result.failed.push((operation, reason));
+                return;
+            }
+        };
 
-        // Then delete the source
-        fs::remove_file(&operation.source).map_err(|e| {
-            // If we can't delete the source, try to clean up the destination
-            let _ = fs::remove_file(&operation.destination);
-            io::Error::new(
-                e.kind(),
-                format!("copied file but failed to remove source: {}", e),
-            )
-        })
+        self.execute_operation(operation, progress, create_folders, result);
     }
 
-    fn enhance_io_error(&self, error: io::Error, operation: &MoveOperation) -> io::Error {
-        let message = match error.kind() {
-            io::ErrorKind::PermissionDenied => {
-                format!(
-                    "permission denied moving '{}' to '{}'",
-                    operation.source.display(),
-                    operation.destination.display()
-                )
-            }
-            io::ErrorKind::NotFound => {
-                format!("source file '{}' not found", operation.source.display())
+    /// Checks and carries out one planned move, recording the outcome.
+    fn execute_operation(&self, operation: MoveOperation, progress: &str, create_folders: bool, result: &mut MoveResult) {
+        // Shard folders are only known once the file is assigned to one
+        if (create_folders || self.sharder.is_some()) && !self.dry_run {
+            if let Err(e) = self.create_folder(&operation) {
+                let reason = self.format_io_error("cannot create destination directory", &e);
+                if self.verbose {
+                    logging::print(&format!("  [{}] Failed to {} {}: {}", progress, self.verb(), operation.file_name, reason));
+                }
+                log_failed(&operation, &reason);
+                result.failed.push((operation, reason));
+                return;
             }
-            io::ErrorKind::AlreadyExists => {
-                format!(
-                    "destination '{}' already exists",
-                    operation.destination.display()
-                )
+        }
+
+        if let Err(reason) = self.should_move(&operation) {
+            if self.verbose {
+                logging::print(&format!("  [{}] Skipping {}: {}", progress, operation.file_name, reason));
             }
-            _ => {
-                format!(
-                    "failed to move '{}': {}",
+            log_skipped(&operation, &reason);
+            result.skipped.push((operation, reason));
+            return;
+        }
+
+        if self.dry_run {
+            // Renamed files are always listed so templates can be checked
+            if self.verbose || (self.is_renamed(&operation) && !self.quiet) {
+                logging::print(&format!(
+                    "  [{}] Would {}: {} -> {}",
+                    progress,
+                    self.verb(),
                     operation.file_name,
-                    error
-                )
+                    self.destination_label(&operation)
+                ));
             }
-        };
-        io::Error::new(error.kind(), message)
-    }
+            logging::info(
+                if self.mode == TransferMode::Copy { "would copy" } else { "would move" },
+                &[("source", &operation.source.display()), ("destination", &operation.destination.display())],
+            );
+            result.moved.push(operation);
+            return;
+        }
+
+        match self.execute_journaled_move(&operation) {
+            Ok(stats) => {
+                if self.verbose {
+                    logging::print(&format!(
+                        "  [{}] {}: {} -> {}",
+                        progress,
+                        if self.mode == TransferMode::Copy { "Copied" } else { "Moved" },
+                        operation.file_name,
+                        self.destination_label(&operation)
+                    ));
+                }
+                if let Some(map) = &self.flatten_map {
+                    if let Err(e) = map.record(&operation.destination, &operation.source) {
+                        eprintln!("Warning: cannot write {}: {}", map.path().display(), e);
+                    }
+                }
+                logging::info(
+                    if self.mode == TransferMode::Copy { "copied" } else { "moved" },
+                    &[("source", &operation.source.display()), ("destination", &operation.destination.display())],
+                );
+                result.copy_stats.add(stats);
+                result.moved.push(operation);
+            }
+            Err(e) => {
+                let reason = e.to_string();
+                if self.verbose {
+                    logging::print(&format!("  [{}] Failed to {} {}: {}", progress, self.verb(), operation.file_name, reason));
+                }
+                log_failed(&operation, &reason);
+                result.failed.push((operation, reason));
+            }
+        }
+    }
+
+    fn create_folder(&self, operation: &MoveOperation) -> io::Result<()> {
+        let folder = match operation.destination.parent() {
+            Some(folder) if !folder.exists() => folder,
+            _ => return Ok(()),
+        };
+        let missing = missing_dirs(folder);
+        fs::create_dir_all(folder)?;
+        self.record_created(missing);
+        if self.verbose {
+            logging::print(&format!("  Created directory: {}", folder.display()));
+        }
+        logging::debug("created directory", &[("path", &folder.display())]);
+        Ok(())
+    }
+
+    /// Moves the file between a START and a DONE journal record. A file is
+    /// not moved at all if its START cannot be written.
+    fn execute_journaled_move(&self, operation: &MoveOperation) -> io::Result<CopyStats> {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return self.execute_move(operation),
+        };
+
+        journal
+            .record_start(&operation.source, &operation.destination)
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot write progress log: {}", e)))?;
+        let stats = self.execute_move(operation)?;
+        journal
+            .record_done(&operation.source, &operation.destination)
+            .map_err(|e| io::Error::new(e.kind(), format!("moved, but cannot write progress log: {}", e)))?;
+        Ok(stats)
+    }
+
+    fn verb(&self) -> &'static str {
+        match self.mode {
+            TransferMode::Move => "move",
+            TransferMode::Copy => "copy",
+        }
+    }
+
+    fn create_move_operation(&self, file: &FileInfo) -> Result<MoveOperation, String> {
+        let Placement { route, mut folder, facts } = self.place(file)?;
+        let layout = self.route_layout(route, file);
+        let name_template = layout
+            .and_then(|layout| layout.file_name.as_ref())
+            .or(self.name_template.as_ref());
+        let uses_counter = name_template.is_some_and(|template| template.uses("counter"));
+        let mut target_name = match name_template {
+            Some(template) if !uses_counter => self.render_file_name(template, file, &folder, &facts)?,
+            _ => self.sanitize_name(&file.name),
+        };
+        if let Some(sharder) = &self.sharder {
+            folder = sharder.borrow_mut().assign(&folder, &target_name);
+        }
+        // Counters count per folder, so they wait until the shard is known
+        if let (true, Some(template)) = (uses_counter, name_template) {
+            target_name = self.render_file_name(template, file, &folder, &facts)?;
+        }
+
+        // Flattened files from different folders may share a name
+        if self.flatten && !file.relative_dir.as_os_str().is_empty() && self.is_taken(&folder.join(&target_name)) {
+            target_name = self.sanitize_name(&flatten::encode_name(&file.relative_dir, &target_name));
+        }
+
+        let mut destination = folder.join(target_name);
+        if self.on_conflict == ConflictStrategy::Rename && destination != file.path && self.is_taken(&destination) {
+            let renamed = self.free_numbered_name(&destination);
+            logging::info(
+                "destination taken, renamed",
+                &[("file", &file.path.display()), ("taken", &destination.display()), ("destination", &renamed.display())],
+            );
+            destination = renamed;
+        }
+        self.planned.borrow_mut().insert(destination.clone());
+        logging::debug(
+            "destination chosen",
+            &[
+                ("file", &file.path.display()),
+                ("category", &file.category.folder_name()),
+                ("route", &self.describe_route(route, file)),
+                ("destination", &destination.display()),
+            ],
+        );
+
+        Ok(MoveOperation {
+            source: file.path.clone(),
+            destination,
+            file_name: file.name.clone(),
+        })
+    }
+
+    fn is_taken(&self, destination: &Path) -> bool {
+        fs::symlink_metadata(destination).is_ok() || self.planned.borrow().contains(destination)
+    }
+
+    /// `photo (1).jpg`, `photo (2).jpg`, ... next to `destination`, whichever
+    /// is free first.
+    fn free_numbered_name(&self, destination: &Path) -> PathBuf {
+        let name = destination.file_name().unwrap_or_default().to_string_lossy();
+        let (stem, extension) = match name.rfind('.') {
+            Some(dot) if dot > 0 => (&name[..dot], &name[dot..]),
+            _ => (&name[..], ""),
+        };
+        (1u64..)
+            .map(|number| destination.with_file_name(format!("{} ({}){}", stem, number, extension)))
+            .find(|candidate| !self.is_taken(candidate))
+            .expect("some numbered name is free")
+    }
+
+    fn sanitize_name(&self, name: &str) -> String {
+        if !self.sanitizer.is_enabled() {
+            return name.to_string();
+        }
+        let extension = Path::new(name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string());
+        self.sanitizer.apply(name, extension.as_deref())
+    }
+
+    /// Folder the file belongs in, from its category or layout template.
+    fn render_folder(&self, file: &FileInfo, layout: Option<&Layout>, facts: &FileFacts) -> Result<PathBuf, String> {
+        let layout = match layout {
+            Some(layout) => &layout.folder,
+            None => return Ok(self.category_folder(file)),
+        };
+
+        // Values must not introduce path separators of their own
+        let rendered = layout.render(|name, spec| {
+            self.resolve_placeholder(file, facts, 0, name, spec)
+                .map(|value| value.replace(['/', '\\'], "_"))
+        })?;
+
+        let mut folder = self.output_dir.clone();
+        let mut depth = 0;
+        for component in rendered.split(['/', '\\']).map(str::trim) {
+            match component {
+                "" | "." => continue,
+                ".." => {
+                    return Err(format!("layout '{}' must stay inside the output directory", layout.as_str()));
+                }
+                component => {
+                    folder.push(component);
+                    depth += 1;
+                }
+            }
+        }
+
+        if depth == 0 {
+            return Err(format!("layout '{}' rendered an empty folder", layout.as_str()));
+        }
+        Ok(folder)
+    }
+
+    fn render_file_name(
+        &self,
+        template: &Template,
+        file: &FileInfo,
+        folder: &Path,
+        facts: &FileFacts,
+    ) -> Result<String, String> {
+        let uses_counter = template.uses("counter");
+        let stem_budget = if uses_counter && self.sanitizer.truncate {
+            self.counter_stem_budget(template, file, facts)?
+        } else {
+            None
+        };
+        let mut tried = HashSet::new();
+
+        loop {
+            let counter = if uses_counter { self.next_counter(folder) } else { 0 };
+            let rendered = template.render(|name, spec| {
+                let value = self.resolve_placeholder(file, facts, counter, name, spec)?;
+                let value = match (name, stem_budget) {
+                    ("stem", Some(budget)) => sanitize::truncate_to_boundary(&value, budget).to_string(),
+                    _ => value,
+                };
+                Ok(value.replace(['/', '\\'], "_"))
+            })?;
+            let rendered = self.sanitize_name(&rendered);
+
+            if rendered.is_empty() || rendered.contains(['/', '\\']) || rendered == "." || rendered == ".." {
+                return Err(format!("template '{}' rendered an invalid file name '{}'", template.as_str(), rendered));
+            }
+
+            // A counter keeps counting past names that are already taken, as
+            // long as each value gives a new name
+            if !uses_counter || !self.is_taken(&folder.join(&rendered)) {
+                return Ok(rendered);
+            }
+            if !tried.insert(rendered.clone()) || tried.len() >= MAX_COUNTER_TRIES {
+                return Err(format!(
+                    "template '{}' found no free name; '{}' is taken",
+                    template.as_str(),
+                    rendered
+                ));
+            }
+        }
+    }
+
+    /// How long `{stem}` may be for the counter, at any width, to survive
+    /// `--sanitize truncate`, or `None` when the name is short enough.
+    fn counter_stem_budget(&self, template: &Template, file: &FileInfo, facts: &FileFacts) -> Result<Option<usize>, String> {
+        let widest = template.render(|name, spec| {
+            self.resolve_placeholder(file, facts, u64::MAX, name, spec)
+                .map(|value| value.replace(['/', '\\'], "_"))
+        })?;
+        let untruncated = NameSanitizer {
+            truncate: false,
+            ..self.sanitizer.clone()
+        };
+        let extension = Path::new(&widest).extension().map(|ext| ext.to_string_lossy().to_string());
+        let excess = untruncated.apply(&widest, extension.as_deref()).len().saturating_sub(MAX_NAME_BYTES);
+        if excess == 0 {
+            return Ok(None);
+        }
+        let stem = self.resolve_placeholder(file, facts, 0, "stem", None)?;
+        Ok(Some(stem.len().saturating_sub(excess).max(1)))
+    }
+
+    fn next_counter(&self, folder: &Path) -> u64 {
+        let mut counters = self.counters.borrow_mut();
+        let counter = counters.entry(folder.to_path_buf()).or_insert(1);
+        let value = *counter;
+        *counter += 1;
+        value
+    }
+
+    fn resolve_placeholder(
+        &self,
+        file: &FileInfo,
+        facts: &FileFacts,
+        counter: u64,
+        name: &str,
+        spec: Option<&str>,
+    ) -> Result<String, String> {
+        let exif = file.exif.as_ref();
+        let tags = file.tags.as_ref();
+        let document = file.document.as_ref();
+        let stem = match &file.extension {
+            Some(ext) if file.name.len() > ext.len() + 1 => &file.name[..file.name.len() - ext.len() - 1],
+            _ => file.name.as_str(),
+        };
+
+        match name {
+            "name" => Ok(file.name.clone()),
+            "stem" => Ok(stem.to_string()),
+            "ext" => Ok(file.extension.as_ref().map(|ext| format!(".{}", ext)).unwrap_or_default()),
+            "category" => Ok(file.category.folder_name().to_string()),
+            "subcategory" => Ok(file.subcategory.unwrap_or_default().to_string()),
+            "date" | "modified" => {
+                let modified = self.metadata(file, facts)?.modified().map_err(|e| e.to_string())?;
+                Ok(DateTime::from_system_time(modified).format(spec.unwrap_or("%Y-%m-%d")))
+            }
+            "created" => {
+                // Not every filesystem records a birth time
+                let metadata = self.metadata(file, facts)?;
+                let created = metadata.created().or_else(|_| metadata.modified()).map_err(|e| e.to_string())?;
+                Ok(DateTime::from_system_time(created).format(spec.unwrap_or("%Y-%m-%d")))
+            }
+            "hash" => {
+                let hash = self.content_hash(file, facts)?;
+                let length = match spec {
+                    Some(spec) => spec.parse::<usize>().map_err(|_| format!("invalid hash length '{}'", spec))?,
+                    None => 8,
+                };
+                Ok(hash[..length.min(hash.len())].to_string())
+            }
+            "counter" => template::format_number(counter, spec),
+            "exif.date" => Ok(self.date_taken(file, facts)?.format(spec.unwrap_or("%Y-%m-%d"))),
+            "exif.year" => Ok(self.date_taken(file, facts)?.format("%Y")),
+            "exif.month" => Ok(self.date_taken(file, facts)?.format("%m")),
+            "exif.day" => Ok(self.date_taken(file, facts)?.format("%d")),
+            "exif.camera" => Ok(exif
+                .and_then(|exif| exif.camera())
+                .unwrap_or_else(|| "Unknown Camera".to_string())),
+            "exif.make" => Ok(exif
+                .and_then(|exif| exif.make.clone())
+                .unwrap_or_else(|| "Unknown Make".to_string())),
+            "exif.model" => Ok(exif
+                .and_then(|exif| exif.model.clone())
+                .unwrap_or_else(|| "Unknown Model".to_string())),
+            "exif.gps" => Ok(if exif.is_some_and(|exif| exif.has_gps) { "GPS" } else { "No GPS" }.to_string()),
+            "artist" => Ok(tags
+                .and_then(|tags| tags.artist.clone().or_else(|| tags.album_artist.clone()))
+                .unwrap_or_else(|| self.unknown_artist.clone())),
+            "album_artist" => Ok(tags
+                .and_then(|tags| tags.album_artist.clone().or_else(|| tags.artist.clone()))
+                .unwrap_or_else(|| self.unknown_artist.clone())),
+            "album" => Ok(tags
+                .and_then(|tags| tags.album.clone())
+                .unwrap_or_else(|| self.unknown_album.clone())),
+            "title" => Ok(tags
+                .and_then(|tags| tags.title.clone())
+                .unwrap_or_else(|| stem.to_string())),
+            "track" => template::format_number(tags.and_then(|tags| tags.track).unwrap_or(0) as u64, spec),
+            "year" => Ok(tags
+                .and_then(|tags| tags.year)
+                .map(|year| year.to_string())
+                .unwrap_or_else(|| "Unknown Year".to_string())),
+            "doc.title" => Ok(document
+                .and_then(|document| document.title.clone())
+                .unwrap_or_else(|| stem.to_string())),
+            "doc.author" => Ok(document
+                .and_then(|document| document.author.clone())
+                .unwrap_or_else(|| "Unknown Author".to_string())),
+            "doc.created" => Ok(self.document_created(file, facts)?.format(spec.unwrap_or("%Y-%m-%d"))),
+            "doc.year" => Ok(self.document_created(file, facts)?.format("%Y")),
+            "doc.pages" => template::format_number(document.and_then(|document| document.pages).unwrap_or(0) as u64, spec),
+            _ => Err(format!("unknown placeholder '{{{}}}'", name)),
+        }
+    }
+
+    /// When the photo was taken, falling back to the modification time.
+    fn date_taken(&self, file: &FileInfo, facts: &FileFacts) -> Result<DateTime, String> {
+        if let Some(date) = file.exif.as_ref().and_then(|exif| exif.date_taken) {
+            return Ok(date);
+        }
+        let modified = self.metadata(file, facts)?.modified().map_err(|e| e.to_string())?;
+        Ok(DateTime::from_system_time(modified))
+    }
+
+    /// When the document was created, falling back to the modification time.
+    fn document_created(&self, file: &FileInfo, facts: &FileFacts) -> Result<DateTime, String> {
+        if let Some(date) = file.document.as_ref().and_then(|document| document.created) {
+            return Ok(date);
+        }
+        let modified = self.metadata(file, facts)?.modified().map_err(|e| e.to_string())?;
+        Ok(DateTime::from_system_time(modified))
+    }
+
+    fn metadata<'a>(&self, file: &FileInfo, facts: &'a FileFacts) -> Result<&'a fs::Metadata, String> {
+        if let Some(metadata) = facts.metadata.get() {
+            return Ok(metadata);
+        }
+        let metadata = file_metadata(&file.path).map_err(|e| self.format_io_error("cannot read file metadata", &e))?;
+        Ok(facts.metadata.get_or_init(|| metadata))
+    }
+
+    fn content_hash<'a>(&self, file: &FileInfo, facts: &'a FileFacts) -> Result<&'a str, String> {
+        if let Some(hash) = facts.hash.get() {
+            return Ok(hash);
+        }
+        let hash = content_hash(&file.path).map_err(|e| self.format_io_error("cannot hash file", &e))?;
+        Ok(facts.hash.get_or_init(|| hash))
+    }
+
+    fn is_renamed(&self, operation: &MoveOperation) -> bool {
+        operation
+            .destination
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy() != operation.file_name)
+    }
+
+    /// Folder the file lands in, plus its new name when it was renamed.
+    pub fn destination_label(&self, operation: &MoveOperation) -> String {
+        let folder = operation
+            .destination
+            .parent()
+            .map(|parent| parent.strip_prefix(&self.output_dir).unwrap_or(parent))
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let target_name = operation
+            .destination
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if target_name == operation.file_name {
+            folder
+        } else {
+            format!("{}/{}", folder, target_name)
+        }
+    }
+
+    fn should_move(&self, operation: &MoveOperation) -> Result<(), String> {
+        // Skip if source and destination are the same
+        if operation.source == operation.destination {
+            return Err("source and destination are the same".to_string());
+        }
+
+        // Skip if destination file already exists (dangling links included)
+        if fs::symlink_metadata(&operation.destination).is_ok() {
+            return Err("destination file already exists".to_string());
+        }
+
+        // Skip detailed permission checks in dry-run mode since directories may not exist yet
+        if self.dry_run {
+            return Ok(());
+        }
+
+        // Check if source file is readable; a link moved as a link never is opened
+        if !(self.symlinks == SymlinkPolicy::MoveLink && is_symlink(&operation.source)) {
+            self.check_source_readable(&operation.source)?;
+        }
+
+        // Check if destination directory is writable
+        self.check_destination_writable(&operation.destination)?;
+
+        Ok(())
+    }
+
+    fn check_source_readable(&self, source: &Path) -> Result<(), String> {
+        // Try to open the file for reading to verify access
+        match fs::File::open(source) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(self.format_io_error("cannot read source file", &e)),
+        }
+    }
+
+    fn check_destination_writable(&self, destination: &Path) -> Result<(), String> {
+        if let Some(parent) = destination.parent() {
+            if parent.exists() {
+                // Check if we can write to the directory by checking metadata
+                match fs::metadata(parent) {
+                    Ok(metadata) => {
+                        if metadata.permissions().readonly() {
+                            return Err("destination directory is read-only".to_string());
+                        }
+                        Ok(())
+                    }
+                    Err(e) => Err(self.format_io_error("cannot access destination directory", &e)),
+                }
+            } else {
+                Err("destination directory does not exist".to_string())
+            }
+        } else {
+            Err("invalid destination path".to_string())
+        }
+    }
+
+    fn execute_move(&self, operation: &MoveOperation) -> io::Result<CopyStats> {
+        if is_symlink(&operation.source) {
+            return self.transfer_symlink(operation).map_err(|e| self.enhance_io_error(e, operation));
+        }
+
+        if self.mode == TransferMode::Copy {
+            return staging::copy_staged(&operation.source, &operation.destination, self.reflink)
+                .map_err(|e| self.enhance_io_error(e, operation));
+        }
+
+        // Try rename first (fastest, works on same filesystem)
+        match fs::rename(&operation.source, &operation.destination) {
+            Ok(()) => Ok(CopyStats::default()),
+            Err(e) => {
+                // Handle cross-device link error by copying and deleting
+                if e.raw_os_error() == Some(libc::EXDEV) {
+                    self.copy_and_delete(operation)
+                } else {
+                    Err(self.enhance_io_error(e, operation))
+                }
+            }
+        }
+    }
+
+    fn copy_and_delete(&self, operation: &MoveOperation) -> io::Result<CopyStats> {
+        // Copy the file first; the destination only appears once complete
+        let stats = staging::copy_staged(&operation.source, &operation.destination, self.reflink)?;
+
+        // Then delete the source
+        fs::remove_file(&operation.source).map_err(|e| {
+            // If we can't delete the source, try to clean up the destination
+            let _ = fs::remove_file(&operation.destination);
+            io::Error::new(
+                e.kind(),
+                format!("copied file but failed to remove source: {}", e),
+            )
+        })?;
+        Ok(stats)
+    }
+
+    /// Recreates the link at the destination, or with `Follow` copies the
+    /// file it points to. In move mode the original link is removed after;
+    /// the target itself is never moved.
+    fn transfer_symlink(&self, operation: &MoveOperation) -> io::Result<CopyStats> {
+        let stats = match self.symlinks {
+            SymlinkPolicy::Follow => staging::copy_staged(&operation.source, &operation.destination, self.reflink)?,
+            _ => {
+                let mut target = fs::read_link(&operation.source)?;
+                // A relative target would resolve against the new folder, and
+                // against the working directory if the source path is relative
+                if target.is_relative() {
+                    if let Some(parent) = operation.source.parent() {
+                        target = std::path::absolute(parent)?.join(target);
+                    }
+                }
+                create_symlink(&target, &operation.destination)?;
+                CopyStats::default()
+            }
+        };
+
+        if self.mode == TransferMode::Move {
+            fs::remove_file(&operation.source).map_err(|e| {
+                let _ = fs::remove_file(&operation.destination);
+                io::Error::new(e.kind(), format!("transferred link but failed to remove it: {}", e))
+            })?;
+        }
+        Ok(stats)
+    }
+
+    fn enhance_io_error(&self, error: io::Error, operation: &MoveOperation) -> io::Error {
+        let message = match error.kind() {
+            io::ErrorKind::PermissionDenied => {
+                format!(
+                    "permission denied moving '{}' to '{}'",
+                    operation.source.display(),
+                    operation.destination.display()
+                )
+            }
+            io::ErrorKind::NotFound => {
+                format!("source file '{}' not found", operation.source.display())
+            }
+            io::ErrorKind::AlreadyExists => {
+                format!(
+                    "destination '{}' already exists",
+                    operation.destination.display()
+                )
+            }
+            _ => {
+                format!(
+                    "failed to {} '{}': {}",
+                    self.verb(),
+                    operation.file_name,
+                    error
+                )
+            }
+        };
+        io::Error::new(error.kind(), message)
+    }
+
+    fn format_io_error(&self, context: &str, error: &io::Error) -> String {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => format!("{}: permission denied", context),
+            io::ErrorKind::NotFound => format!("{}: file not found", context),
+            _ => format!("{}: {}", context, error),
+        }
+    }
+}
+
+/// Records a file left in place, with the reason, in the log.
+pub fn log_skipped(operation: &MoveOperation, reason: &str) {
+    logging::warn(
+        "skipped",
+        &[("source", &operation.source.display()), ("destination", &operation.destination.display()), ("reason", &reason)],
+    );
+}
+
+/// Records a file that could not be moved, with the reason, in the log.
+pub fn log_failed(operation: &MoveOperation, reason: &str) {
+    logging::error(
+        "failed",
+        &[("source", &operation.source.display()), ("destination", &operation.destination.display()), ("reason", &reason)],
+    );
+}
+
+/// `dir` and the parents of it that do not exist yet, outermost first.
+fn missing_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && fs::symlink_metadata(ancestor).is_err())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+/// Metadata of the file, or of the link itself when its target is missing.
+fn file_metadata(path: &Path) -> io::Result<fs::Metadata> {
+    fs::metadata(path).or_else(|_| fs::symlink_metadata(path))
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// 64-bit FNV-1a hash of the file contents, as 16 hex digits.
+fn content_hash(path: &Path) -> io::Result<String> {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut file = fs::File::open(path)?;
+    let mut buffer = [0u8; 64 * 1024];
+    let mut hash = OFFSET_BASIS;
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        for byte in &buffer[..read] {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+
+    Ok(format!("{:016x}", hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::categories::CategoryMapper;
+    use crate::scanner::DirectoryScanner;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_create_directories_for_categories() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        // Create test files
+        File::create(source_dir.path().join("photo.jpg")).unwrap();
+        File::create(source_dir.path().join("document.pdf")).unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        let created = organizer.create_category_directories(&scan_result).unwrap();
+
+        assert_eq!(created.len(), 2);
+        assert!(output_dir.path().join("Images").exists());
+        assert!(output_dir.path().join("Documents").exists());
+    }
+
+    #[test]
+    fn test_dry_run_does_not_create_directories() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        File::create(source_dir.path().join("photo.jpg")).unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), true, false);
+        let created = organizer.create_category_directories(&scan_result).unwrap();
+
+        assert_eq!(created.len(), 1);
+        assert!(!output_dir.path().join("Images").exists());
+    }
+
+    #[test]
+    fn test_existing_directory_not_recreated() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        // Create the Images directory ahead of time
+        fs::create_dir(output_dir.path().join("Images")).unwrap();
+
+        File::create(source_dir.path().join("photo.jpg")).unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        let created = organizer.create_category_directories(&scan_result).unwrap();
+
+        // Should not be in created list since it already existed
+        assert_eq!(created.len(), 0);
+        assert!(output_dir.path().join("Images").exists());
+    }
+
+    #[test]
+    fn test_get_target_path() {
+        let output_dir = tempdir().unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+
+        let target = organizer.get_target_path(&FileCategory::Images, "photo.jpg");
+        assert_eq!(
+            target,
+            output_dir.path().join("Images").join("photo.jpg")
+        );
+    }
+
+    #[test]
+    fn test_empty_scan_creates_no_directories() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        let created = organizer.create_category_directories(&scan_result).unwrap();
+
+        assert!(created.is_empty());
+    }
+
+    #[test]
+    fn test_move_files() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        // Create test files with content
+        std::fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+        std::fs::write(source_dir.path().join("document.pdf"), "pdf data").unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 2);
+        assert!(move_result.failed.is_empty());
+        assert!(move_result.skipped.is_empty());
+
+        // Verify files were moved
+        assert!(output_dir.path().join("Images/photo.jpg").exists());
+        assert!(output_dir.path().join("Documents/document.pdf").exists());
+
+        // Verify source files no longer exist
+        assert!(!source_dir.path().join("photo.jpg").exists());
+        assert!(!source_dir.path().join("document.pdf").exists());
+    }
+
+    #[test]
+    fn test_dry_run_does_not_move_files() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), true, false);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 1);
+
+        // File should still exist in source (dry run)
+        assert!(source_dir.path().join("photo.jpg").exists());
+    }
+
+    #[test]
+    fn test_skip_existing_destination() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("photo.jpg"), "new image").unwrap();
+
+        // Create destination with existing file
+        fs::create_dir_all(output_dir.path().join("Images")).unwrap();
+        std::fs::write(output_dir.path().join("Images/photo.jpg"), "existing image").unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        let move_result = organizer.move_files(&scan_result);
+
+        assert!(move_result.moved.is_empty());
+        assert_eq!(move_result.skipped.len(), 1);
+
+        // Source file should still exist
+        assert!(source_dir.path().join("photo.jpg").exists());
+
+        // Destination should have original content
+        let content = std::fs::read_to_string(output_dir.path().join("Images/photo.jpg")).unwrap();
+        assert_eq!(content, "existing image");
+    }
+
+    #[test]
+    fn test_rename_on_conflict() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("photo.jpg"), "new image").unwrap();
+        fs::create_dir_all(output_dir.path().join("Images")).unwrap();
+        std::fs::write(output_dir.path().join("Images/photo.jpg"), "existing image").unwrap();
+        std::fs::write(output_dir.path().join("Images/photo (1).jpg"), "older copy").unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_conflict_strategy(ConflictStrategy::Rename);
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 1);
+        let content = std::fs::read_to_string(output_dir.path().join("Images/photo (2).jpg")).unwrap();
+        assert_eq!(content, "new image");
+        let content = std::fs::read_to_string(output_dir.path().join("Images/photo.jpg")).unwrap();
+        assert_eq!(content, "existing image");
+    }
+
+    #[test]
+    fn test_subcategory_folders() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("budget.xlsx"), "sheet").unwrap();
+        std::fs::write(source_dir.path().join("shot.NEF"), "raw").unwrap();
+        std::fs::write(source_dir.path().join("letter.pdf"), "pdf").unwrap();
+
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source_dir.path()).unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
 
-    fn format_io_error(&self, context: &str, error: &io::Error) -> String {
-        match error.kind() {
-            io::ErrorKind::PermissionDenied => format!("{}: permission denied", context),
-            io::ErrorKind::NotFound => format!("{}: file not found", context),
-            _ => format!("{}: {}", context, error),
-        }
+        assert_eq!(move_result.moved.len(), 3);
+        assert!(output_dir.path().join("Documents/Spreadsheets/budget.xlsx").exists());
+        assert!(output_dir.path().join("Images/RAW/shot.NEF").exists());
+        assert!(output_dir.path().join("Documents/letter.pdf").exists());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::categories::CategoryMapper;
-    use crate::scanner::DirectoryScanner;
-    use std::fs::File;
-    use tempfile::tempdir;
+    #[test]
+    fn test_flat_mapper_keeps_single_level() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("budget.xlsx"), "sheet").unwrap();
+
+        let mapper = CategoryMapper::new().with_subcategories(false);
+        let scan_result = DirectoryScanner::new(mapper).scan(source_dir.path()).unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        organizer.create_category_directories(&scan_result).unwrap();
+        organizer.move_files(&scan_result);
+
+        assert!(output_dir.path().join("Documents/budget.xlsx").exists());
+    }
 
     #[test]
-    fn test_create_directories_for_categories() {
+    fn test_sanitizer_renames_on_move() {
+        use crate::sanitize::{CaseStyle, SanitizeStep};
+
         let source_dir = tempdir().unwrap();
         let output_dir = tempdir().unwrap();
 
-        // Create test files
-        File::create(source_dir.path().join("photo.jpg")).unwrap();
-        File::create(source_dir.path().join("document.pdf")).unwrap();
+        std::fs::write(source_dir.path().join("My  Trip: Day 1?.JPG"), "image data").unwrap();
 
         let scanner = DirectoryScanner::new(CategoryMapper::new());
         let scan_result = scanner.scan(source_dir.path()).unwrap();
 
-        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
-        let created = organizer.create_category_directories(&scan_result).unwrap();
+        let sanitizer = NameSanitizer::new(&[SanitizeStep::All], Some(CaseStyle::Kebab));
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_sanitizer(sanitizer);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
 
-        assert_eq!(created.len(), 2);
-        assert!(output_dir.path().join("Images").exists());
-        assert!(output_dir.path().join("Documents").exists());
+        assert_eq!(move_result.moved.len(), 1);
+        assert_eq!(move_result.moved[0].file_name, "My  Trip: Day 1?.JPG");
+        assert!(output_dir.path().join("Images/my-trip-day-1.jpg").exists());
     }
 
     #[test]
-    fn test_dry_run_does_not_create_directories() {
+    fn test_name_template_with_counter() {
         let source_dir = tempdir().unwrap();
         let output_dir = tempdir().unwrap();
 
-        File::create(source_dir.path().join("photo.jpg")).unwrap();
+        std::fs::write(source_dir.path().join("a.jpg"), "a").unwrap();
+        std::fs::write(source_dir.path().join("b.jpg"), "b").unwrap();
+
+        // An existing file takes the first counter value
+        fs::create_dir_all(output_dir.path().join("Images")).unwrap();
+        std::fs::write(output_dir.path().join("Images/Images-0001.jpg"), "old").unwrap();
 
         let scanner = DirectoryScanner::new(CategoryMapper::new());
         let scan_result = scanner.scan(source_dir.path()).unwrap();
 
-        let organizer = Organizer::new(output_dir.path().to_path_buf(), true, false);
-        let created = organizer.create_category_directories(&scan_result).unwrap();
+        let template = Template::parse("{category}-{counter:04}{ext}").unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_name_template(template);
+        let move_result = organizer.move_files(&scan_result);
 
-        assert_eq!(created.len(), 1);
-        assert!(!output_dir.path().join("Images").exists());
+        assert_eq!(move_result.moved.len(), 2);
+        assert!(output_dir.path().join("Images/Images-0002.jpg").exists());
+        assert!(output_dir.path().join("Images/Images-0003.jpg").exists());
+        assert_eq!(std::fs::read_to_string(output_dir.path().join("Images/Images-0001.jpg")).unwrap(), "old");
     }
 
     #[test]
-    fn test_existing_directory_not_recreated() {
+    fn test_counter_survives_truncation() {
+        use crate::sanitize::SanitizeStep;
+
         let source_dir = tempdir().unwrap();
         let output_dir = tempdir().unwrap();
 
-        // Create the Images directory ahead of time
-        fs::create_dir(output_dir.path().join("Images")).unwrap();
+        // Both stems lose their differing tail to truncation
+        let long_stem = "x".repeat(249);
+        std::fs::write(source_dir.path().join(format!("{}1.jpg", long_stem)), "a").unwrap();
+        std::fs::write(source_dir.path().join(format!("{}2.jpg", long_stem)), "b").unwrap();
+        fs::create_dir_all(output_dir.path().join("Images")).unwrap();
 
-        File::create(source_dir.path().join("photo.jpg")).unwrap();
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let template = Template::parse("{stem}-{counter}{ext}").unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_name_template(template)
+            .with_sanitizer(NameSanitizer::new(&[SanitizeStep::Truncate], None));
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 2);
+        let mut names: Vec<String> = fs::read_dir(output_dir.path().join("Images"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 2);
+        assert!(names.iter().all(|name| name.len() <= MAX_NAME_BYTES));
+        assert!(names[0].ends_with("-1.jpg"));
+        assert!(names[1].ends_with("-2.jpg"));
+    }
+
+    #[test]
+    fn test_name_template_dry_run_renders_names() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("report.pdf"), "pdf data").unwrap();
 
         let scanner = DirectoryScanner::new(CategoryMapper::new());
         let scan_result = scanner.scan(source_dir.path()).unwrap();
 
-        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
-        let created = organizer.create_category_directories(&scan_result).unwrap();
+        let template = Template::parse("{stem}_{hash:6}{ext}").unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), true, false)
+            .with_name_template(template);
+        let move_result = organizer.move_files(&scan_result);
 
-        // Should not be in created list since it already existed
-        assert_eq!(created.len(), 0);
-        assert!(output_dir.path().join("Images").exists());
+        let expected = format!("report_{}.pdf", &content_hash(&source_dir.path().join("report.pdf")).unwrap()[..6]);
+        assert_eq!(move_result.moved.len(), 1);
+        assert_eq!(move_result.moved[0].destination, output_dir.path().join("Documents").join(expected));
+        assert!(source_dir.path().join("report.pdf").exists());
     }
 
     #[test]
-    fn test_get_target_path() {
+    fn test_layout_uses_exif_with_mtime_fallback() {
+        use crate::exif::tests::{build_jpeg, build_tiff};
+
+        let source_dir = tempdir().unwrap();
         let output_dir = tempdir().unwrap();
-        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
 
-        let target = organizer.get_target_path(&FileCategory::Images, "photo.jpg");
-        assert_eq!(
-            target,
-            output_dir.path().join("Images").join("photo.jpg")
-        );
+        let tiff = build_tiff("Canon", "Canon EOS R6", "2021:05:09 12:00:00", false);
+        std::fs::write(source_dir.path().join("tagged.jpg"), build_jpeg(&tiff)).unwrap();
+        std::fs::write(source_dir.path().join("untagged.jpg"), "no exif").unwrap();
+        std::fs::write(source_dir.path().join("notes.txt"), "text").unwrap();
+        // Mid-June 2019, so the year is the same in every time zone
+        std::fs::File::options()
+            .write(true)
+            .open(source_dir.path().join("untagged.jpg"))
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_560_600_000))
+            .unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new()).with_exif(true);
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let layout = Layout::parse("Images/{exif.year}/{exif.camera}").unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_layout(Some(FileCategory::Images), layout);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 3);
+        assert!(output_dir.path().join("Images/2021/Canon EOS R6/tagged.jpg").exists());
+        assert!(output_dir.path().join("Documents/notes.txt").exists());
+
+        assert!(output_dir.path().join("Images/2019/Unknown Camera/untagged.jpg").exists());
     }
 
     #[test]
-    fn test_empty_scan_creates_no_directories() {
+    fn test_audio_layout_with_unknown_buckets() {
+        use crate::audio_tags::tests::build_id3;
+
         let source_dir = tempdir().unwrap();
         let output_dir = tempdir().unwrap();
 
-        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        std::fs::write(source_dir.path().join("a.mp3"), build_id3("AC/DC", "Back in Black", "Hells Bells", "1/10")).unwrap();
+        std::fs::write(source_dir.path().join("b.mp3"), "untagged").unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new()).with_audio_tags(true);
         let scan_result = scanner.scan(source_dir.path()).unwrap();
 
-        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
-        let created = organizer.create_category_directories(&scan_result).unwrap();
+        let layout = Layout::parse("Audio/{artist}/{album}/{track:02} - {title}{ext}").unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_layout(Some(FileCategory::Audio), layout)
+            .with_unknown_buckets("No Artist".to_string(), "No Album".to_string());
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
 
-        assert!(created.is_empty());
+        assert_eq!(move_result.moved.len(), 2);
+        assert!(output_dir.path().join("Audio/AC_DC/Back in Black/01 - Hells Bells.mp3").exists());
+        assert!(output_dir.path().join("Audio/No Artist/No Album/00 - b.mp3").exists());
     }
 
     #[test]
-    fn test_move_files() {
+    fn test_rules_route_documents_by_author() {
+        use crate::document_meta::tests::{build_docx, build_pdf};
+
         let source_dir = tempdir().unwrap();
         let output_dir = tempdir().unwrap();
 
-        // Create test files with content
+        std::fs::write(source_dir.path().join("contract.pdf"), build_pdf("Master Agreement", "ACME Legal")).unwrap();
+        std::fs::write(source_dir.path().join("offer.docx"), build_docx("Offer", "Initech")).unwrap();
+        std::fs::write(source_dir.path().join("notes.txt"), "text").unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new()).with_documents(true);
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let rules = vec![
+            Rule::parse("doc.author contains acme => Clients/ACME/{doc.year}/{doc.title}{ext}").unwrap(),
+            Rule::parse("doc.pages > 5 => Long").unwrap(),
+        ];
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_layout(Some(FileCategory::Documents), Layout::parse("Documents/{doc.author}").unwrap())
+            .with_rules(rules);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 3);
+        assert!(output_dir.path().join("Clients/ACME/2023/Master Agreement.pdf").exists());
+        assert!(output_dir.path().join("Long/offer.docx").exists());
+        assert!(output_dir.path().join("Documents/Unknown Author/notes.txt").exists());
+    }
+
+    #[test]
+    fn test_document_rules_only_apply_to_documents() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("notes.txt"), "text").unwrap();
         std::fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
-        std::fs::write(source_dir.path().join("document.pdf"), "pdf data").unwrap();
 
-        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scanner = DirectoryScanner::new(CategoryMapper::new()).with_documents(true);
         let scan_result = scanner.scan(source_dir.path()).unwrap();
 
-        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        let rules = vec![Rule::parse("doc.author is \"Unknown Author\" => Unsorted").unwrap()];
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false).with_rules(rules);
         organizer.create_category_directories(&scan_result).unwrap();
+        assert_eq!(organizer.placements.borrow().len(), 2);
         let move_result = organizer.move_files(&scan_result);
 
         assert_eq!(move_result.moved.len(), 2);
-        assert!(move_result.failed.is_empty());
-        assert!(move_result.skipped.is_empty());
-
-        // Verify files were moved
+        assert!(output_dir.path().join("Unsorted/notes.txt").exists());
         assert!(output_dir.path().join("Images/photo.jpg").exists());
-        assert!(output_dir.path().join("Documents/document.pdf").exists());
-
-        // Verify source files no longer exist
-        assert!(!source_dir.path().join("photo.jpg").exists());
-        assert!(!source_dir.path().join("document.pdf").exists());
+        // Each file's folder was worked out once, for creating it
+        assert!(organizer.placements.borrow().is_empty());
     }
 
     #[test]
-    fn test_dry_run_does_not_move_files() {
+    fn test_layout_cannot_escape_output() {
         let source_dir = tempdir().unwrap();
         let output_dir = tempdir().unwrap();
 
@@ -446,42 +1901,250 @@ mod tests {
         let scanner = DirectoryScanner::new(CategoryMapper::new());
         let scan_result = scanner.scan(source_dir.path()).unwrap();
 
-        let organizer = Organizer::new(output_dir.path().to_path_buf(), true, false);
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_layout(None, Layout::parse("../{category}").unwrap());
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.failed.len(), 1);
+        assert!(move_result.failed[0].1.contains("inside the output directory"));
+    }
+
+    #[test]
+    fn test_journal_records_moves() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source_dir.path()).unwrap();
+        let journal = Journal::create(output_dir.path()).unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false).with_journal(journal);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+        assert_eq!(move_result.moved.len(), 1);
+
+        let state = crate::journal::read_state(output_dir.path()).unwrap().unwrap();
+        assert!(!state.is_interrupted());
+        assert!(state.in_flight.is_empty());
+        assert_eq!(state.done, vec![(source_dir.path().join("photo.jpg"), output_dir.path().join("Images/photo.jpg"))]);
+    }
+
+    #[test]
+    fn test_recursive_keeps_relative_folders() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        fs::create_dir_all(source_dir.path().join("trip/day1")).unwrap();
+        std::fs::write(source_dir.path().join("trip/day1/photo.jpg"), "image data").unwrap();
+
+        let scan_result = DirectoryScanner::new(CategoryMapper::new())
+            .with_recursive(true)
+            .scan(source_dir.path())
+            .unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
         organizer.create_category_directories(&scan_result).unwrap();
         let move_result = organizer.move_files(&scan_result);
 
         assert_eq!(move_result.moved.len(), 1);
+        assert!(output_dir.path().join("Images/trip/day1/photo.jpg").exists());
+    }
 
-        // File should still exist in source (dry run)
-        assert!(source_dir.path().join("photo.jpg").exists());
+    #[test]
+    fn test_organize_stream_creates_folders_as_needed() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+        std::fs::write(source_dir.path().join("document.pdf"), "pdf data").unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        let move_result = organizer.organize_stream(scanner.scan_iter(source_dir.path()).unwrap());
+
+        assert_eq!(move_result.moved.len(), 2);
+        assert!(move_result.failed.is_empty());
+        assert!(output_dir.path().join("Images/photo.jpg").exists());
+        assert!(output_dir.path().join("Documents/document.pdf").exists());
     }
 
     #[test]
-    fn test_skip_existing_destination() {
+    fn test_flatten_encodes_colliding_names() {
         let source_dir = tempdir().unwrap();
         let output_dir = tempdir().unwrap();
 
-        std::fs::write(source_dir.path().join("photo.jpg"), "new image").unwrap();
+        fs::create_dir_all(source_dir.path().join("a/b")).unwrap();
+        fs::create_dir_all(source_dir.path().join("c")).unwrap();
+        std::fs::write(source_dir.path().join("notes.txt"), "top").unwrap();
+        std::fs::write(source_dir.path().join("a/b/notes.txt"), "nested").unwrap();
+        std::fs::write(source_dir.path().join("c/todo.txt"), "unique").unwrap();
+
+        let scan_result = DirectoryScanner::new(CategoryMapper::new())
+            .with_recursive(true)
+            .scan(source_dir.path())
+            .unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_flatten(true)
+            .with_flatten_map(FlattenMap::open(output_dir.path()).unwrap());
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 3);
+        let documents = output_dir.path().join("Documents");
+        assert!(documents.join("todo.txt").exists());
+        let mut names: Vec<String> = fs::read_dir(&documents)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        // Files of the top folder are scanned first and keep their names
+        assert_eq!(names, ["a__b__notes.txt", "notes.txt", "todo.txt"]);
+        assert_eq!(fs::read_to_string(documents.join("notes.txt")).unwrap(), "top");
+
+        let map = fs::read_to_string(output_dir.path().join(flatten::FLATTEN_MAP_FILE_NAME)).unwrap();
+        assert_eq!(map.lines().count(), 3);
+        assert!(map.contains(&format!(
+            "{}\t{}",
+            output_dir.path().join("Documents/todo.txt").display(),
+            source_dir.path().join("c/todo.txt").display()
+        )));
+    }
+
+    #[test]
+    fn test_max_per_folder_shards_overflow() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
 
-        // Create destination with existing file
         fs::create_dir_all(output_dir.path().join("Images")).unwrap();
-        std::fs::write(output_dir.path().join("Images/photo.jpg"), "existing image").unwrap();
+        std::fs::write(output_dir.path().join("Images/old.jpg"), "earlier run").unwrap();
+        for name in ["a.jpg", "b.jpg", "c.jpg", "d.jpg"] {
+            std::fs::write(source_dir.path().join(name), name).unwrap();
+        }
 
-        let scanner = DirectoryScanner::new(CategoryMapper::new());
-        let scan_result = scanner.scan(source_dir.path()).unwrap();
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source_dir.path()).unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_max_per_folder(2, ShardScheme::Number);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 4, "{:?}", move_result.failed);
+        let images = output_dir.path().join("Images");
+        let count = |dir: &Path| fs::read_dir(dir).unwrap().count();
+        assert!(images.join("old.jpg").exists());
+        assert_eq!(count(&images), 3); // old.jpg, 0001 and 0002
+        assert_eq!(count(&images.join("0001")), 2);
+        assert_eq!(count(&images.join("0002")), 2);
+
+        // Copying the same files again finds them in their shards
+        for name in ["a.jpg", "b.jpg", "c.jpg", "d.jpg"] {
+            std::fs::write(source_dir.path().join(name), name).unwrap();
+        }
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source_dir.path()).unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_mode(TransferMode::Copy)
+            .with_max_per_folder(2, ShardScheme::Number);
+        let move_result = organizer.move_files(&scan_result);
+        assert_eq!(move_result.skipped.len(), 4);
+        assert_eq!(count(&images), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_link_recreates_links() {
+        use std::os::unix::fs::symlink;
 
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("real.txt"), "data").unwrap();
+        symlink("real.txt", source_dir.path().join("link.txt")).unwrap();
+        symlink("missing.txt", source_dir.path().join("dangling.txt")).unwrap();
+
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source_dir.path()).unwrap();
         let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 3, "{:?}", move_result.failed);
+        let link = output_dir.path().join("Documents/link.txt");
+        let real = output_dir.path().join("Documents/real.txt");
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        // Relative targets are rewritten against the folder the link came from
+        assert_eq!(fs::read_link(&link).unwrap(), source_dir.path().join("real.txt"));
+        assert!(fs::symlink_metadata(output_dir.path().join("Documents/dangling.txt")).is_ok());
+        assert!(real.exists());
+        assert!(fs::read_dir(source_dir.path()).unwrap().next().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_copies_link_targets() {
+        use std::os::unix::fs::symlink;
+
+        let source_dir = tempdir().unwrap();
+        let target_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(target_dir.path().join("real.txt"), "data").unwrap();
+        symlink(target_dir.path().join("real.txt"), source_dir.path().join("link.txt")).unwrap();
+
+        let scan_result = DirectoryScanner::new(CategoryMapper::new())
+            .with_symlinks(SymlinkPolicy::Follow)
+            .scan(source_dir.path())
+            .unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false).with_symlinks(SymlinkPolicy::Follow);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 1);
+        let copied = output_dir.path().join("Documents/link.txt");
+        assert!(!fs::symlink_metadata(&copied).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "data");
+        assert!(fs::symlink_metadata(source_dir.path().join("link.txt")).is_err());
+        assert!(target_dir.path().join("real.txt").exists());
+    }
+
+    #[test]
+    fn test_stop_flag_interrupts_run() {
+        static STOP: AtomicBool = AtomicBool::new(true);
+
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        std::fs::write(source_dir.path().join("a.jpg"), "a").unwrap();
+        std::fs::write(source_dir.path().join("b.jpg"), "b").unwrap();
+
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source_dir.path()).unwrap();
+        let journal = Journal::create(output_dir.path()).unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_journal(journal)
+            .with_stop_flag(&STOP);
         let move_result = organizer.move_files(&scan_result);
 
+        assert!(move_result.interrupted);
+        assert_eq!(move_result.remaining, 2);
         assert!(move_result.moved.is_empty());
-        assert_eq!(move_result.skipped.len(), 1);
+        assert!(source_dir.path().join("a.jpg").exists());
+        assert!(crate::journal::read_state(output_dir.path()).unwrap().unwrap().is_interrupted());
+    }
 
-        // Source file should still exist
-        assert!(source_dir.path().join("photo.jpg").exists());
+    #[test]
+    fn test_copy_mode_keeps_source() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
 
-        // Destination should have original content
-        let content = std::fs::read_to_string(output_dir.path().join("Images/photo.jpg")).unwrap();
-        assert_eq!(content, "existing image");
+        std::fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source_dir.path()).unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false)
+            .with_mode(TransferMode::Copy)
+            .with_reflink(ReflinkMode::Never);
+        organizer.create_category_directories(&scan_result).unwrap();
+        let move_result = organizer.move_files(&scan_result);
+
+        assert_eq!(move_result.moved.len(), 1);
+        assert_eq!(move_result.copy_stats, CopyStats { copied_bytes: 10, ..CopyStats::default() });
+        assert!(source_dir.path().join("photo.jpg").exists());
+        assert_eq!(std::fs::read_to_string(output_dir.path().join("Images/photo.jpg")).unwrap(), "image data");
     }
 
     #[test]
@@ -596,6 +2259,42 @@ mod tests {
         assert!(enhanced.to_string().contains("/source/file.jpg"));
     }
 
+    #[test]
+    fn test_estimate_space_same_device() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = source_dir.path().join("organized");
+
+        std::fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new());
+        let scan_result = scanner.scan(source_dir.path()).unwrap();
+
+        let organizer = Organizer::new(output_dir, true, false);
+        let estimate = organizer.estimate_space(&scan_result).unwrap();
+
+        // Same filesystem means a rename, so nothing needs to be copied
+        assert_eq!(estimate.cross_device_files, 0);
+        assert_eq!(estimate.cross_device_bytes, 0);
+        assert!(organizer.check_space(&estimate).is_ok());
+    }
+
+    #[test]
+    fn test_check_space_insufficient() {
+        let output_dir = tempdir().unwrap();
+        let organizer = Organizer::new(output_dir.path().to_path_buf(), false, false);
+
+        let estimate = SpaceEstimate {
+            cross_device_files: 1,
+            cross_device_bytes: 1024,
+            available_bytes: 10,
+            margin_bytes: 0,
+        };
+
+        let error = organizer.check_space(&estimate).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+        assert!(error.to_string().contains("not enough free space"));
+    }
+
     #[test]
     fn test_move_result_has_failures() {
         let mut result = MoveResult::default();
@@ -0,0 +1,116 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ignore::IgnoreList;
+use crate::journal::Journal;
+use crate::logging;
+
+/// Removes the directories under `root` that became empty because files
+/// were moved out of them, deepest first, and returns them in that order.
+///
+/// Only folders that held one of `moved_from` (or contain such a folder)
+/// are candidates, so directories that were empty before the run stay.
+/// `root` itself, ignored directories and symlinks are never removed, and
+/// a directory that still has anything in it fails to delete and is kept.
+pub fn prune_empty(
+    root: &Path,
+    moved_from: &[&Path],
+    ignored: &IgnoreList,
+    journal: Option<&Journal>,
+) -> io::Result<Vec<PathBuf>> {
+    let mut candidates = BTreeSet::new();
+    for source in moved_from {
+        for dir in source.ancestors().skip(1) {
+            let relative = match dir.strip_prefix(root) {
+                Ok(relative) if relative.as_os_str().is_empty() => break,
+                Ok(relative) => relative,
+                Err(_) => break,
+            };
+            if !candidates.insert((relative.components().count(), dir.to_path_buf())) {
+                break; // its ancestors are in already
+            }
+        }
+    }
+
+    let mut removed = Vec::new();
+    for (_, dir) in candidates.into_iter().rev() {
+        // Nothing inside an ignored directory is touched either
+        let relative = dir.strip_prefix(root).unwrap_or(&dir);
+        if relative.ancestors().any(|dir| !dir.as_os_str().is_empty() && ignored.is_ignored(dir)) {
+            continue;
+        }
+        match fs::symlink_metadata(&dir) {
+            Ok(metadata) if metadata.is_dir() => {}
+            _ => continue,
+        }
+
+        match fs::remove_dir(&dir) {
+            Ok(()) => {
+                if let Some(journal) = journal {
+                    journal.record_rmdir(&dir)?;
+                }
+                logging::debug("removed empty directory", &[("path", &dir.display())]);
+                removed.push(dir);
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::DirectoryNotEmpty | io::ErrorKind::NotFound) => {}
+            Err(e) => {
+                return Err(io::Error::new(e.kind(), format!("cannot remove {}: {}", dir.display(), e)));
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_prunes_only_emptied_directories() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        for path in ["a/b/c", "a/keep", "already-empty", "node_modules/x", "busy"] {
+            fs::create_dir_all(root.join(path)).unwrap();
+        }
+        fs::write(root.join("busy/still-here.txt"), "").unwrap();
+
+        // As if these files had been moved away
+        let moved = [
+            root.join("a/b/c/photo.jpg"),
+            root.join("a/b/song.mp3"),
+            root.join("node_modules/x/index.js"),
+            root.join("busy/doc.pdf"),
+            root.join("top.txt"),
+        ];
+        let moved: Vec<&Path> = moved.iter().map(PathBuf::as_path).collect();
+        let ignored = IgnoreList::parse("node_modules\n");
+
+        let removed = prune_empty(root, &moved, &ignored, None).unwrap();
+
+        assert_eq!(removed, vec![root.join("a/b/c"), root.join("a/b")]);
+        // `a` still holds the untouched, already empty `keep`
+        assert!(root.join("a/keep").exists());
+        assert!(root.join("already-empty").exists());
+        assert!(root.join("node_modules/x").exists());
+        assert!(root.join("busy").exists());
+        assert!(root.exists());
+    }
+
+    #[test]
+    fn test_removals_are_journaled() {
+        let dir = tempdir().unwrap();
+        let output = tempdir().unwrap();
+        fs::create_dir(dir.path().join("empty")).unwrap();
+
+        let journal = Journal::create(output.path()).unwrap();
+        let moved = dir.path().join("empty/file.txt");
+        prune_empty(dir.path(), &[moved.as_path()], &IgnoreList::default(), Some(&journal)).unwrap();
+
+        let state = crate::journal::read_state(output.path()).unwrap().unwrap();
+        assert_eq!(state.removed_dirs, vec![dir.path().join("empty")]);
+    }
+}
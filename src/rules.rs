@@ -0,0 +1,158 @@
+use std::cmp::Ordering;
+
+use crate::template::{Layout, FILE_NAME_ONLY_PLACEHOLDERS, PLACEHOLDERS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Contains,
+    Is,
+    StartsWith,
+    EndsWith,
+    GreaterThan,
+    LessThan,
+}
+
+impl Operator {
+    fn parse(text: &str) -> Option<Self> {
+        match text.to_lowercase().as_str() {
+            "contains" => Some(Self::Contains),
+            "is" | "==" | "=" => Some(Self::Is),
+            "startswith" | "starts-with" => Some(Self::StartsWith),
+            "endswith" | "ends-with" => Some(Self::EndsWith),
+            ">" => Some(Self::GreaterThan),
+            "<" => Some(Self::LessThan),
+            _ => None,
+        }
+    }
+}
+
+/// Routes files whose placeholder value matches to a layout of their own,
+/// e.g. `doc.author contains ACME => Clients/ACME`.
+///
+/// Text comparisons ignore case. `>` and `<` compare numerically when both
+/// sides are numbers and as text otherwise, which also orders
+/// `YYYY-MM-DD` dates correctly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    source: String,
+    pub field: String,
+    pub operator: Operator,
+    pub value: String,
+    pub layout: Layout,
+}
+
+impl Rule {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let (condition, layout) = source
+            .split_once("=>")
+            .or_else(|| source.split_once('→'))
+            .ok_or_else(|| format!("rule '{}' is missing '=> LAYOUT'", source))?;
+
+        let condition = condition.trim();
+        let (field, rest) = condition
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("rule '{}' must look like 'FIELD OPERATOR VALUE => LAYOUT'", source))?;
+        let rest = rest.trim_start();
+        let (operator, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+        if !PLACEHOLDERS.contains(&field) || FILE_NAME_ONLY_PLACEHOLDERS.contains(&field) {
+            return Err(format!("unknown field '{}' in rule '{}'", field, source));
+        }
+        let operator = Operator::parse(operator).ok_or_else(|| {
+            format!(
+                "unknown operator '{}' in rule '{}' (expected contains, is, startswith, endswith, > or <)",
+                operator, source
+            )
+        })?;
+
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        if value.is_empty() {
+            return Err(format!("rule '{}' has no value to compare against", source));
+        }
+
+        Ok(Self {
+            source: source.trim().to_string(),
+            field: field.to_string(),
+            operator,
+            value: value.to_string(),
+            layout: Layout::parse(layout.trim())?,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, actual: &str) -> bool {
+        let actual = actual.to_lowercase();
+        let expected = self.value.to_lowercase();
+
+        match self.operator {
+            Operator::Contains => actual.contains(&expected),
+            Operator::Is => actual == expected,
+            Operator::StartsWith => actual.starts_with(&expected),
+            Operator::EndsWith => actual.ends_with(&expected),
+            Operator::GreaterThan => compare(&actual, &expected) == Ordering::Greater,
+            Operator::LessThan => compare(&actual, &expected) == Ordering::Less,
+        }
+    }
+}
+
+fn compare(actual: &str, expected: &str) -> Ordering {
+    match (actual.trim().parse::<f64>(), expected.trim().parse::<f64>()) {
+        (Ok(actual), Ok(expected)) => actual.partial_cmp(&expected).unwrap_or(Ordering::Equal),
+        _ => actual.cmp(expected),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rule() {
+        let rule = Rule::parse("doc.author contains ACME => Clients/ACME").unwrap();
+        assert_eq!(rule.field, "doc.author");
+        assert_eq!(rule.operator, Operator::Contains);
+        assert_eq!(rule.value, "ACME");
+        assert_eq!(rule.layout.folder.as_str(), "Clients/ACME");
+
+        let quoted = Rule::parse("doc.title startswith \"Invoice \" → Finance/{doc.year}").unwrap();
+        assert_eq!(quoted.value, "Invoice ");
+        assert_eq!(quoted.layout.folder.as_str(), "Finance/{doc.year}");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Rule::parse("doc.author contains ACME").is_err());
+        assert!(Rule::parse("bogus is x => Somewhere").is_err());
+        assert!(Rule::parse("doc.author resembles x => Somewhere").is_err());
+        assert!(Rule::parse("doc.author is => Somewhere").is_err());
+        assert!(Rule::parse("counter > 3 => Somewhere").is_err());
+    }
+
+    #[test]
+    fn test_matches_text_case_insensitively() {
+        let rule = Rule::parse("doc.author contains acme => Clients/ACME").unwrap();
+        assert!(rule.matches("ACME Corp"));
+        assert!(!rule.matches("Initech"));
+
+        let rule = Rule::parse("ext is .PDF => PDFs").unwrap();
+        assert!(rule.matches(".pdf"));
+    }
+
+    #[test]
+    fn test_matches_numbers_and_dates() {
+        let rule = Rule::parse("doc.pages > 9 => Long").unwrap();
+        assert!(rule.matches("10"));
+        assert!(!rule.matches("9"));
+
+        let rule = Rule::parse("doc.created < 2020-01-01 => Archive").unwrap();
+        assert!(rule.matches("2019-12-31"));
+        assert!(!rule.matches("2020-06-01"));
+    }
+}
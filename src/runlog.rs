@@ -0,0 +1,86 @@
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A run log is rotated once it grows past this size.
+pub const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// Rotated logs kept next to the current one: `run.log.1` to `run.log.5`.
+pub const KEPT_LOGS: usize = 5;
+
+/// Sends standard output and error to the end of `path` for the rest of the
+/// process, rotating the file first if it is over `max_bytes`.
+#[cfg(unix)]
+pub fn redirect_output(path: &Path, max_bytes: u64, keep: usize) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::metadata(path).is_ok_and(|metadata| metadata.len() > max_bytes) {
+        rotate(path, keep)?;
+    }
+
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    for target in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(file.as_raw_fd(), target) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn redirect_output(_path: &Path, _max_bytes: u64, _keep: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "run logs are only supported on Unix"))
+}
+
+/// Shifts `run.log` to `run.log.1`, `run.log.1` to `run.log.2` and so on,
+/// dropping the oldest beyond `keep`.
+pub fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+
+    match fs::remove_file(numbered(path, keep)) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    for number in (1..keep).rev() {
+        match fs::rename(numbered(path, number), numbered(path, number + 1)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    fs::rename(path, numbered(path, 1))
+}
+
+fn numbered(path: &Path, number: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", number));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rotate_keeps_newest() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("run.log");
+
+        for run in 1..=4 {
+            fs::write(&log, format!("run {}", run)).unwrap();
+            rotate(&log, 2).unwrap();
+        }
+
+        assert!(!log.exists());
+        assert_eq!(fs::read_to_string(dir.path().join("run.log.1")).unwrap(), "run 4");
+        assert_eq!(fs::read_to_string(dir.path().join("run.log.2")).unwrap(), "run 3");
+        assert!(!dir.path().join("run.log.3").exists());
+    }
+}
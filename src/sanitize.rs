@@ -0,0 +1,237 @@
+use clap::ValueEnum;
+use unicode_normalization::UnicodeNormalization;
+
+/// Longest file name most filesystems accept, in bytes.
+pub const MAX_NAME_BYTES: usize = 255;
+
+/// Characters that are rejected by Windows and SMB shares.
+const ILLEGAL_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SanitizeStep {
+    /// Normalize to Unicode NFC
+    Nfc,
+    /// Strip control characters
+    Control,
+    /// Replace characters illegal on Windows/SMB
+    Illegal,
+    /// Collapse runs of whitespace and trim
+    Whitespace,
+    /// Truncate to 255 bytes, keeping the extension
+    Truncate,
+    /// Apply every step above
+    All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CaseStyle {
+    /// all lowercase
+    Lower,
+    /// lowercase-words-joined-by-dashes
+    Kebab,
+    /// lowercase_words_joined_by_underscores
+    Snake,
+}
+
+/// Optional renaming stage applied to each file before it is moved.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameSanitizer {
+    pub nfc: bool,
+    pub strip_control: bool,
+    pub replace_illegal: bool,
+    pub collapse_whitespace: bool,
+    pub truncate: bool,
+    pub case: Option<CaseStyle>,
+}
+
+impl NameSanitizer {
+    pub fn new(steps: &[SanitizeStep], case: Option<CaseStyle>) -> Self {
+        let mut sanitizer = Self {
+            case,
+            ..Self::default()
+        };
+
+        for step in steps {
+            match step {
+                SanitizeStep::Nfc => sanitizer.nfc = true,
+                SanitizeStep::Control => sanitizer.strip_control = true,
+                SanitizeStep::Illegal => sanitizer.replace_illegal = true,
+                SanitizeStep::Whitespace => sanitizer.collapse_whitespace = true,
+                SanitizeStep::Truncate => sanitizer.truncate = true,
+                SanitizeStep::All => {
+                    sanitizer.nfc = true;
+                    sanitizer.strip_control = true;
+                    sanitizer.replace_illegal = true;
+                    sanitizer.collapse_whitespace = true;
+                    sanitizer.truncate = true;
+                }
+            }
+        }
+
+        sanitizer
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Returns the cleaned-up name. `extension` is the one found by the
+    /// scanner and is kept intact when the name has to be shortened.
+    pub fn apply(&self, name: &str, extension: Option<&str>) -> String {
+        let (stem, ext) = split_name(name, extension);
+        let mut stem = stem.to_string();
+        let mut ext = ext.map(str::to_string);
+
+        if self.nfc {
+            stem = stem.nfc().collect();
+            ext = ext.map(|e| e.nfc().collect());
+        }
+
+        if self.strip_control {
+            stem.retain(|c| !c.is_control());
+            if let Some(e) = ext.as_mut() {
+                e.retain(|c| !c.is_control());
+            }
+        }
+
+        if self.replace_illegal {
+            stem = replace_illegal(&stem);
+            ext = ext.map(|e| replace_illegal(&e));
+        }
+
+        if self.collapse_whitespace {
+            stem = stem.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+
+        if let Some(case) = self.case {
+            stem = apply_case(&stem, case);
+            ext = ext.map(|e| e.to_lowercase());
+        }
+
+        if self.replace_illegal {
+            // Windows silently drops trailing dots and spaces
+            stem = stem.trim_end_matches(['.', ' ']).to_string();
+        }
+
+        if stem.is_empty() {
+            stem = "unnamed".to_string();
+        }
+
+        let suffix = ext
+            .filter(|e| !e.is_empty())
+            .map(|e| format!(".{}", e))
+            .unwrap_or_default();
+
+        if self.truncate && stem.len() + suffix.len() > MAX_NAME_BYTES {
+            let budget = MAX_NAME_BYTES.saturating_sub(suffix.len()).max(1);
+            stem = truncate_to_boundary(&stem, budget).to_string();
+        }
+
+        format!("{}{}", stem, suffix)
+    }
+}
+
+fn split_name<'a>(name: &'a str, extension: Option<&str>) -> (&'a str, Option<&'a str>) {
+    match extension {
+        Some(ext) if name.len() > ext.len() + 1 && name.ends_with(ext) => {
+            let stem_len = name.len() - ext.len() - 1;
+            (&name[..stem_len], Some(&name[stem_len + 1..]))
+        }
+        _ => (name, None),
+    }
+}
+
+fn replace_illegal(text: &str) -> String {
+    text.chars()
+        .map(|c| if ILLEGAL_CHARS.contains(&c) { '_' } else { c })
+        .collect()
+}
+
+fn apply_case(stem: &str, case: CaseStyle) -> String {
+    let separator = match case {
+        CaseStyle::Lower => return stem.to_lowercase(),
+        CaseStyle::Kebab => "-",
+        CaseStyle::Snake => "_",
+    };
+
+    stem.split(|c: char| c.is_whitespace() || c == '-' || c == '_' || c == '.')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+pub fn truncate_to_boundary(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> NameSanitizer {
+        NameSanitizer::new(&[SanitizeStep::All], None)
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let sanitizer = NameSanitizer::default();
+        assert!(!sanitizer.is_enabled());
+        assert_eq!(sanitizer.apply("a:b ?.txt", Some("txt")), "a:b ?.txt");
+    }
+
+    #[test]
+    fn test_nfc_normalization() {
+        let sanitizer = NameSanitizer::new(&[SanitizeStep::Nfc], None);
+        // "e" followed by a combining acute accent
+        let decomposed = "cafe\u{301}.txt";
+        assert_eq!(sanitizer.apply(decomposed, Some("txt")), "caf\u{e9}.txt");
+    }
+
+    #[test]
+    fn test_strip_control_and_illegal() {
+        let name = "report\u{7}: draft?.pdf";
+        assert_eq!(all().apply(name, Some("pdf")), "report_ draft_.pdf");
+    }
+
+    #[test]
+    fn test_collapse_whitespace_and_trailing_space() {
+        assert_eq!(all().apply("  my   holiday  photo .jpg", Some("jpg")), "my holiday photo.jpg");
+    }
+
+    #[test]
+    fn test_case_styles() {
+        let kebab = NameSanitizer::new(&[], Some(CaseStyle::Kebab));
+        let snake = NameSanitizer::new(&[], Some(CaseStyle::Snake));
+        let lower = NameSanitizer::new(&[], Some(CaseStyle::Lower));
+
+        assert_eq!(kebab.apply("My Holiday_Photo.JPG", Some("JPG")), "my-holiday-photo.jpg");
+        assert_eq!(snake.apply("My Holiday-Photo.JPG", Some("JPG")), "my_holiday_photo.jpg");
+        assert_eq!(lower.apply("My Photo.JPG", Some("JPG")), "my photo.jpg");
+    }
+
+    #[test]
+    fn test_truncate_keeps_extension() {
+        let name = format!("{}.pdf", "é".repeat(200));
+        let result = all().apply(&name, Some("pdf"));
+
+        assert!(result.len() <= MAX_NAME_BYTES);
+        assert!(result.ends_with(".pdf"));
+        assert!(result.starts_with("é"));
+    }
+
+    #[test]
+    fn test_name_without_extension() {
+        assert_eq!(all().apply("  notes  ", None), "notes");
+        assert_eq!(all().apply("???", None), "___");
+        assert_eq!(all().apply("\u{1}", None), "unnamed");
+    }
+}
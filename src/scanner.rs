@@ -1,89 +1,489 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
+use clap::ValueEnum;
+
+use crate::audio_tags::{self, AudioTags};
 use crate::categories::{CategoryMapper, FileCategory};
+use crate::document_meta::{self, DocumentInfo};
+use crate::exif::{self, ExifData};
+use crate::ignore::IgnoreList;
+use crate::logging::{self, Level};
+use crate::mounts::{self, Mount};
+
+/// What the scanner does with symbolic links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SymlinkPolicy {
+    /// Leave every symlink where it is
+    Skip,
+    /// Move links to files (and dangling links) as links; their targets stay put
+    #[default]
+    MoveLink,
+    /// Organize the contents of linked files and descend into linked directories
+    Follow,
+}
 
 #[derive(Debug, Clone)]
 pub struct FileInfo {
     pub path: PathBuf,
     pub name: String,
+    /// Directory of the file relative to the scanned root, empty at the top
+    pub relative_dir: PathBuf,
+    /// The entry is a symbolic link rather than the file itself
+    pub is_symlink: bool,
     pub extension: Option<String>,
     pub category: FileCategory,
+    /// Folder inside the category folder, unless the layout is flat
+    pub subcategory: Option<&'static str>,
+    pub exif: Option<ExifData>,
+    pub tags: Option<AudioTags>,
+    pub document: Option<DocumentInfo>,
 }
 
 #[derive(Debug)]
 pub struct ScanResult {
     pub files: Vec<FileInfo>,
-    pub categorized: HashMap<FileCategory, Vec<FileInfo>>,
+    /// Indices into `files` per category
+    pub categorized: HashMap<FileCategory, Vec<usize>>,
     pub total_count: usize,
+    /// Symlinks whose target does not exist
+    pub dangling_links: Vec<PathBuf>,
+    /// Directories not descended into, with the reason
+    pub skipped_dirs: Vec<(PathBuf, String)>,
 }
 
 impl ScanResult {
     pub fn category_count(&self, category: &FileCategory) -> usize {
         self.categorized.get(category).map_or(0, |v| v.len())
     }
+
+    pub fn files_in(&self, category: &FileCategory) -> impl Iterator<Item = &FileInfo> {
+        self.categorized
+            .get(category)
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.files[index])
+    }
 }
 
 pub struct DirectoryScanner {
     mapper: CategoryMapper,
+    read_exif: bool,
+    read_audio_tags: bool,
+    read_documents: bool,
+    recursive: bool,
+    symlinks: SymlinkPolicy,
+    /// Directories never descended into, such as the output tree
+    excluded_dirs: Vec<PathBuf>,
+    /// Stay on the filesystem of the scanned root, like `find -xdev`
+    one_file_system: bool,
+    /// Filesystem types (from the mount table) not descended into
+    skipped_fs_types: Vec<String>,
+    /// Directories listed in the ignore file
+    ignored: IgnoreList,
+}
+
+/// Walk state shared by every directory of one scan.
+struct Walk {
+    dangling_links: Vec<PathBuf>,
+    skipped_dirs: Vec<(PathBuf, String)>,
+    /// (device, inode) of every directory entered, to break symlink loops
+    visited: HashSet<(u64, u64)>,
+    /// (device, inode) of the excluded directories
+    excluded: HashSet<(u64, u64)>,
+    excluded_paths: Vec<PathBuf>,
+    /// Device of the scanned root
+    root_device: Option<u64>,
+    /// Mount table, read only when filesystem types are skipped
+    mounts: Vec<Mount>,
+    /// Skipped filesystem type per device, looked up once per device
+    device_fs_types: HashMap<u64, Option<String>>,
+}
+
+/// What one directory entry turned out to be.
+enum Visit {
+    File(Box<FileInfo>),
+    /// A directory to walk once the current one is done: path, relative path
+    Directory(PathBuf, PathBuf),
+    Nothing,
+}
+
+/// One directory of the walk.
+struct Frame {
+    path: PathBuf,
+    relative_dir: PathBuf,
+    /// Open while the directory is being read
+    entries: Option<fs::ReadDir>,
+    /// Subdirectories found so far, walked after the files of this one
+    subdirs: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Frame {
+    fn new(path: PathBuf, relative_dir: PathBuf) -> Self {
+        Self {
+            path,
+            relative_dir,
+            entries: None,
+            subdirs: Vec::new(),
+        }
+    }
+}
+
+/// Files of a directory tree, yielded as the walk finds them.
+///
+/// The files of a directory come before those of its subdirectories, and
+/// only one directory is open at a time.
+pub struct ScanIter<'a> {
+    scanner: &'a DirectoryScanner,
+    /// Directories still to finish, innermost last
+    stack: Vec<Frame>,
+    walk: Walk,
+}
+
+impl ScanIter<'_> {
+    /// Symlinks found so far whose target does not exist.
+    pub fn dangling_links(&self) -> &[PathBuf] {
+        &self.walk.dangling_links
+    }
+
+    /// Directories not descended into so far, with the reason.
+    pub fn skipped_dirs(&self) -> &[(PathBuf, String)] {
+        &self.walk.skipped_dirs
+    }
+}
+
+impl Iterator for ScanIter<'_> {
+    type Item = FileInfo;
+
+    fn next(&mut self) -> Option<FileInfo> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            let entries = match &mut frame.entries {
+                Some(entries) => entries,
+                None => match fs::read_dir(&frame.path) {
+                    Ok(entries) => frame.entries.insert(entries),
+                    Err(e) => {
+                        // An unreadable subdirectory should not end the whole scan
+                        self.walk.skipped_dirs.push((frame.path.clone(), e.to_string()));
+                        self.stack.pop();
+                        continue;
+                    }
+                },
+            };
+
+            let entry = match entries.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => {
+                    // The rest of this directory cannot be read
+                    self.walk.skipped_dirs.push((frame.path.clone(), e.to_string()));
+                    self.finish_directory();
+                    continue;
+                }
+                None => {
+                    self.finish_directory();
+                    continue;
+                }
+            };
+
+            let relative_dir = frame.relative_dir.clone();
+            match self.scanner.visit_entry(&entry, &relative_dir, &mut self.walk) {
+                Visit::File(file_info) => return Some(*file_info),
+                Visit::Directory(path, relative_dir) => frame.subdirs.push((path, relative_dir)),
+                Visit::Nothing => {}
+            }
+        }
+    }
+}
+
+impl ScanIter<'_> {
+    /// Closes the innermost directory and queues its subdirectories, in the
+    /// order they were found.
+    fn finish_directory(&mut self) {
+        if let Some(frame) = self.stack.pop() {
+            for (path, relative_dir) in frame.subdirs.into_iter().rev() {
+                self.stack.push(Frame::new(path, relative_dir));
+            }
+        }
+    }
 }
 
 impl DirectoryScanner {
     pub fn new(mapper: CategoryMapper) -> Self {
-        Self { mapper }
+        Self {
+            mapper,
+            read_exif: false,
+            read_audio_tags: false,
+            read_documents: false,
+            recursive: false,
+            symlinks: SymlinkPolicy::MoveLink,
+            excluded_dirs: Vec::new(),
+            one_file_system: false,
+            skipped_fs_types: Vec::new(),
+            ignored: IgnoreList::default(),
+        }
     }
 
-    pub fn scan(&self, source_dir: &Path) -> io::Result<ScanResult> {
-        let mut files = Vec::new();
-        let mut categorized: HashMap<FileCategory, Vec<FileInfo>> = HashMap::new();
+    /// Descends into subdirectories. Hidden directories are skipped.
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
 
-        self.scan_directory(source_dir, &mut files)?;
+    pub fn with_symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Directories the recursive scan must not enter.
+    pub fn with_excluded_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.excluded_dirs = dirs;
+        self
+    }
+
+    /// Does not descend into directories on another device than the root,
+    /// such as mounted shares or bind mounts.
+    pub fn with_one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
 
-        for file in &files {
-            categorized
-                .entry(file.category.clone())
-                .or_default()
-                .push(file.clone());
+    /// Does not descend into filesystems of these types, e.g. `nfs` or
+    /// `fuse.sshfs`. `fuse` covers every `fuse.*` type.
+    pub fn with_skipped_fs_types(mut self, fs_types: Vec<String>) -> Self {
+        self.skipped_fs_types = fs_types;
+        self
+    }
+
+    /// Directories the recursive scan leaves alone, from the ignore file.
+    pub fn with_ignored(mut self, ignored: IgnoreList) -> Self {
+        self.ignored = ignored;
+        self
+    }
+
+    /// Parses EXIF from images while scanning, for layouts that use it.
+    pub fn with_exif(mut self, read_exif: bool) -> Self {
+        self.read_exif = read_exif;
+        self
+    }
+
+    /// Parses ID3, Vorbis and MP4 tags from audio files while scanning.
+    pub fn with_audio_tags(mut self, read_audio_tags: bool) -> Self {
+        self.read_audio_tags = read_audio_tags;
+        self
+    }
+
+    /// Reads title, author, creation date and page count from PDF and
+    /// Office documents while scanning.
+    pub fn with_documents(mut self, read_documents: bool) -> Self {
+        self.read_documents = read_documents;
+        self
+    }
+
+    /// Scans the whole tree into memory. Use [`scan_iter`](Self::scan_iter)
+    /// to process files as they are found instead.
+    pub fn scan(&self, source_dir: &Path) -> io::Result<ScanResult> {
+        let mut iter = self.scan_iter(source_dir)?;
+        let files: Vec<FileInfo> = iter.by_ref().collect();
+
+        let mut categorized: HashMap<FileCategory, Vec<usize>> = HashMap::new();
+        for (index, file) in files.iter().enumerate() {
+            categorized.entry(file.category.clone()).or_default().push(index);
         }
 
         let total_count = files.len();
+        let Walk {
+            dangling_links,
+            skipped_dirs,
+            ..
+        } = iter.walk;
 
         Ok(ScanResult {
             files,
             categorized,
             total_count,
+            dangling_links,
+            skipped_dirs,
         })
     }
 
-    fn scan_directory(&self, dir: &Path, files: &mut Vec<FileInfo>) -> io::Result<()> {
-        if !dir.is_dir() {
+    /// Walks `source_dir` lazily, yielding each file as it is found. Only
+    /// the directories still being read are kept in memory. Dangling links
+    /// and skipped directories are available from the iterator afterwards.
+    pub fn scan_iter(&self, source_dir: &Path) -> io::Result<ScanIter<'_>> {
+        let mut walk = Walk {
+            dangling_links: Vec::new(),
+            skipped_dirs: Vec::new(),
+            visited: HashSet::new(),
+            excluded: HashSet::new(),
+            excluded_paths: self.excluded_dirs.clone(),
+            root_device: None,
+            mounts: Vec::new(),
+            device_fs_types: HashMap::new(),
+        };
+
+        if !source_dir.is_dir() {
             return Err(io::Error::new(
                 io::ErrorKind::NotADirectory,
-                format!("{} is not a directory", dir.display()),
+                format!("{} is not a directory", source_dir.display()),
             ));
         }
+        if let Some(id) = directory_id(&fs::metadata(source_dir)?) {
+            walk.visited.insert(id);
+            walk.root_device = Some(id.0);
+        }
+        if self.recursive && !self.skipped_fs_types.is_empty() {
+            walk.mounts = mounts::read_mounts()?;
+        }
+        // Compared by identity, so any spelling of the path matches
+        for dir in &self.excluded_dirs {
+            if let Some(id) = fs::metadata(dir).ok().as_ref().and_then(directory_id) {
+                walk.excluded.insert(id);
+            }
+        }
 
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
+        let mut root = Frame::new(source_dir.to_path_buf(), PathBuf::new());
+        root.entries = Some(fs::read_dir(source_dir)?);
+        Ok(ScanIter {
+            scanner: self,
+            stack: vec![root],
+            walk,
+        })
+    }
+
+    /// Looks at one directory entry: returns it if it is a file to organize,
+    /// and opens it for the walk if it is a directory to descend into.
+    fn visit_entry(&self, entry: &fs::DirEntry, relative_dir: &Path, walk: &mut Walk) -> Visit {
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => {
+                walk.skipped_dirs.push((path, e.to_string()));
+                return Visit::Nothing;
+            }
+        };
 
-            if path.is_file() {
-                if let Some(file_info) = self.process_file(&path) {
-                    files.push(file_info);
+        let (is_symlink, metadata) = if file_type.is_symlink() {
+            match fs::metadata(&path) {
+                Ok(metadata) => (true, metadata),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    walk.dangling_links.push(path.clone());
+                    // A dangling link can only be moved as a link
+                    if self.symlinks == SymlinkPolicy::MoveLink {
+                        if let Some(file_info) = self.process_file(&path, relative_dir, true) {
+                            return Visit::File(Box::new(file_info));
+                        }
+                    }
+                    return Visit::Nothing;
+                }
+                Err(e) => {
+                    walk.skipped_dirs.push((path, e.to_string()));
+                    return Visit::Nothing;
+                }
+            }
+        } else {
+            match entry.metadata() {
+                Ok(metadata) => (false, metadata),
+                Err(e) => {
+                    walk.skipped_dirs.push((path, e.to_string()));
+                    return Visit::Nothing;
                 }
             }
+        };
+
+        if is_symlink && self.symlinks == SymlinkPolicy::Skip {
+            logging::debug("skipped symlink", &[("path", &path.display()), ("reason", &"--symlinks skip")]);
+            return Visit::Nothing;
         }
 
-        Ok(())
+        if metadata.is_file() {
+            match self.process_file(&path, relative_dir, is_symlink) {
+                Some(file_info) => Visit::File(Box::new(file_info)),
+                None => Visit::Nothing,
+            }
+        } else if metadata.is_dir() && self.recursive {
+            // Linked directories are only entered when following links
+            if is_symlink && self.symlinks != SymlinkPolicy::Follow {
+                return Visit::Nothing;
+            }
+            let relative_dir = relative_dir.join(entry.file_name());
+            if self.should_enter(&path, &relative_dir, &metadata, walk) {
+                Visit::Directory(path, relative_dir)
+            } else {
+                Visit::Nothing
+            }
+        } else {
+            Visit::Nothing
+        }
     }
 
-    fn process_file(&self, path: &Path) -> Option<FileInfo> {
+    /// Whether the walk descends into a directory; reasons worth reporting
+    /// are added to the skipped directories.
+    fn should_enter(&self, path: &Path, relative_dir: &Path, metadata: &fs::Metadata, walk: &mut Walk) -> bool {
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        // Folders created after the scan started are only known by path
+        let excluded = walk.excluded_paths.iter().any(|excluded| excluded == path);
+        if hidden || excluded || self.ignored.is_ignored(relative_dir) {
+            let reason = if hidden {
+                "hidden"
+            } else if excluded {
+                "output folder"
+            } else {
+                "listed in the ignore file"
+            };
+            logging::trace("not entering directory", &[("path", &path.display()), ("reason", &reason)]);
+            return false;
+        }
+
+        if let Some(id) = directory_id(metadata) {
+            if walk.visited.contains(&id) {
+                walk.skipped_dirs.push((path.to_path_buf(), "directory loop (already scanned)".to_string()));
+                return false;
+            }
+            if walk.excluded.contains(&id) {
+                logging::trace("not entering directory", &[("path", &path.display()), ("reason", &"output folder")]);
+                return false;
+            }
+            if let Some(reason) = self.filesystem_skip_reason(path, id.0, walk) {
+                walk.skipped_dirs.push((path.to_path_buf(), reason));
+                return false;
+            }
+            walk.visited.insert(id);
+        }
+
+        true
+    }
+
+    /// Why a directory on `device` is not scanned, if it is on another
+    /// filesystem or one of the skipped filesystem types.
+    fn filesystem_skip_reason(&self, path: &Path, device: u64, walk: &mut Walk) -> Option<String> {
+        if walk.root_device == Some(device) {
+            return None;
+        }
+        if self.one_file_system {
+            return Some("on another filesystem (--one-file-system)".to_string());
+        }
+        if self.skipped_fs_types.is_empty() {
+            return None;
+        }
+
+        let mounts = &walk.mounts;
+        let skipped = walk.device_fs_types.entry(device).or_insert_with(|| {
+            let canonical = fs::canonicalize(path).ok()?;
+            mounts::fs_type_of(mounts, &canonical)
+                .filter(|fs_type| mounts::matches_fs_type(fs_type, &self.skipped_fs_types))
+                .map(str::to_string)
+        });
+        skipped.as_ref().map(|fs_type| format!("{} filesystem is skipped", fs_type))
+    }
+
+    fn process_file(&self, path: &Path, relative_dir: &Path, is_symlink: bool) -> Option<FileInfo> {
         let name = path.file_name()?.to_string_lossy().to_string();
 
         // Skip hidden files (starting with .)
         if name.starts_with('.') {
+            logging::trace("skipped hidden file", &[("path", &path.display())]);
             return None;
         }
 
@@ -95,16 +495,68 @@ impl DirectoryScanner {
             Some(ext) => self.mapper.categorize(ext),
             None => FileCategory::Other,
         };
+        let subcategory = extension.as_deref().and_then(|ext| self.mapper.subcategory(ext));
+        if logging::enabled(Level::Debug) {
+            let reason = match &extension {
+                Some(ext) if category != FileCategory::Other => format!("extension .{}", ext),
+                Some(ext) => format!("unknown extension .{}", ext),
+                None => "no extension".to_string(),
+            };
+            let file = path.display();
+            let category_name = category.folder_name();
+            let mut fields: Vec<logging::Field> = vec![("file", &file), ("category", &category_name)];
+            if let Some(subcategory) = &subcategory {
+                fields.push(("subcategory", subcategory));
+            }
+            fields.push(("reason", &reason));
+            logging::debug("categorized", &fields);
+        }
+
+        // Unreadable or malformed metadata just means no EXIF
+        let exif = if self.read_exif && category == FileCategory::Images {
+            exif::read_exif(path).ok().flatten()
+        } else {
+            None
+        };
+
+        let tags = if self.read_audio_tags && category == FileCategory::Audio {
+            audio_tags::read_tags(path).ok().flatten()
+        } else {
+            None
+        };
+
+        let document = if self.read_documents && category == FileCategory::Documents {
+            document_meta::read_document_info(path).ok().flatten()
+        } else {
+            None
+        };
 
         Some(FileInfo {
             path: path.to_path_buf(),
             name,
+            relative_dir: relative_dir.to_path_buf(),
+            is_symlink,
             extension,
             category,
+            subcategory,
+            exif,
+            tags,
+            document,
         })
     }
 }
 
+#[cfg(unix)]
+fn directory_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn directory_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,6 +591,21 @@ mod tests {
         assert_eq!(result.category_count(&FileCategory::Audio), 1);
     }
 
+    #[test]
+    fn test_subcategory_recorded() {
+        let dir = tempdir().unwrap();
+        File::create(dir.path().join("budget.xlsx")).unwrap();
+
+        let result = DirectoryScanner::new(CategoryMapper::new()).scan(dir.path()).unwrap();
+        assert_eq!(result.files[0].category, FileCategory::Documents);
+        assert_eq!(result.files[0].subcategory, Some("Spreadsheets"));
+
+        let flat = DirectoryScanner::new(CategoryMapper::new().with_subcategories(false))
+            .scan(dir.path())
+            .unwrap();
+        assert_eq!(flat.files[0].subcategory, None);
+    }
+
     #[test]
     fn test_skip_hidden_files() {
         let dir = tempdir().unwrap();
@@ -152,6 +619,136 @@ mod tests {
         assert_eq!(result.total_count, 1);
     }
 
+    #[test]
+    fn test_exif_read_only_when_enabled() {
+        let dir = tempdir().unwrap();
+        let tiff = crate::exif::tests::build_tiff("Canon", "EOS R6", "2023:07:14 09:30:00", false);
+        std::fs::write(dir.path().join("photo.jpg"), crate::exif::tests::build_jpeg(&tiff)).unwrap();
+
+        let plain = DirectoryScanner::new(CategoryMapper::new()).scan(dir.path()).unwrap();
+        assert!(plain.files[0].exif.is_none());
+
+        let with_exif = DirectoryScanner::new(CategoryMapper::new())
+            .with_exif(true)
+            .scan(dir.path())
+            .unwrap();
+        assert_eq!(with_exif.files[0].exif.as_ref().unwrap().model.as_deref(), Some("EOS R6"));
+    }
+
+    #[test]
+    fn test_document_info_read_only_when_enabled() {
+        let dir = tempdir().unwrap();
+        let pdf = crate::document_meta::tests::build_pdf("Offer", "ACME");
+        std::fs::write(dir.path().join("offer.pdf"), pdf).unwrap();
+
+        let plain = DirectoryScanner::new(CategoryMapper::new()).scan(dir.path()).unwrap();
+        assert!(plain.files[0].document.is_none());
+
+        let with_documents = DirectoryScanner::new(CategoryMapper::new())
+            .with_documents(true)
+            .scan(dir.path())
+            .unwrap();
+        let document = with_documents.files[0].document.as_ref().unwrap();
+        assert_eq!(document.author.as_deref(), Some("ACME"));
+        assert_eq!(document.pages, Some(2));
+    }
+
+    #[test]
+    fn test_recursive_scan() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("trip/day1")).unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::create_dir_all(dir.path().join("organized")).unwrap();
+        File::create(dir.path().join("top.txt")).unwrap();
+        File::create(dir.path().join("trip/day1/photo.jpg")).unwrap();
+        File::create(dir.path().join(".git/config")).unwrap();
+        File::create(dir.path().join("organized/done.txt")).unwrap();
+
+        let flat = DirectoryScanner::new(CategoryMapper::new()).scan(dir.path()).unwrap();
+        assert_eq!(flat.total_count, 1);
+
+        let result = DirectoryScanner::new(CategoryMapper::new())
+            .with_recursive(true)
+            .with_excluded_dirs(vec![dir.path().join("organized")])
+            .scan(dir.path())
+            .unwrap();
+        assert_eq!(result.total_count, 2);
+        let photo = result.files.iter().find(|file| file.name == "photo.jpg").unwrap();
+        assert_eq!(photo.relative_dir, PathBuf::from("trip/day1"));
+    }
+
+    #[test]
+    fn test_scan_iter_matches_scan() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("nested")).unwrap();
+        File::create(dir.path().join("photo.jpg")).unwrap();
+        File::create(dir.path().join("nested/song.mp3")).unwrap();
+        File::create(dir.path().join("nested/notes.txt")).unwrap();
+
+        let scanner = DirectoryScanner::new(CategoryMapper::new()).with_recursive(true);
+        let mut streamed: Vec<String> = scanner.scan_iter(dir.path()).unwrap().map(|file| file.name).collect();
+        let result = scanner.scan(dir.path()).unwrap();
+        let mut scanned: Vec<String> = result.files.iter().map(|file| file.name.clone()).collect();
+        streamed.sort();
+        scanned.sort();
+
+        assert_eq!(streamed, scanned);
+        let audio: Vec<&str> = result.files_in(&FileCategory::Audio).map(|file| file.name.as_str()).collect();
+        assert_eq!(audio, vec!["song.mp3"]);
+        assert_eq!(result.files_in(&FileCategory::Videos).count(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policies() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("real.txt"), "data").unwrap();
+        symlink(dir.path().join("real.txt"), dir.path().join("link.txt")).unwrap();
+        symlink(dir.path().join("missing.txt"), dir.path().join("dangling.txt")).unwrap();
+
+        let scan = |policy| {
+            DirectoryScanner::new(CategoryMapper::new())
+                .with_symlinks(policy)
+                .scan(dir.path())
+                .unwrap()
+        };
+
+        let skip = scan(SymlinkPolicy::Skip);
+        assert_eq!(skip.total_count, 1);
+        assert_eq!(skip.dangling_links, vec![dir.path().join("dangling.txt")]);
+
+        let move_link = scan(SymlinkPolicy::MoveLink);
+        assert_eq!(move_link.total_count, 3);
+        assert!(move_link.files.iter().filter(|file| file.is_symlink).count() == 2);
+
+        let follow = scan(SymlinkPolicy::Follow);
+        assert_eq!(follow.total_count, 2);
+        assert_eq!(follow.dangling_links.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_loop_detected() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a")).unwrap();
+        File::create(dir.path().join("a/file.txt")).unwrap();
+        symlink(dir.path(), dir.path().join("a/back-to-root")).unwrap();
+
+        let result = DirectoryScanner::new(CategoryMapper::new())
+            .with_recursive(true)
+            .with_symlinks(SymlinkPolicy::Follow)
+            .scan(dir.path())
+            .unwrap();
+
+        assert_eq!(result.total_count, 1);
+        assert_eq!(result.skipped_dirs.len(), 1);
+        assert!(result.skipped_dirs[0].1.contains("loop"));
+    }
+
     #[test]
     fn test_file_without_extension() {
         let dir = tempdir().unwrap();
@@ -0,0 +1,323 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use crate::exitcode;
+
+/// First line of every unit file we write, so `list` and `remove` never
+/// touch units that belong to something else.
+const MARKER: &str = "# Generated by file-organizer schedule install";
+
+/// Prefix of the unit names: `file-organizer-<name>.service` and `.timer`.
+const UNIT_PREFIX: &str = "file-organizer-";
+
+/// Where scheduled runs log, as chosen on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogTarget {
+    /// The systemd journal
+    #[default]
+    Journal,
+    /// A log file rotated as it grows
+    File,
+}
+
+/// Where a scheduled run writes its output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobLog {
+    /// Standard output and error go to the systemd journal
+    Journal,
+    /// Appended to this file, which is rotated as it grows
+    File(PathBuf),
+}
+
+/// A scheduled run: what to run and when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub name: String,
+    /// A systemd calendar expression, such as `daily` or `Mon *-*-* 02:00`
+    pub on_calendar: String,
+    /// Program and arguments of the run
+    pub command: Vec<String>,
+    pub log: JobLog,
+}
+
+/// An installed job as found in the unit directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledJob {
+    pub name: String,
+    pub on_calendar: String,
+    /// The `ExecStart` line as written in the service
+    pub command: String,
+}
+
+/// `~/.config/systemd/user`, or the same under `$XDG_CONFIG_HOME`.
+pub fn user_unit_dir() -> io::Result<PathBuf> {
+    Ok(config_home()?.join("systemd/user"))
+}
+
+/// Default log file of a job: `~/.local/state/file-organizer/<name>.log`, or
+/// the same under `$XDG_STATE_HOME`.
+pub fn default_log_path(name: &str) -> io::Result<PathBuf> {
+    let state_home = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir()?.join(".local/state"),
+    };
+    Ok(state_home.join("file-organizer").join(format!("{}.log", name)))
+}
+
+fn config_home() -> io::Result<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => Ok(home_dir()?.join(".config")),
+    }
+}
+
+fn home_dir() -> io::Result<PathBuf> {
+    match env::var_os("HOME") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => Err(io::Error::new(io::ErrorKind::NotFound, "HOME is not set")),
+    }
+}
+
+/// Job names end up in unit names, so they are kept to letters, digits,
+/// `-` and `_`.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!(
+            "invalid job name '{}': use letters, digits, '-' and '_' only",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn service_name(name: &str) -> String {
+    format!("{}{}.service", UNIT_PREFIX, name)
+}
+
+pub fn timer_name(name: &str) -> String {
+    format!("{}{}.timer", UNIT_PREFIX, name)
+}
+
+impl Job {
+    pub fn service_unit(&self) -> String {
+        let command: Vec<String> = self.command.iter().map(|arg| quote_exec_arg(arg)).collect();
+        // Only failed files and fatal errors mark the service as failed
+        let mut unit = format!(
+            "{}\n[Unit]\nDescription=file-organizer job '{}'\n\n[Service]\nType=oneshot\nExecStart={}\nSuccessExitStatus={} {}\n",
+            MARKER,
+            self.name,
+            command.join(" "),
+            exitcode::NOTHING_TO_DO,
+            exitcode::SKIPPED
+        );
+        match &self.log {
+            JobLog::Journal => unit.push_str(&format!(
+                "StandardOutput=journal\nStandardError=journal\nSyslogIdentifier={}{}\n",
+                UNIT_PREFIX, self.name
+            )),
+            // The run rotates and appends to the file itself
+            JobLog::File(_) => unit.push_str("StandardOutput=null\nStandardError=journal\n"),
+        }
+        unit
+    }
+
+    pub fn timer_unit(&self) -> String {
+        format!(
+            "{}\n[Unit]\nDescription=Run file-organizer job '{}'\n\n[Timer]\nOnCalendar={}\nPersistent=true\n\n[Install]\nWantedBy=timers.target\n",
+            MARKER, self.name, self.on_calendar
+        )
+    }
+
+    /// Writes the service and timer into `unit_dir`, replacing an earlier
+    /// version of the same job. Returns the paths written.
+    pub fn install(&self, unit_dir: &Path) -> io::Result<(PathBuf, PathBuf)> {
+        fs::create_dir_all(unit_dir)?;
+        let service = unit_dir.join(service_name(&self.name));
+        let timer = unit_dir.join(timer_name(&self.name));
+        for path in [&service, &timer] {
+            if path.exists() && !is_ours(path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and was not written by file-organizer", path.display()),
+                ));
+            }
+        }
+
+        fs::write(&service, self.service_unit())?;
+        fs::write(&timer, self.timer_unit())?;
+        Ok((service, timer))
+    }
+}
+
+/// Jobs installed in `unit_dir`, sorted by name.
+pub fn list(unit_dir: &Path) -> io::Result<Vec<InstalledJob>> {
+    let entries = match fs::read_dir(unit_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut jobs = Vec::new();
+    for entry in entries {
+        let file_name = entry?.file_name().to_string_lossy().into_owned();
+        let name = match file_name.strip_prefix(UNIT_PREFIX).and_then(|rest| rest.strip_suffix(".timer")) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let timer = match read_ours(&unit_dir.join(&file_name))? {
+            Some(timer) => timer,
+            None => continue,
+        };
+        let service = read_ours(&unit_dir.join(service_name(&name)))?.unwrap_or_default();
+
+        jobs.push(InstalledJob {
+            on_calendar: unit_value(&timer, "OnCalendar").unwrap_or_default(),
+            command: unit_value(&service, "ExecStart").unwrap_or_default(),
+            name,
+        });
+    }
+    jobs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(jobs)
+}
+
+/// Deletes the units of job `name`, and the link that enables its timer.
+/// Returns the files removed.
+pub fn remove(unit_dir: &Path, name: &str) -> io::Result<Vec<PathBuf>> {
+    let units = [unit_dir.join(service_name(name)), unit_dir.join(timer_name(name))];
+    if !units.iter().any(|path| is_ours(path)) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no scheduled job named '{}'", name),
+        ));
+    }
+
+    let mut removed = Vec::new();
+    let enabled = unit_dir.join("timers.target.wants").join(timer_name(name));
+    if fs::symlink_metadata(&enabled).is_ok() {
+        fs::remove_file(&enabled)?;
+        removed.push(enabled);
+    }
+    for path in units {
+        if is_ours(&path) {
+            fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
+
+fn is_ours(path: &Path) -> bool {
+    matches!(read_ours(path), Ok(Some(_)))
+}
+
+fn read_ours(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) if contents.starts_with(MARKER) => Ok(Some(contents)),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn unit_value(contents: &str, key: &str) -> Option<String> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+        .map(str::to_string)
+}
+
+/// Quotes an argument for `ExecStart`: `%` and `$` are doubled so systemd
+/// does not expand them, and arguments with spaces or quotes are put in
+/// double quotes.
+fn quote_exec_arg(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty() && !escaped.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';')) {
+        return escaped;
+    }
+    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn job(name: &str, log: JobLog) -> Job {
+        Job {
+            name: name.to_string(),
+            on_calendar: "daily".to_string(),
+            command: vec![
+                "/usr/bin/file-organizer".to_string(),
+                "--source".to_string(),
+                "/srv/shared/My Files".to_string(),
+                "--name-template".to_string(),
+                "{date:%Y}-{name}".to_string(),
+            ],
+            log,
+        }
+    }
+
+    #[test]
+    fn test_units() {
+        let service = job("nightly", JobLog::Journal).service_unit();
+        assert!(service.starts_with(MARKER));
+        assert!(service.contains(
+            "\nExecStart=/usr/bin/file-organizer --source \"/srv/shared/My Files\" --name-template {date:%%Y}-{name}\n"
+        ));
+        assert!(service.contains("\nSuccessExitStatus=3 4\n"));
+        assert!(service.contains("\nSyslogIdentifier=file-organizer-nightly\n"));
+
+        let logged = job("nightly", JobLog::File(PathBuf::from("/tmp/x.log"))).service_unit();
+        assert!(logged.contains("\nStandardOutput=null\n"));
+
+        let timer = job("nightly", JobLog::Journal).timer_unit();
+        assert!(timer.contains("\nOnCalendar=daily\nPersistent=true\n"));
+        assert!(timer.contains("\nWantedBy=timers.target\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_list_remove() {
+        let dir = tempdir().unwrap();
+        job("nightly", JobLog::Journal).install(dir.path()).unwrap();
+        job("hourly-scans", JobLog::Journal).install(dir.path()).unwrap();
+        // Units of other programs are left alone
+        fs::write(dir.path().join("file-organizer-other.timer"), "[Timer]\n").unwrap();
+
+        let jobs = list(dir.path()).unwrap();
+        let names: Vec<&str> = jobs.iter().map(|job| job.name.as_str()).collect();
+        assert_eq!(names, vec!["hourly-scans", "nightly"]);
+        assert_eq!(jobs[1].on_calendar, "daily");
+        assert!(jobs[1].command.starts_with("/usr/bin/file-organizer --source"));
+
+        let wants = dir.path().join("timers.target.wants");
+        fs::create_dir(&wants).unwrap();
+        std::os::unix::fs::symlink(dir.path().join(timer_name("nightly")), wants.join(timer_name("nightly"))).unwrap();
+
+        let removed = remove(dir.path(), "nightly").unwrap();
+        assert_eq!(removed.len(), 3);
+        assert_eq!(list(dir.path()).unwrap().len(), 1);
+        assert_eq!(remove(dir.path(), "nightly").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(remove(dir.path(), "other").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_install_keeps_foreign_units() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("file-organizer-nightly.service"), "[Service]\n").unwrap();
+
+        let error = job("nightly", JobLog::Journal).install(dir.path()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("nightly_2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../x").is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+/// How overflowing folders are split.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ShardScheme {
+    /// Numbered folders filled one after another: 0001, 0002, ...
+    #[default]
+    Number,
+    /// Folders by first letter: A-C, D-F, ..., 0-9 and # for the rest
+    Alpha,
+}
+
+/// First-letter ranges for [`ShardScheme::Alpha`].
+const ALPHA_BUCKETS: [(char, char); 8] = [
+    ('A', 'C'),
+    ('D', 'F'),
+    ('G', 'I'),
+    ('J', 'L'),
+    ('M', 'O'),
+    ('P', 'R'),
+    ('S', 'U'),
+    ('V', 'Z'),
+];
+
+/// Shard a new file goes into, per folder and bucket.
+#[derive(Debug, Clone, Copy)]
+struct Shard {
+    sequence: u32,
+    entries: usize,
+}
+
+/// Caps the number of entries per folder.
+///
+/// Shard subfolders count as entries of their folder, so a folder takes
+/// files directly while it has room for them and for a shard folder; after
+/// that new files go into shard subfolders, each filled up to the same cap
+/// before the next is started. Files already on disk are never moved and
+/// only the newest shard receives files, so what is in a shard stays there
+/// from one run to the next. A name that is already in the folder or one
+/// of its shards is sent back there, where it meets the conflict handling
+/// instead of being copied into a new shard.
+///
+/// Letter buckets are folders too: the folder keeps room for all of them,
+/// each new one counts against the cap, and once only one entry is left
+/// the files go into numbered shards instead.
+#[derive(Debug)]
+pub struct Sharder {
+    max_entries: usize,
+    scheme: ShardScheme,
+    /// Entries directly in each folder, counted once and then tracked
+    loose: HashMap<PathBuf, usize>,
+    /// Newest shard per folder and bucket
+    shards: HashMap<(PathBuf, String), Shard>,
+    /// Names found in the shards each folder had before the run
+    existing: HashMap<PathBuf, HashMap<String, PathBuf>>,
+}
+
+impl Sharder {
+    pub fn new(max_entries: usize, scheme: ShardScheme) -> Self {
+        Self {
+            max_entries: max_entries.max(1),
+            scheme,
+            loose: HashMap::new(),
+            shards: HashMap::new(),
+            existing: HashMap::new(),
+        }
+    }
+
+    /// Folder under `base` that the file `name` goes into.
+    pub fn assign(&mut self, base: &Path, name: &str) -> PathBuf {
+        if fs::symlink_metadata(base.join(name)).is_ok() {
+            return base.to_path_buf();
+        }
+        let scheme = self.scheme;
+        let existing = self
+            .existing
+            .entry(base.to_path_buf())
+            .or_insert_with(|| names_in_shards(base, scheme));
+        if let Some(shard) = existing.get(name) {
+            return shard.clone();
+        }
+
+        let loose = self.loose.entry(base.to_path_buf()).or_insert_with(|| count_entries(base));
+        // Leave room for the shard folders that take the overflow
+        let reserved = match self.scheme {
+            ShardScheme::Number => 1,
+            ShardScheme::Alpha => ALPHA_BUCKETS.len() + 3,
+        };
+        if *loose + reserved < self.max_entries {
+            *loose += 1;
+            return base.to_path_buf();
+        }
+
+        if self.scheme == ShardScheme::Alpha {
+            if let Some(folder) = self.next_shard(base, &alpha_bucket(name), true) {
+                return folder;
+            }
+        }
+        // Numbered shards take what the letter buckets have no room for
+        self.next_shard(base, "", false).expect("numbered shards always have room")
+    }
+
+    /// Shard of `bucket` in `base` for one more file. With `keep_room`, a
+    /// new shard folder is only started while `base` still has room for it
+    /// and a numbered shard; otherwise this is `None`.
+    fn next_shard(&mut self, base: &Path, bucket: &str, keep_room: bool) -> Option<PathBuf> {
+        let max_entries = self.max_entries;
+        let loose = self.loose.entry(base.to_path_buf()).or_insert_with(|| count_entries(base));
+        let shard = self
+            .shards
+            .entry((base.to_path_buf(), bucket.to_string()))
+            .or_insert_with(|| newest_shard(base, bucket));
+
+        let next = if shard.entries >= max_entries {
+            Shard {
+                sequence: shard.sequence + 1,
+                entries: 0,
+            }
+        } else {
+            *shard
+        };
+        let folder = base.join(shard_name(bucket, next.sequence));
+        if next.entries == 0 && !folder.exists() {
+            if keep_room && *loose + 1 >= max_entries {
+                return None;
+            }
+            *loose += 1;
+        }
+        *shard = Shard {
+            entries: next.entries + 1,
+            ..next
+        };
+        Some(folder)
+    }
+}
+
+/// Whether `name` is a shard folder of `scheme`, in any bucket.
+fn is_shard_name(scheme: ShardScheme, name: &str) -> bool {
+    match scheme {
+        ShardScheme::Number => parse_shard_name("", name).is_some(),
+        ShardScheme::Alpha => ALPHA_BUCKETS
+            .iter()
+            .map(|(start, end)| format!("{}-{}", start, end))
+            .chain(["0-9".to_string(), "#".to_string(), String::new()])
+            .any(|bucket| parse_shard_name(&bucket, name).is_some()),
+    }
+}
+
+/// The shard folder of each name in the existing shards of `base`.
+fn names_in_shards(base: &Path, scheme: ShardScheme) -> HashMap<String, PathBuf> {
+    let mut names = HashMap::new();
+    let shards = fs::read_dir(base)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter(|entry| is_shard_name(scheme, &entry.file_name().to_string_lossy()));
+    for shard in shards {
+        for entry in fs::read_dir(shard.path()).into_iter().flatten().filter_map(Result::ok) {
+            names.insert(entry.file_name().to_string_lossy().into_owned(), shard.path());
+        }
+    }
+    names
+}
+
+/// `0001` for numbered shards; `A-C`, `A-C-2`, ... for letter buckets.
+fn shard_name(bucket: &str, sequence: u32) -> String {
+    match (bucket, sequence) {
+        ("", sequence) => format!("{:04}", sequence),
+        (bucket, 1) => bucket.to_string(),
+        (bucket, sequence) => format!("{}-{}", bucket, sequence),
+    }
+}
+
+/// Sequence number of `name` if it is a shard of `bucket`.
+fn parse_shard_name(bucket: &str, name: &str) -> Option<u32> {
+    if bucket.is_empty() {
+        return if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) {
+            name.parse().ok()
+        } else {
+            None
+        };
+    }
+    match name.strip_prefix(bucket)? {
+        "" => Some(1),
+        rest => rest.strip_prefix('-')?.parse().ok().filter(|sequence| *sequence > 1),
+    }
+}
+
+/// The highest-numbered existing shard of `bucket` in `base`, or a fresh
+/// first shard.
+fn newest_shard(base: &Path, bucket: &str) -> Shard {
+    let sequence = fs::read_dir(base)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter_map(|entry| parse_shard_name(bucket, &entry.file_name().to_string_lossy()))
+        .max();
+
+    match sequence {
+        Some(sequence) => Shard {
+            sequence,
+            entries: count_entries(&base.join(shard_name(bucket, sequence))),
+        },
+        None => Shard { sequence: 1, entries: 0 },
+    }
+}
+
+fn alpha_bucket(name: &str) -> String {
+    let first = name.chars().next().unwrap_or('#').to_ascii_uppercase();
+    if first.is_ascii_digit() {
+        return "0-9".to_string();
+    }
+    ALPHA_BUCKETS
+        .iter()
+        .find(|(start, end)| (*start..=*end).contains(&first))
+        .map(|(start, end)| format!("{}-{}", start, end))
+        .unwrap_or_else(|| "#".to_string())
+}
+
+/// Visible entries in `dir`; a missing folder is empty.
+fn count_entries(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_numbered_shards_fill_in_order() {
+        let dir = tempdir().unwrap();
+        let mut sharder = Sharder::new(2, ShardScheme::Number);
+
+        let folders: Vec<PathBuf> = (0..7).map(|i| sharder.assign(dir.path(), &format!("{}.jpg", i))).collect();
+
+        let base = dir.path().to_path_buf();
+        assert_eq!(
+            folders,
+            vec![
+                base.clone(),
+                base.join("0001"),
+                base.join("0001"),
+                base.join("0002"),
+                base.join("0002"),
+                base.join("0003"),
+                base.join("0003"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_continues_in_newest_existing_shard() {
+        let dir = tempdir().unwrap();
+        for name in ["a.jpg", "b.jpg", "0001/c.jpg", "0001/d.jpg", "0002/e.jpg"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        // The top folder holds a, b, 0001 and 0002
+        let mut sharder = Sharder::new(2, ShardScheme::Number);
+        assert_eq!(sharder.assign(dir.path(), "f.jpg"), dir.path().join("0002"));
+        assert_eq!(sharder.assign(dir.path(), "g.jpg"), dir.path().join("0003"));
+    }
+
+    #[test]
+    fn test_existing_names_keep_their_folder() {
+        let dir = tempdir().unwrap();
+        for name in ["a.jpg", "0001/b.jpg", "0001/c.jpg", "0002/d.jpg", "misc/e.jpg"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let mut sharder = Sharder::new(2, ShardScheme::Number);
+        assert_eq!(sharder.assign(dir.path(), "a.jpg"), dir.path());
+        assert_eq!(sharder.assign(dir.path(), "b.jpg"), dir.path().join("0001"));
+        assert_eq!(sharder.assign(dir.path(), "d.jpg"), dir.path().join("0002"));
+        // misc is not a shard, so e.jpg is new here
+        assert_eq!(sharder.assign(dir.path(), "e.jpg"), dir.path().join("0002"));
+    }
+
+    #[test]
+    fn test_alpha_buckets() {
+        let dir = tempdir().unwrap();
+        let mut sharder = Sharder::new(6, ShardScheme::Alpha);
+
+        // A cap this small leaves no room for files next to the buckets
+        assert_eq!(sharder.assign(dir.path(), "first.jpg"), dir.path().join("D-F"));
+        assert_eq!(sharder.assign(dir.path(), "beach.jpg"), dir.path().join("A-C"));
+        assert_eq!(sharder.assign(dir.path(), "castle.jpg"), dir.path().join("A-C"));
+        assert_eq!(sharder.assign(dir.path(), "zebra.jpg"), dir.path().join("V-Z"));
+        assert_eq!(sharder.assign(dir.path(), "2024.jpg"), dir.path().join("0-9"));
+        assert_eq!(sharder.assign(dir.path(), "_misc.jpg"), dir.path().join("#"));
+        // The last free entry is kept for the numbered overflow
+        assert_eq!(sharder.assign(dir.path(), "moon.jpg"), dir.path().join("0001"));
+        assert_eq!(sharder.assign(dir.path(), "pear.jpg"), dir.path().join("0001"));
+    }
+
+    #[test]
+    fn test_alpha_buckets_stay_under_cap() {
+        let dir = tempdir().unwrap();
+        let mut sharder = Sharder::new(3, ShardScheme::Alpha);
+
+        // More initials than the folder has entries for
+        for name in ["apple.jpg", "delta.jpg", "golf.jpg", "juliet.jpg", "mike.jpg"] {
+            let folder = sharder.assign(dir.path(), name);
+            fs::create_dir_all(&folder).unwrap();
+            fs::write(folder.join(name), "").unwrap();
+        }
+
+        assert_eq!(count_entries(dir.path()), 3);
+        for entry in fs::read_dir(dir.path()).unwrap() {
+            assert!(count_entries(&entry.unwrap().path()) <= 3);
+        }
+        assert!(dir.path().join("0001/golf.jpg").exists());
+
+        // A later run sees the overflow shard and keeps using it
+        let mut sharder = Sharder::new(3, ShardScheme::Alpha);
+        assert_eq!(sharder.assign(dir.path(), "juliet.jpg"), dir.path().join("0001"));
+        assert_eq!(sharder.assign(dir.path(), "beta.jpg"), dir.path().join("A-C"));
+    }
+
+    #[test]
+    fn test_parse_shard_name() {
+        assert_eq!(parse_shard_name("", "0042"), Some(42));
+        assert_eq!(parse_shard_name("", "A-C"), None);
+        assert_eq!(parse_shard_name("A-C", "A-C"), Some(1));
+        assert_eq!(parse_shard_name("A-C", "A-C-3"), Some(3));
+        assert_eq!(parse_shard_name("A-C", "A-Cx"), None);
+    }
+}
@@ -0,0 +1,36 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once SIGINT or SIGTERM arrives. The organizer finishes the file it is
+/// working on and stops before the next one.
+pub static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the SIGINT and SIGTERM handlers. The handlers reset themselves,
+/// so a second Ctrl-C terminates the process immediately.
+#[cfg(unix)]
+pub fn install_stop_handlers() -> io::Result<()> {
+    extern "C" fn request_stop(_signal: libc::c_int) {
+        STOP_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = request_stop as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND | libc::SA_RESTART;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install_stop_handlers() -> io::Result<()> {
+    Ok(())
+}
+
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
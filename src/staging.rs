@@ -0,0 +1,158 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::copy::{self, CopyStats, ReflinkMode};
+
+/// Prefix of the hidden files cross-device copies are written to.
+pub const STAGING_PREFIX: &str = ".file-organizer-staging.";
+
+/// Temporary name for a copy into `destination`'s directory. One copy runs
+/// at a time, so the PID is enough to keep it unique.
+pub fn staging_path(destination: &Path) -> PathBuf {
+    let name = format!("{}{}.tmp", STAGING_PREFIX, std::process::id());
+    match destination.parent() {
+        Some(parent) => parent.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+/// Copies `source` to `destination` so that `destination` either does not
+/// exist or is complete: the data goes to a staging file first, is flushed
+/// to disk, and is then linked into place without replacing anything.
+pub fn copy_staged(source: &Path, destination: &Path, reflink: ReflinkMode) -> io::Result<CopyStats> {
+    let staged = staging_path(destination);
+    // Left over from an earlier process that had the same PID
+    let _ = fs::remove_file(&staged);
+
+    let result = write_staged(source, &staged, reflink).and_then(|stats| {
+        commit(&staged, destination)?;
+        Ok(stats)
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    result
+}
+
+fn write_staged(source: &Path, staged: &Path, reflink: ReflinkMode) -> io::Result<CopyStats> {
+    let input = File::open(source)?;
+    let output = OpenOptions::new().write(true).create_new(true).open(staged)?;
+
+    let stats = copy::copy_contents(&input, &output, reflink)?;
+    output.set_permissions(input.metadata()?.permissions())?;
+    output.sync_all()?;
+    Ok(stats)
+}
+
+/// Moves the staged file to its final name, failing if the name is taken.
+fn commit(staged: &Path, destination: &Path) -> io::Result<()> {
+    match fs::hard_link(staged, destination) {
+        Ok(()) => fs::remove_file(staged)?,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("destination '{}' already exists", destination.display()),
+            ));
+        }
+        // Filesystems without hard links: the lock keeps other runs out, so
+        // checking first is good enough
+        Err(_) => {
+            if destination.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("destination '{}' already exists", destination.display()),
+                ));
+            }
+            fs::rename(staged, destination)?;
+        }
+    }
+
+    if let Some(parent) = destination.parent() {
+        sync_directory(parent)?;
+    }
+    Ok(())
+}
+
+/// Makes the new directory entry durable.
+#[cfg(unix)]
+fn sync_directory(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Deletes staging files a crashed run left in `dir`.
+pub fn remove_stale(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(removed),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let is_staged = entry.file_name().to_string_lossy().starts_with(STAGING_PREFIX);
+        if is_staged && entry.file_type()?.is_file() {
+            fs::remove_file(entry.path())?;
+            removed.push(entry.path());
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_copy_staged() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source.bin");
+        let destination = dir.path().join("out/destination.bin");
+        fs::write(&source, "payload").unwrap();
+        fs::create_dir(dir.path().join("out")).unwrap();
+
+        let stats = copy_staged(&source, &destination, ReflinkMode::Auto).unwrap();
+
+        assert_eq!(stats.cloned_bytes + stats.copied_bytes, 7);
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "payload");
+        assert!(source.exists());
+        assert!(!staging_path(&destination).exists());
+    }
+
+    #[test]
+    fn test_copy_staged_never_overwrites() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source.bin");
+        let destination = dir.path().join("destination.bin");
+        fs::write(&source, "new").unwrap();
+        fs::write(&destination, "old").unwrap();
+
+        let error = copy_staged(&source, &destination, ReflinkMode::Auto).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+        assert!(!staging_path(&destination).exists());
+    }
+
+    #[test]
+    fn test_remove_stale() {
+        let dir = tempdir().unwrap();
+        let stale = dir.path().join(format!("{}4242.tmp", STAGING_PREFIX));
+        fs::write(&stale, "partial").unwrap();
+        fs::write(dir.path().join("keep.txt"), "keep").unwrap();
+
+        assert_eq!(remove_stale(dir.path()).unwrap(), vec![stale.clone()]);
+        assert!(!stale.exists());
+        assert!(dir.path().join("keep.txt").exists());
+        assert!(remove_stale(&dir.path().join("missing")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use clap::ValueEnum;
+
+use crate::categories::{CategoryMapper, FileCategory};
+use crate::datetime::DateTime;
+use crate::diskspace::format_bytes;
+use crate::logging::json_string;
+use crate::scanner::FileInfo;
+
+/// How `stats` prints its report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum StatsFormat {
+    #[default]
+    Table,
+    Json,
+    /// One row per record, with a `kind` column: category, largest or unmapped
+    Csv,
+}
+
+/// Totals for one category.
+#[derive(Debug)]
+pub struct CategoryStats {
+    pub category: FileCategory,
+    pub files: usize,
+    pub bytes: u64,
+    /// Biggest first, as (path relative to the scanned root, size)
+    pub largest: Vec<(PathBuf, u64)>,
+    pub oldest: Option<SystemTime>,
+    pub newest: Option<SystemTime>,
+}
+
+/// What a directory contains, by category.
+#[derive(Debug)]
+pub struct Stats {
+    /// Only the categories that have files, in the usual order
+    pub categories: Vec<CategoryStats>,
+    /// Extensions of files that fall into Other, most common first, as
+    /// (lowercase extension, files)
+    pub unmapped: Vec<(String, usize)>,
+    pub total_files: usize,
+    pub total_bytes: u64,
+}
+
+impl Stats {
+    /// Adds up `files`, keeping the `top` largest files per category and the
+    /// `top` most common unmapped extensions. Files that vanish while being
+    /// counted are left out.
+    pub fn collect<I: IntoIterator<Item = FileInfo>>(files: I, top: usize) -> Self {
+        let mut by_category: HashMap<FileCategory, CategoryStats> = HashMap::new();
+        let mut unmapped: HashMap<String, usize> = HashMap::new();
+
+        for file in files {
+            // Links count as what they point to, dangling ones as themselves
+            let metadata = match fs::metadata(&file.path).or_else(|_| fs::symlink_metadata(&file.path)) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let modified = metadata.modified().ok();
+
+            if file.category == FileCategory::Other {
+                if let Some(extension) = &file.extension {
+                    *unmapped.entry(extension.to_lowercase()).or_insert(0) += 1;
+                }
+            }
+
+            let stats = by_category.entry(file.category.clone()).or_insert_with(|| CategoryStats {
+                category: file.category.clone(),
+                files: 0,
+                bytes: 0,
+                largest: Vec::new(),
+                oldest: None,
+                newest: None,
+            });
+            stats.files += 1;
+            stats.bytes += metadata.len();
+            if let Some(modified) = modified {
+                stats.oldest = Some(stats.oldest.map_or(modified, |oldest| oldest.min(modified)));
+                stats.newest = Some(stats.newest.map_or(modified, |newest| newest.max(modified)));
+            }
+
+            let position = stats.largest.partition_point(|(_, size)| *size >= metadata.len());
+            if position < top {
+                stats.largest.insert(position, (file.relative_dir.join(&file.name), metadata.len()));
+                stats.largest.truncate(top);
+            }
+        }
+
+        let categories: Vec<CategoryStats> = CategoryMapper::new()
+            .all_categories()
+            .iter()
+            .filter_map(|category| by_category.remove(category))
+            .collect();
+        let mut unmapped: Vec<(String, usize)> = unmapped.into_iter().collect();
+        unmapped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        unmapped.truncate(top);
+
+        Self {
+            total_files: categories.iter().map(|stats| stats.files).sum(),
+            total_bytes: categories.iter().map(|stats| stats.bytes).sum(),
+            categories,
+            unmapped,
+        }
+    }
+
+    pub fn render(&self, format: StatsFormat) -> String {
+        match format {
+            StatsFormat::Table => self.to_table(),
+            StatsFormat::Json => self.to_json(),
+            StatsFormat::Csv => self.to_csv(),
+        }
+    }
+
+    fn to_table(&self) -> String {
+        let mut out = format!(
+            "{:<12} {:>8} {:>12}  {:<10}  {}\n",
+            "Category", "Files", "Size", "Oldest", "Newest"
+        );
+        for stats in &self.categories {
+            out.push_str(&format!(
+                "{:<12} {:>8} {:>12}  {:<10}  {}\n",
+                stats.category.folder_name(),
+                stats.files,
+                format_bytes(stats.bytes),
+                format_time(stats.oldest, "%Y-%m-%d"),
+                format_time(stats.newest, "%Y-%m-%d")
+            ));
+        }
+        out.push_str(&format!(
+            "{:<12} {:>8} {:>12}\n",
+            "Total",
+            self.total_files,
+            format_bytes(self.total_bytes)
+        ));
+
+        if self.categories.iter().any(|stats| !stats.largest.is_empty()) {
+            out.push_str("\nLargest files:\n");
+            for stats in &self.categories {
+                out.push_str(&format!("  {}\n", stats.category.folder_name()));
+                for (path, size) in &stats.largest {
+                    out.push_str(&format!("    {:>12}  {}\n", format_bytes(*size), path.display()));
+                }
+            }
+        }
+
+        if !self.unmapped.is_empty() {
+            out.push_str("\nUnmapped extensions (now in Other):\n");
+            for (extension, files) in &self.unmapped {
+                out.push_str(&format!("  .{:<12} {} file(s)\n", extension, files));
+            }
+        }
+        out
+    }
+
+    fn to_json(&self) -> String {
+        let categories: Vec<String> = self
+            .categories
+            .iter()
+            .map(|stats| {
+                let largest: Vec<String> = stats
+                    .largest
+                    .iter()
+                    .map(|(path, size)| {
+                        format!(
+                            "{{\"path\": {}, \"bytes\": {}}}",
+                            json_string(&path.to_string_lossy()),
+                            size
+                        )
+                    })
+                    .collect();
+                format!(
+                    "    {{\"category\": {}, \"files\": {}, \"bytes\": {}, \"oldest\": {}, \"newest\": {}, \"largest\": [{}]}}",
+                    json_string(stats.category.folder_name()),
+                    stats.files,
+                    stats.bytes,
+                    json_time(stats.oldest),
+                    json_time(stats.newest),
+                    largest.join(", ")
+                )
+            })
+            .collect();
+        let unmapped: Vec<String> = self
+            .unmapped
+            .iter()
+            .map(|(extension, files)| format!("    {{\"extension\": {}, \"files\": {}}}", json_string(extension), files))
+            .collect();
+
+        format!(
+            "{{\n  \"total_files\": {},\n  \"total_bytes\": {},\n  \"categories\": {},\n  \"unmapped_extensions\": {}\n}}\n",
+            self.total_files,
+            self.total_bytes,
+            json_array(&categories),
+            json_array(&unmapped)
+        )
+    }
+
+    fn to_csv(&self) -> String {
+        let mut out = String::from("kind,category,name,files,bytes,oldest,newest\n");
+        for stats in &self.categories {
+            let category = stats.category.folder_name();
+            out.push_str(&format!(
+                "category,{},,{},{},{},{}\n",
+                category,
+                stats.files,
+                stats.bytes,
+                format_time(stats.oldest, ISO_FORMAT),
+                format_time(stats.newest, ISO_FORMAT)
+            ));
+            for (path, size) in &stats.largest {
+                out.push_str(&format!("largest,{},{},1,{},,\n", category, csv_field(&path.to_string_lossy()), size));
+            }
+        }
+        for (extension, files) in &self.unmapped {
+            out.push_str(&format!("unmapped,Other,{},{},,,\n", csv_field(extension), files));
+        }
+        out
+    }
+}
+
+const ISO_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+fn format_time(time: Option<SystemTime>, spec: &str) -> String {
+    time.map(|time| DateTime::from_system_time(time).format(spec))
+        .unwrap_or_default()
+}
+
+fn json_time(time: Option<SystemTime>) -> String {
+    match time {
+        Some(_) => json_string(&format_time(time, ISO_FORMAT)),
+        None => "null".to_string(),
+    }
+}
+
+/// Items already indented by four spaces, one per line.
+fn json_array(items: &[String]) -> String {
+    if items.is_empty() {
+        "[]".to_string()
+    } else {
+        format!("[\n{}\n  ]", items.join(",\n"))
+    }
+}
+
+/// Quotes a field that holds a comma, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::DirectoryScanner;
+    use std::fs::File;
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tempfile::tempdir;
+
+    fn write(root: &Path, name: &str, size: usize, modified: u64) {
+        let path = root.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![b'x'; size]).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(modified))
+            .unwrap();
+    }
+
+    fn collect(root: &Path) -> Stats {
+        let scanner = DirectoryScanner::new(CategoryMapper::new()).with_recursive(true);
+        Stats::collect(scanner.scan_iter(root).unwrap(), 2)
+    }
+
+    #[test]
+    fn test_totals_and_largest_files() {
+        let dir = tempdir().unwrap();
+        write(dir.path(), "a.jpg", 10, 1_000_000_000);
+        write(dir.path(), "trip/b.jpg", 30, 1_600_000_000);
+        write(dir.path(), "c.png", 20, 1_300_000_000);
+        write(dir.path(), "notes.txt", 5, 1_500_000_000);
+
+        let stats = collect(dir.path());
+
+        assert_eq!(stats.total_files, 4);
+        assert_eq!(stats.total_bytes, 65);
+        let images = &stats.categories[0];
+        assert_eq!(images.category, FileCategory::Images);
+        assert_eq!((images.files, images.bytes), (3, 60));
+        assert_eq!(
+            images.largest,
+            vec![(PathBuf::from("trip/b.jpg"), 30), (PathBuf::from("c.png"), 20)]
+        );
+        assert_eq!(images.oldest, Some(UNIX_EPOCH + Duration::from_secs(1_000_000_000)));
+        assert_eq!(images.newest, Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)));
+        assert_eq!(stats.categories[1].category, FileCategory::Documents);
+    }
+
+    #[test]
+    fn test_unmapped_extensions() {
+        let dir = tempdir().unwrap();
+        for name in ["a.xyz", "b.XYZ", "c.xyz", "d.abc", "e.qqq", "f.qqq", "noext"] {
+            write(dir.path(), name, 1, 1_000_000_000);
+        }
+
+        let stats = collect(dir.path());
+
+        assert_eq!(stats.categories.len(), 1);
+        assert_eq!(stats.categories[0].files, 7);
+        assert_eq!(stats.unmapped, vec![("xyz".to_string(), 3), ("qqq".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_formats() {
+        let dir = tempdir().unwrap();
+        write(dir.path(), "my, photo.jpg", 10, 1_000_000_000);
+        write(dir.path(), "data.xyz", 1, 1_000_000_000);
+        let stats = collect(dir.path());
+
+        let table = stats.render(StatsFormat::Table);
+        assert!(table.starts_with("Category"));
+        assert!(table.contains("Unmapped extensions (now in Other):\n  .xyz"));
+
+        let csv = stats.render(StatsFormat::Csv);
+        assert!(csv.contains("\nlargest,Images,\"my, photo.jpg\",1,10,,\n"));
+        assert!(csv.ends_with("unmapped,Other,xyz,1,,,\n"));
+
+        let json = stats.render(StatsFormat::Json);
+        assert!(json.contains("\"total_files\": 2"));
+        assert!(json.contains("\"largest\": [{\"path\": \"my, photo.jpg\", \"bytes\": 10}]"));
+        assert!(json.contains("{\"extension\": \"xyz\", \"files\": 1}"));
+    }
+}
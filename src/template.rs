@@ -0,0 +1,277 @@
+/// Placeholders understood in destination file name and layout templates.
+pub const PLACEHOLDERS: [&str; 29] = [
+    "name", "stem", "ext", "category", "subcategory", "date", "modified", "created", "hash", "counter",
+    "exif.date", "exif.year", "exif.month", "exif.day", "exif.camera", "exif.make", "exif.model", "exif.gps",
+    "artist", "album_artist", "album", "title", "track", "year",
+    "doc.title", "doc.author", "doc.created", "doc.year", "doc.pages",
+];
+
+/// Placeholders filled from audio tags.
+pub const AUDIO_PLACEHOLDERS: [&str; 6] = ["artist", "album_artist", "album", "title", "track", "year"];
+
+/// Placeholders that only make sense once the destination folder is known.
+pub const FILE_NAME_ONLY_PLACEHOLDERS: [&str; 1] = ["counter"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder { name: String, spec: Option<String> },
+}
+
+/// A parsed `{placeholder:spec}` template such as `{date:%Y-%m-%d}_{stem}{ext}`.
+///
+/// Literal braces are written as `{{` and `}}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut body = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => body.push(c),
+                            None => return Err(format!("unclosed '{{' in template '{}'", source)),
+                        }
+                    }
+
+                    let (name, spec) = match body.split_once(':') {
+                        Some((name, spec)) => (name.trim(), Some(spec.to_string())),
+                        None => (body.trim(), None),
+                    };
+                    if name.is_empty() {
+                        return Err(format!("empty placeholder in template '{}'", source));
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder {
+                        name: name.to_string(),
+                        spec,
+                    });
+                }
+                '}' => return Err(format!("unmatched '}}' in template '{}'", source)),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Names of all placeholders used, in order of appearance.
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Placeholder { name, .. } => Some(name.as_str()),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    pub fn uses(&self, placeholder: &str) -> bool {
+        self.placeholders().any(|name| name == placeholder)
+    }
+
+    /// Rejects placeholders that are not in `known`.
+    pub fn validate(&self, known: &[&str]) -> Result<(), String> {
+        match self.placeholders().find(|name| !known.contains(name)) {
+            Some(name) => Err(format!(
+                "unknown placeholder '{{{}}}' in template '{}' (expected one of: {})",
+                name,
+                self.source,
+                known.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Renders the template, asking `resolve` for the value of each placeholder.
+    pub fn render<F>(&self, mut resolve: F) -> Result<String, String>
+    where
+        F: FnMut(&str, Option<&str>) -> Result<String, String>,
+    {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => output.push_str(text),
+                Segment::Placeholder { name, spec } => {
+                    output.push_str(&resolve(name, spec.as_deref())?);
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Destination of a file relative to the output directory.
+///
+/// A layout names a folder such as `Images/{exif.year}`. When its last path
+/// component refers to the file itself (it uses `{ext}` or `{name}`), as in
+/// `Audio/{artist}/{album}/{track:02} - {title}{ext}`, that component is used
+/// as the file name template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    source: String,
+    pub folder: Template,
+    pub file_name: Option<Template>,
+}
+
+impl Layout {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let (folder, file_name) = match last_separator(source) {
+            Some(index) => {
+                let file_name = Template::parse(&source[index + 1..])?;
+                if file_name.uses("ext") || file_name.uses("name") {
+                    (Template::parse(&source[..index])?, Some(file_name))
+                } else {
+                    (Template::parse(source)?, None)
+                }
+            }
+            None => (Template::parse(source)?, None),
+        };
+
+        folder.validate(&PLACEHOLDERS)?;
+        if let Some(name) = folder.placeholders().find(|name| FILE_NAME_ONLY_PLACEHOLDERS.contains(name)) {
+            return Err(format!(
+                "'{{{}}}' can only be used in the file name part of layout '{}'",
+                name, source
+            ));
+        }
+        if let Some(file_name) = &file_name {
+            file_name.validate(&PLACEHOLDERS)?;
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            folder,
+            file_name,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn templates(&self) -> impl Iterator<Item = &Template> {
+        std::iter::once(&self.folder).chain(self.file_name.iter())
+    }
+}
+
+/// Byte index of the last `/` that is not inside a placeholder.
+fn last_separator(source: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut last = None;
+    for (index, c) in source.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            '/' if depth == 0 => last = Some(index),
+            _ => {}
+        }
+    }
+    last
+}
+
+/// Formats a number zero-padded to the width given by `spec`, so `04`
+/// renders 7 as `0007`.
+pub fn format_number(value: u64, spec: Option<&str>) -> Result<String, String> {
+    let width = match spec {
+        Some(spec) => spec
+            .parse::<usize>()
+            .map_err(|_| format!("invalid number format '{}'", spec))?,
+        None => 0,
+    };
+    Ok(format!("{:0width$}", value, width = width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> String {
+        Template::parse(template)
+            .unwrap()
+            .render(|name, spec| Ok(format!("<{}|{}>", name, spec.unwrap_or("-"))))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_render() {
+        assert_eq!(render("{date:%Y-%m-%d}_{stem}{ext}"), "<date|%Y-%m-%d>_<stem|-><ext|->");
+        assert_eq!(render("plain.txt"), "plain.txt");
+        assert_eq!(render("{{literal}}"), "{literal}");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Template::parse("{stem").is_err());
+        assert!(Template::parse("stem}").is_err());
+        assert!(Template::parse("{}").is_err());
+    }
+
+    #[test]
+    fn test_validate_placeholders() {
+        let template = Template::parse("{category}-{counter:04}{ext}").unwrap();
+        assert!(template.validate(&PLACEHOLDERS).is_ok());
+        assert!(template.uses("counter"));
+        assert!(!template.uses("hash"));
+
+        let unknown = Template::parse("{bogus}").unwrap();
+        assert!(unknown.validate(&PLACEHOLDERS).unwrap_err().contains("bogus"));
+    }
+
+    #[test]
+    fn test_layout_with_file_name() {
+        let layout = Layout::parse("Audio/{artist}/{album}/{track:02} - {title}{ext}").unwrap();
+        assert_eq!(layout.folder.as_str(), "Audio/{artist}/{album}");
+        assert_eq!(layout.file_name.unwrap().as_str(), "{track:02} - {title}{ext}");
+
+        // A slash inside a placeholder spec is not a separator
+        let layout = Layout::parse("Images/{date:%Y/%m}").unwrap();
+        assert_eq!(layout.folder.as_str(), "Images/{date:%Y/%m}");
+        assert!(layout.file_name.is_none());
+    }
+
+    #[test]
+    fn test_layout_rejects_counter_in_folder() {
+        assert!(Layout::parse("Images/{counter}").is_err());
+        assert!(Layout::parse("Images/{counter:03}{ext}").is_ok());
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(7, Some("04")).unwrap(), "0007");
+        assert_eq!(format_number(7, Some("2")).unwrap(), "07");
+        assert_eq!(format_number(12345, Some("03")).unwrap(), "12345");
+        assert_eq!(format_number(7, None).unwrap(), "7");
+        assert!(format_number(7, Some("x")).is_err());
+    }
+}
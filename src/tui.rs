@@ -0,0 +1,828 @@
+use std::fs;
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use crate::categories::{CategoryMapper, FileCategory};
+use crate::datetime::DateTime;
+use crate::diskspace::format_bytes;
+use crate::interactive::Review;
+use crate::organizer::{MoveOperation, MoveResult, Organizer};
+use crate::scanner::{FileInfo, ScanResult};
+
+/// Reason recorded for files left out of the plan in the terminal UI.
+const EXCLUDED_REASON: &str = "excluded in the terminal UI";
+
+const HELP: &str = "up/down move  space select  * all  x exclude  c category  / filter  a apply  q quit";
+
+/// A key press decoded from terminal input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Backspace,
+    Escape,
+    /// Ctrl-C, which does not raise SIGINT while the screen is in raw mode
+    Interrupt,
+}
+
+impl Key {
+    /// Decodes the first key in `bytes`, returning it (if it is one we know)
+    /// and the number of bytes it took up.
+    pub fn parse(bytes: &[u8]) -> (Option<Key>, usize) {
+        match bytes {
+            [] => (None, 0),
+            [0x1b, b'[' | b'O', rest @ ..] => {
+                // CSI and SS3 sequences end with a byte in 0x40..=0x7e
+                let end = match rest.iter().position(|b| (0x40..=0x7e).contains(b)) {
+                    Some(end) => end,
+                    None => return (None, bytes.len()),
+                };
+                let key = match &rest[..=end] {
+                    b"A" => Some(Key::Up),
+                    b"B" => Some(Key::Down),
+                    b"H" | b"1~" | b"7~" => Some(Key::Home),
+                    b"F" | b"4~" | b"8~" => Some(Key::End),
+                    b"5~" => Some(Key::PageUp),
+                    b"6~" => Some(Key::PageDown),
+                    _ => None,
+                };
+                (key, end + 3)
+            }
+            [0x1b, ..] => (Some(Key::Escape), 1),
+            [b'\r' | b'\n', ..] => (Some(Key::Enter), 1),
+            [0x7f | 0x08, ..] => (Some(Key::Backspace), 1),
+            [0x03, ..] => (Some(Key::Interrupt), 1),
+            [first, ..] => {
+                let width = match first {
+                    0x00..=0x7f => 1,
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                }
+                .min(bytes.len());
+                let key = std::str::from_utf8(&bytes[..width])
+                    .ok()
+                    .and_then(|text| text.chars().next())
+                    .filter(|c| !c.is_control())
+                    .map(Key::Char);
+                (key, width)
+            }
+        }
+    }
+}
+
+/// A planned move as shown in the list.
+struct Entry {
+    file: FileInfo,
+    operation: MoveOperation,
+    size: u64,
+    modified: Option<SystemTime>,
+    created: Option<SystemTime>,
+    accessed: Option<SystemTime>,
+    excluded: bool,
+    selected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Browse,
+    /// Typing the filter
+    Filter,
+    /// Picking the category for the targeted files
+    Category,
+}
+
+/// What the caller should do after a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Apply,
+    Quit,
+}
+
+enum Row {
+    Header(FileCategory, usize),
+    /// Position in the visible entries
+    Entry(usize),
+}
+
+/// The plan being edited: every planned move, grouped by category, with the
+/// filter, selection and cursor of the list.
+pub struct PlanView {
+    entries: Vec<Entry>,
+    /// Files that could not be planned at all
+    failed: Vec<(MoveOperation, String)>,
+    categories: Vec<FileCategory>,
+    /// Indices into `entries` that pass the filter, grouped by category
+    visible: Vec<usize>,
+    /// Position in `visible`
+    cursor: usize,
+    /// First list row on screen
+    scroll: usize,
+    /// List rows that fit on screen, for paging
+    page: usize,
+    filter: String,
+    mode: Mode,
+    message: Option<String>,
+}
+
+impl PlanView {
+    pub fn new(organizer: &Organizer, scan_result: &ScanResult) -> Self {
+        let categories = CategoryMapper::new().all_categories();
+        let mut entries = Vec::new();
+        let mut failed = Vec::new();
+
+        for category in &categories {
+            for file in scan_result.files_in(category) {
+                match organizer.plan(file) {
+                    Ok(operation) => {
+                        let metadata = fs::symlink_metadata(&file.path).ok();
+                        entries.push(Entry {
+                            file: file.clone(),
+                            operation,
+                            size: metadata.as_ref().map_or(0, |metadata| metadata.len()),
+                            modified: metadata.as_ref().and_then(|metadata| metadata.modified().ok()),
+                            created: metadata.as_ref().and_then(|metadata| metadata.created().ok()),
+                            accessed: metadata.as_ref().and_then(|metadata| metadata.accessed().ok()),
+                            excluded: false,
+                            selected: false,
+                        });
+                    }
+                    Err(reason) => {
+                        let operation = MoveOperation {
+                            source: file.path.clone(),
+                            destination: organizer.get_target_path(&file.category, &file.name),
+                            file_name: file.name.clone(),
+                        };
+                        failed.push((operation, reason));
+                    }
+                }
+            }
+        }
+
+        let mut view = Self {
+            entries,
+            failed,
+            categories,
+            visible: Vec::new(),
+            cursor: 0,
+            scroll: 0,
+            page: 10,
+            filter: String::new(),
+            mode: Mode::Browse,
+            message: None,
+        };
+        view.refresh();
+        view
+    }
+
+    /// Recomputes the visible entries, keeping the cursor on the same file
+    /// if it is still shown.
+    fn refresh(&mut self) {
+        let current = self.visible.get(self.cursor).copied();
+        let filter = self.filter.to_lowercase();
+
+        self.visible.clear();
+        for category in &self.categories {
+            for (index, entry) in self.entries.iter().enumerate() {
+                if entry.file.category == *category && matches_filter(&entry.file, &filter) {
+                    self.visible.push(index);
+                }
+            }
+        }
+
+        self.cursor = current
+            .and_then(|current| self.visible.iter().position(|index| *index == current))
+            .unwrap_or(self.cursor)
+            .min(self.visible.len().saturating_sub(1));
+    }
+
+    /// Selected files, or the one under the cursor if none are.
+    fn targets(&self) -> Vec<usize> {
+        let selected: Vec<usize> = (0..self.entries.len()).filter(|index| self.entries[*index].selected).collect();
+        if selected.is_empty() {
+            self.visible.get(self.cursor).copied().into_iter().collect()
+        } else {
+            selected
+        }
+    }
+
+    pub fn handle_key(&mut self, key: Key, organizer: &Organizer) -> Action {
+        match self.mode {
+            Mode::Browse => return self.handle_browse_key(key),
+            Mode::Filter => match key {
+                Key::Char(c) => self.filter.push(c),
+                Key::Backspace => {
+                    self.filter.pop();
+                }
+                Key::Enter => self.mode = Mode::Browse,
+                Key::Escape | Key::Interrupt => {
+                    self.filter.clear();
+                    self.mode = Mode::Browse;
+                }
+                _ => {}
+            },
+            Mode::Category => match key {
+                Key::Char(c) if c.is_ascii_digit() => {
+                    // 1-9 and then 0, in the order of the key line
+                    let index = (c as usize + 10 - '1' as usize) % 10;
+                    if let Some(category) = self.categories.get(index).cloned() {
+                        self.reassign(category, organizer);
+                        self.mode = Mode::Browse;
+                    }
+                }
+                Key::Escape | Key::Interrupt => self.mode = Mode::Browse,
+                _ => {}
+            },
+        }
+        self.refresh();
+        Action::Continue
+    }
+
+    fn handle_browse_key(&mut self, key: Key) -> Action {
+        self.message = None;
+        let last = self.visible.len().saturating_sub(1);
+
+        match key {
+            Key::Up | Key::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.cursor = (self.cursor + 1).min(last),
+            Key::PageUp => self.cursor = self.cursor.saturating_sub(self.page),
+            Key::PageDown => self.cursor = (self.cursor + self.page).min(last),
+            Key::Home | Key::Char('g') => self.cursor = 0,
+            Key::End | Key::Char('G') => self.cursor = last,
+            Key::Char(' ') => {
+                if let Some(index) = self.visible.get(self.cursor).copied() {
+                    self.entries[index].selected = !self.entries[index].selected;
+                    self.cursor = (self.cursor + 1).min(last);
+                }
+            }
+            Key::Char('*') => {
+                let select = !self.visible.iter().all(|index| self.entries[*index].selected);
+                for index in &self.visible {
+                    self.entries[*index].selected = select;
+                }
+            }
+            Key::Char('x') => {
+                let targets = self.targets();
+                let exclude = !targets.iter().all(|index| self.entries[*index].excluded);
+                for index in &targets {
+                    self.entries[*index].excluded = exclude;
+                }
+                self.message = Some(format!(
+                    "{} {} file(s)",
+                    if exclude { "Excluded" } else { "Included" },
+                    targets.len()
+                ));
+            }
+            Key::Char('c') if !self.targets().is_empty() => self.mode = Mode::Category,
+            Key::Char('/') => self.mode = Mode::Filter,
+            Key::Escape => {
+                if self.filter.is_empty() {
+                    self.entries.iter_mut().for_each(|entry| entry.selected = false);
+                } else {
+                    self.filter.clear();
+                    self.refresh();
+                }
+            }
+            Key::Char('a') => return Action::Apply,
+            Key::Char('q') | Key::Interrupt => return Action::Quit,
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    /// Plans the targeted files again under `category`. A file that cannot
+    /// go there keeps its old destination.
+    fn reassign(&mut self, category: FileCategory, organizer: &Organizer) {
+        let mut moved = 0;
+        let mut failure = None;
+
+        for index in self.targets() {
+            let entry = &mut self.entries[index];
+            entry.selected = false;
+            if entry.file.category == category {
+                continue;
+            }
+
+            let mut recategorized = entry.file.clone();
+            recategorized.category = category.clone();
+            recategorized.subcategory = None;
+            organizer.release(&entry.operation);
+            match organizer.plan(&recategorized) {
+                Ok(operation) => {
+                    entry.file = recategorized;
+                    entry.operation = operation;
+                    moved += 1;
+                }
+                Err(reason) => {
+                    organizer.reserve(&entry.operation);
+                    failure = Some(format!("{}: {}", entry.file.name, reason));
+                }
+            }
+        }
+
+        self.message = Some(match failure {
+            Some(failure) => format!("Moved {} file(s) to {}; {}", moved, category.folder_name(), failure),
+            None => format!("Moved {} file(s) to {}", moved, category.folder_name()),
+        });
+    }
+
+    /// The list rows: a header per category, then its files.
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        let mut position = 0;
+        while position < self.visible.len() {
+            let category = &self.entries[self.visible[position]].file.category;
+            let count = self.visible[position..]
+                .iter()
+                .take_while(|index| self.entries[**index].file.category == *category)
+                .count();
+            rows.push(Row::Header(category.clone(), count));
+            rows.extend((position..position + count).map(Row::Entry));
+            position += count;
+        }
+        rows
+    }
+
+    /// Draws the whole screen as terminal output.
+    pub fn render(&mut self, width: usize, height: usize, organizer: &Organizer) -> String {
+        let width = width.max(20);
+        let list_height = height.max(3) - 2;
+        let panel_width = if width >= 60 { (width / 3).clamp(24, 40) } else { 0 };
+        let list_width = if panel_width > 0 { width - panel_width - 1 } else { width };
+
+        let rows = self.rows();
+        let cursor_row = rows
+            .iter()
+            .position(|row| matches!(row, Row::Entry(position) if *position == self.cursor));
+        if let Some(cursor_row) = cursor_row {
+            // Keep the cursor, and the header just above it, on screen
+            let top = match cursor_row.checked_sub(1).map(|row| &rows[row]) {
+                Some(Row::Header(..)) => cursor_row - 1,
+                _ => cursor_row,
+            };
+            if top < self.scroll {
+                self.scroll = top;
+            } else if cursor_row >= self.scroll + list_height {
+                self.scroll = cursor_row + 1 - list_height;
+            }
+        }
+        self.scroll = self.scroll.min(rows.len().saturating_sub(list_height));
+        self.page = list_height;
+
+        let panel = self.panel_lines(organizer);
+        let mut screen = String::from("\x1b[H");
+
+        let excluded = self.entries.iter().filter(|entry| entry.excluded).count();
+        let selected = self.entries.iter().filter(|entry| entry.selected).count();
+        let mut title = format!(
+            " file-organizer: {} file(s), {} excluded, {} selected",
+            self.entries.len(),
+            excluded,
+            selected
+        );
+        if !self.filter.is_empty() {
+            title.push_str(&format!(", filter '{}'", self.filter));
+        }
+        screen.push_str(&format!("\x1b[7m{}\x1b[0m\r\n", fit(&title, width)));
+
+        for line in 0..list_height {
+            let (text, style) = match rows.get(self.scroll + line) {
+                Some(Row::Header(category, count)) => (format!("{} ({})", category.folder_name(), count), "\x1b[1m"),
+                Some(Row::Entry(position)) => {
+                    let entry = &self.entries[self.visible[*position]];
+                    let text = format!(
+                        "{}{} {} -> {}",
+                        if entry.selected { '*' } else { ' ' },
+                        if entry.excluded { 'x' } else { ' ' },
+                        entry.file.name,
+                        if entry.excluded {
+                            "(excluded)".to_string()
+                        } else {
+                            organizer.destination_label(&entry.operation)
+                        }
+                    );
+                    let style = if *position == self.cursor {
+                        "\x1b[7m"
+                    } else if entry.excluded {
+                        "\x1b[2m"
+                    } else {
+                        ""
+                    };
+                    (text, style)
+                }
+                None if line == 0 && rows.is_empty() => ("  No files match the filter".to_string(), ""),
+                None => (String::new(), ""),
+            };
+            screen.push_str(&format!("{}{}\x1b[0m", style, fit(&text, list_width)));
+            if panel_width > 0 {
+                let side = panel.get(line).map(String::as_str).unwrap_or("");
+                screen.push_str(&format!("\u{2502}{}", fit(side, panel_width)));
+            }
+            screen.push_str("\r\n");
+        }
+
+        let footer = match self.mode {
+            Mode::Browse => self.message.clone().unwrap_or_else(|| HELP.to_string()),
+            Mode::Filter => format!("Filter: {}_   (Enter keeps it, Esc clears it; .ext matches extensions)", self.filter),
+            Mode::Category => {
+                let keys: Vec<String> = self
+                    .categories
+                    .iter()
+                    .enumerate()
+                    .map(|(index, category)| format!("{} {}", (index + 1) % 10, category.folder_name()))
+                    .collect();
+                format!("Move to: {}   (Esc cancels)", keys.join("  "))
+            }
+        };
+        screen.push_str(&format!("\x1b[7m{}\x1b[0m\x1b[J", fit(&footer, width)));
+        screen
+    }
+
+    /// Details of the file under the cursor for the side panel.
+    fn panel_lines(&self, organizer: &Organizer) -> Vec<String> {
+        let entry = match self.visible.get(self.cursor) {
+            Some(index) => &self.entries[*index],
+            None => return Vec::new(),
+        };
+        let from = if entry.file.relative_dir.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            entry.file.relative_dir.display().to_string()
+        };
+
+        let mut lines = vec![
+            format!(" {}", entry.file.name),
+            String::new(),
+            format!(" Size:     {}", format_bytes(entry.size)),
+            format!(" Modified: {}", format_time(entry.modified)),
+            format!(" Created:  {}", format_time(entry.created)),
+            format!(" Accessed: {}", format_time(entry.accessed)),
+            String::new(),
+            format!(" From: {}", from),
+            format!(" To:   {}", organizer.destination_label(&entry.operation)),
+        ];
+        if entry.file.is_symlink {
+            lines.push(" Symbolic link".to_string());
+        }
+        if entry.excluded {
+            lines.push(" Excluded".to_string());
+        }
+        lines
+    }
+
+    /// Ends the session: excluded files are dropped from the plan and listed
+    /// as skipped.
+    pub fn into_review(self, organizer: &Organizer) -> Review {
+        let mut review = Review {
+            failed: self.failed,
+            ..Review::default()
+        };
+        for entry in self.entries {
+            if entry.excluded {
+                organizer.release(&entry.operation);
+                review.skipped.push((entry.operation, EXCLUDED_REASON.to_string()));
+            } else {
+                review.accepted.push(entry.operation);
+            }
+        }
+        review
+    }
+}
+
+/// `.ext` matches the extension, anything else part of the name.
+fn matches_filter(file: &FileInfo, filter: &str) -> bool {
+    match filter.strip_prefix('.') {
+        _ if filter.is_empty() => true,
+        Some(extension) => file
+            .extension
+            .as_deref()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(extension)),
+        None => file.name.to_lowercase().contains(filter),
+    }
+}
+
+fn format_time(time: Option<SystemTime>) -> String {
+    time.map(|time| DateTime::from_system_time(time).format("%Y-%m-%d %H:%M"))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Cuts or pads `text` to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let length = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - length));
+    fitted
+}
+
+/// `[#####-----]` for `done` out of `total`.
+fn progress_bar(done: usize, total: usize, width: usize) -> String {
+    let filled = (done * width).checked_div(total).unwrap_or(width);
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+/// Carries out the reviewed plan with a progress bar on the normal screen.
+pub fn apply(organizer: &Organizer, review: Review) -> MoveResult {
+    println!("Applying {} planned move(s)...", review.accepted.len());
+    let mut stdout = io::stdout();
+    let mut result = organizer.execute_plan_with_progress(review.accepted, |done, total, result| {
+        let _ = write!(
+            stdout,
+            "\r  {} {}/{}  done {}, skipped {}, failed {}",
+            progress_bar(done, total, 30),
+            done,
+            total,
+            result.moved.len(),
+            result.skipped.len(),
+            result.failed.len()
+        );
+        let _ = stdout.flush();
+    });
+    println!();
+
+    result.add_left_out(review.skipped, review.failed);
+    result
+}
+
+/// Runs the full-screen review of the plan and returns what the user
+/// accepted, for [`apply`]; returns `None` if the user quit instead.
+#[cfg(unix)]
+pub fn run(organizer: &Organizer, scan_result: &ScanResult) -> io::Result<Option<Review>> {
+    let mut view = PlanView::new(organizer, scan_result);
+
+    {
+        let mut terminal = Terminal::enter()?;
+        let mut size = (0, 0);
+        let mut redraw = true;
+        loop {
+            // Polled, so a resized window is redrawn without a key press
+            let current = terminal.size();
+            if redraw || current != size {
+                size = current;
+                terminal.draw(&view.render(size.0, size.1, organizer))?;
+            }
+
+            redraw = false;
+            if let Some(key) = terminal.read_key(250)? {
+                redraw = true;
+                match view.handle_key(key, organizer) {
+                    Action::Continue => {}
+                    Action::Apply => break,
+                    Action::Quit => return Ok(None),
+                }
+            }
+        }
+    }
+
+    Ok(Some(view.into_review(organizer)))
+}
+
+#[cfg(not(unix))]
+pub fn run(_organizer: &Organizer, _scan_result: &ScanResult) -> io::Result<Option<Review>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "the terminal UI needs a Unix terminal"))
+}
+
+/// Raw mode on the alternate screen, undone when dropped.
+#[cfg(unix)]
+struct Terminal {
+    original: libc::termios,
+    /// Input read but not decoded yet
+    pending: Vec<u8>,
+}
+
+#[cfg(unix)]
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let terminal = Self {
+            original,
+            pending: Vec::new(),
+        };
+        terminal.draw("\x1b[?1049h\x1b[?25l")?;
+        Ok(terminal)
+    }
+
+    /// Columns and rows, 80x24 if the terminal does not say.
+    fn size(&self) -> (usize, usize) {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+            return (80, 24);
+        }
+        (size.ws_col as usize, size.ws_row as usize)
+    }
+
+    /// The next key, or `None` if nothing was pressed within `timeout_ms`.
+    fn read_key(&mut self, timeout_ms: i32) -> io::Result<Option<Key>> {
+        if self.pending.is_empty() {
+            let mut poll = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut poll, 1, timeout_ms) } {
+                0 => return Ok(None),
+                ready if ready < 0 => {
+                    let error = io::Error::last_os_error();
+                    return if error.kind() == io::ErrorKind::Interrupted {
+                        Ok(None)
+                    } else {
+                        Err(error)
+                    };
+                }
+                _ => {}
+            }
+
+            let mut buffer = [0u8; 64];
+            let read = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr().cast(), buffer.len()) };
+            match read {
+                0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the terminal was closed")),
+                read if read < 0 => {
+                    let error = io::Error::last_os_error();
+                    return if error.kind() == io::ErrorKind::Interrupted {
+                        Ok(None)
+                    } else {
+                        Err(error)
+                    };
+                }
+                read => self.pending.extend_from_slice(&buffer[..read as usize]),
+            }
+        }
+
+        let (key, length) = Key::parse(&self.pending);
+        self.pending.drain(..length);
+        Ok(key)
+    }
+
+    fn draw(&self, output: &str) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.draw("\x1b[?25h\x1b[?1049l");
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::DirectoryScanner;
+    use crate::template::Layout;
+    use std::path::PathBuf;
+    use tempfile::{tempdir, TempDir};
+
+    fn setup() -> (TempDir, TempDir, Organizer, ScanResult) {
+        let source = tempdir().unwrap();
+        let output = tempdir().unwrap();
+        for name in ["a.jpg", "b.jpg", "notes.txt"] {
+            fs::write(source.path().join(name), name).unwrap();
+        }
+        let scan_result = DirectoryScanner::new(CategoryMapper::new()).scan(source.path()).unwrap();
+        let organizer = Organizer::new(output.path().to_path_buf(), true, false);
+        (source, output, organizer, scan_result)
+    }
+
+    fn press(view: &mut PlanView, organizer: &Organizer, keys: &str) {
+        for c in keys.chars() {
+            let key = match c {
+                '\n' => Key::Enter,
+                '\x1b' => Key::Escape,
+                c => Key::Char(c),
+            };
+            view.handle_key(key, organizer);
+        }
+    }
+
+    fn visible_names(view: &PlanView) -> Vec<&str> {
+        view.visible.iter().map(|index| view.entries[*index].file.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(Key::parse(b"\x1b[A"), (Some(Key::Up), 3));
+        assert_eq!(Key::parse(b"\x1b[6~j"), (Some(Key::PageDown), 4));
+        assert_eq!(Key::parse(b"\x1bOH"), (Some(Key::Home), 3));
+        assert_eq!(Key::parse(b"\x1b"), (Some(Key::Escape), 1));
+        assert_eq!(Key::parse(b"ab"), (Some(Key::Char('a')), 1));
+        assert_eq!(Key::parse("é".as_bytes()), (Some(Key::Char('é')), 2));
+        assert_eq!(Key::parse(b"\r"), (Some(Key::Enter), 1));
+        assert_eq!(Key::parse(b"\x03"), (Some(Key::Interrupt), 1));
+        assert_eq!(Key::parse(b"\x1b[99z"), (None, 5));
+    }
+
+    #[test]
+    fn test_filter_by_name_and_extension() {
+        let (_source, _output, organizer, scan_result) = setup();
+        let mut view = PlanView::new(&organizer, &scan_result);
+        assert_eq!(view.visible.len(), 3);
+
+        press(&mut view, &organizer, "/NOTES\n");
+        assert_eq!(visible_names(&view), vec!["notes.txt"]);
+
+        press(&mut view, &organizer, "\x1b/.jpg\n");
+        assert_eq!(visible_names(&view).len(), 2);
+        assert!(visible_names(&view).iter().all(|name| name.ends_with(".jpg")));
+
+        press(&mut view, &organizer, "\x1b");
+        assert_eq!(view.visible.len(), 3);
+    }
+
+    #[test]
+    fn test_exclude_selected_files() {
+        let (_source, _output, organizer, scan_result) = setup();
+        let mut view = PlanView::new(&organizer, &scan_result);
+
+        // Select both images, then exclude them
+        press(&mut view, &organizer, "  x");
+        let review = view.into_review(&organizer);
+
+        assert_eq!(review.accepted.len(), 1);
+        assert_eq!(review.accepted[0].file_name, "notes.txt");
+        assert_eq!(review.skipped.len(), 2);
+        assert!(review.skipped.iter().all(|(_, reason)| reason == EXCLUDED_REASON));
+    }
+
+    #[test]
+    fn test_reassign_category() {
+        let (_source, output, organizer, scan_result) = setup();
+        let mut view = PlanView::new(&organizer, &scan_result);
+
+        // The first image goes to Documents and joins that group
+        let name = visible_names(&view)[0].to_string();
+        press(&mut view, &organizer, "c2");
+        assert_eq!(visible_names(&view)[1..], [name.as_str(), "notes.txt"]);
+
+        let review = view.into_review(&organizer);
+        let mut destinations: Vec<PathBuf> = review.accepted.into_iter().map(|op| op.destination).collect();
+        destinations.sort();
+        assert!(destinations.contains(&output.path().join("Documents").join(&name)));
+        assert!(destinations.contains(&output.path().join("Documents/notes.txt")));
+    }
+
+    #[test]
+    fn test_failed_reassign_keeps_destination() {
+        let (_source, output, organizer, scan_result) = setup();
+        // No file can be placed in Documents
+        let organizer = organizer.with_layout(Some(FileCategory::Documents), Layout::parse("..").unwrap());
+        let mut view = PlanView::new(&organizer, &scan_result);
+
+        let name = visible_names(&view)[0].to_string();
+        press(&mut view, &organizer, "c2");
+        assert!(view.message.as_deref().unwrap().contains("must stay inside the output directory"));
+
+        let review = view.into_review(&organizer);
+        let image = review.accepted.iter().find(|op| op.file_name == name).unwrap();
+        assert_eq!(image.destination, output.path().join("Images").join(&name));
+        // Still reserved, so no other file can be given its name
+        let mut other = review.accepted.iter().find(|op| op.file_name != name).unwrap().clone();
+        assert!(organizer.rename_planned(&mut other, &name).is_err());
+    }
+
+    #[test]
+    fn test_render_shows_groups_and_details() {
+        let (_source, _output, organizer, scan_result) = setup();
+        let mut view = PlanView::new(&organizer, &scan_result);
+
+        let screen = view.render(100, 20, &organizer);
+        assert!(screen.contains("Images (2)"));
+        assert!(screen.contains("Documents (1)"));
+        assert!(screen.contains("Size:     5 B"));
+        assert!(screen.contains("3 file(s), 0 excluded, 0 selected"));
+
+        // Every line is cut to the width
+        let narrow = view.render(30, 5, &organizer);
+        assert_eq!(narrow.matches("\r\n").count(), 4);
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(progress_bar(0, 4, 8), "[--------]");
+        assert_eq!(progress_bar(1, 4, 8), "[##------]");
+        assert_eq!(progress_bar(4, 4, 8), "[########]");
+        assert_eq!(progress_bar(0, 0, 4), "[####]");
+    }
+}
@@ -0,0 +1,328 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::copy::ReflinkMode;
+use crate::exitcode;
+use crate::journal::{self, JOURNAL_FILE_NAME};
+use crate::logging;
+use crate::staging;
+
+/// Outcome of putting the files of the last run back.
+#[derive(Debug, Default)]
+pub struct UndoResult {
+    /// Files moved back, as (organized location, original location)
+    pub restored: Vec<(PathBuf, PathBuf)>,
+    /// Pruned source directories that were created again
+    pub recreated_dirs: Vec<PathBuf>,
+    /// Output folders removed once their files had gone back
+    pub removed_dirs: Vec<PathBuf>,
+    pub skipped: Vec<(PathBuf, String)>,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl UndoResult {
+    pub fn print_summary(&self, dry_run: bool) {
+        if logging::json_output() {
+            self.print_summary_records(dry_run);
+            return;
+        }
+
+        println!("\n{}", "=".repeat(50));
+        if dry_run {
+            println!("DRY RUN SUMMARY");
+        } else {
+            println!("UNDO COMPLETE");
+        }
+        println!("{}", "=".repeat(50));
+
+        if dry_run {
+            println!("Files that would be restored: {}", self.restored.len());
+        } else {
+            println!("Files restored:            {}", self.restored.len());
+        }
+
+        if !self.recreated_dirs.is_empty() {
+            println!("Directories recreated:     {}", self.recreated_dirs.len());
+        }
+
+        if !self.removed_dirs.is_empty() {
+            println!("Empty folders removed:     {}", self.removed_dirs.len());
+        }
+
+        if !self.skipped.is_empty() {
+            println!("Files skipped:             {}", self.skipped.len());
+        }
+
+        if !self.failed.is_empty() {
+            println!("Files failed:              {}", self.failed.len());
+        }
+
+        if !self.skipped.is_empty() {
+            println!("\nSkipped files:");
+            for (path, reason) in &self.skipped {
+                println!("  {} - {}", path.display(), reason);
+            }
+        }
+
+        if !self.failed.is_empty() {
+            println!("\nFailed files:");
+            for (path, reason) in &self.failed {
+                println!("  {} - {}", path.display(), reason);
+            }
+        }
+
+        println!("{}", "=".repeat(50));
+    }
+
+    /// The summary as JSON records: the counts, then each skipped and
+    /// failed file.
+    fn print_summary_records(&self, dry_run: bool) {
+        logging::print_record(
+            logging::Level::Info,
+            "undo summary",
+            &[
+                ("dry_run", &dry_run),
+                ("restored", &self.restored.len()),
+                ("recreated_dirs", &self.recreated_dirs.len()),
+                ("removed_dirs", &self.removed_dirs.len()),
+                ("skipped", &self.skipped.len()),
+                ("failed", &self.failed.len()),
+            ],
+        );
+        for (path, reason) in &self.skipped {
+            logging::print_record(logging::Level::Warn, "skipped", &[("path", &path.display()), ("reason", reason)]);
+        }
+        for (path, reason) in &self.failed {
+            logging::print_record(logging::Level::Error, "failed", &[("path", &path.display()), ("reason", reason)]);
+        }
+    }
+
+    /// The failed files, on standard error, for `--quiet`.
+    pub fn print_errors(&self) {
+        for (path, reason) in &self.failed {
+            eprintln!("Failed: {} - {}", path.display(), reason);
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        exitcode::outcome(self.restored.len(), self.skipped.len(), self.failed.len())
+    }
+}
+
+/// Reverts the run recorded in the journal in `output_dir`: recreates the
+/// directories `--prune-empty` removed, then moves every file back to where
+/// it came from, newest first.
+///
+/// Files whose original location is taken again, or that are no longer in
+/// the output directory, are skipped. Once all files are back, the output
+/// folders the run created are removed if empty, then the journal is
+/// deleted, so a partial undo can simply be run again. A `--mode copy` run
+/// never touched its sources and cannot be undone.
+pub fn undo(output_dir: &Path, dry_run: bool, verbose: bool, reflink: ReflinkMode) -> io::Result<UndoResult> {
+    let state = journal::read_state(output_dir)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("nothing to undo: no progress log in {}", output_dir.display()),
+        )
+    })?;
+    if state.copied {
+        return Err(io::Error::other(format!(
+            "the last run into {} copied its files with --mode copy and cannot be undone; the originals are still in place",
+            output_dir.display()
+        )));
+    }
+    if state.is_interrupted() && !state.in_flight.is_empty() {
+        return Err(io::Error::other(format!(
+            "the last run into {} was interrupted; finish it with --resume before undoing it",
+            output_dir.display()
+        )));
+    }
+
+    let mut result = UndoResult::default();
+
+    // Removed deepest first, so the reverse order recreates parents first
+    for dir in state.removed_dirs.iter().rev() {
+        if dir.exists() {
+            continue;
+        }
+        if dry_run {
+            logging::print(&format!("  [DRY RUN] Would recreate: {}", dir.display()));
+        } else {
+            fs::create_dir_all(dir)?;
+            if verbose {
+                logging::print(&format!("  Recreated directory: {}", dir.display()));
+            }
+        }
+        result.recreated_dirs.push(dir.clone());
+    }
+
+    for (source, destination) in state.done.iter().rev() {
+        let skip_reason = if fs::symlink_metadata(destination).is_err() {
+            Some("no longer in the output directory".to_string())
+        } else if fs::symlink_metadata(source).is_ok() {
+            Some(format!("'{}' exists again", source.display()))
+        } else {
+            None
+        };
+        if let Some(reason) = skip_reason {
+            logging::warn("not restored", &[("file", &destination.display()), ("reason", &reason)]);
+            result.skipped.push((destination.clone(), reason));
+            continue;
+        }
+
+        if dry_run {
+            if verbose {
+                logging::print(&format!("  Would restore: {} -> {}", destination.display(), source.display()));
+            }
+        } else if let Err(e) = move_back(destination, source, reflink) {
+            logging::error("cannot restore", &[("file", &destination.display()), ("reason", &e)]);
+            result.failed.push((destination.clone(), e.to_string()));
+            continue;
+        } else if verbose {
+            logging::print(&format!("  Restored: {} -> {}", destination.display(), source.display()));
+        }
+        logging::info(
+            if dry_run { "would restore" } else { "restored" },
+            &[("file", &destination.display()), ("destination", &source.display())],
+        );
+        result.restored.push((destination.clone(), source.clone()));
+    }
+
+    if !dry_run && result.failed.is_empty() && result.skipped.is_empty() {
+        // Deepest first; folders that were there before the run, or that
+        // hold other files, stay
+        for dir in state.created_dirs.iter().rev() {
+            if fs::remove_dir(dir).is_ok() {
+                result.removed_dirs.push(dir.clone());
+            }
+        }
+        fs::remove_file(output_dir.join(JOURNAL_FILE_NAME))?;
+    }
+
+    Ok(result)
+}
+
+fn move_back(from: &Path, to: &Path, reflink: ReflinkMode) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            staging::copy_staged(from, to, reflink)?;
+            fs::remove_file(from)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Journal;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_undo_restores_files_and_directories() {
+        let source = tempdir().unwrap();
+        let output = tempdir().unwrap();
+        let original = source.path().join("trip/day1/photo.jpg");
+        let organized = output.path().join("Images/trip/day1/photo.jpg");
+        fs::create_dir_all(organized.parent().unwrap()).unwrap();
+        fs::write(&organized, "image").unwrap();
+
+        let journal = Journal::create(output.path()).unwrap();
+        for dir in ["Images", "Images/trip", "Images/trip/day1"] {
+            journal.record_mkdir(&output.path().join(dir)).unwrap();
+        }
+        journal.record_start(&original, &organized).unwrap();
+        journal.record_done(&original, &organized).unwrap();
+        journal.finish(false).unwrap();
+        journal.record_rmdir(&source.path().join("trip/day1")).unwrap();
+        journal.record_rmdir(&source.path().join("trip")).unwrap();
+
+        let result = undo(output.path(), false, false, ReflinkMode::Auto).unwrap();
+
+        assert_eq!(result.restored.len(), 1);
+        assert_eq!(result.recreated_dirs.len(), 2);
+        assert_eq!(fs::read_to_string(&original).unwrap(), "image");
+        assert!(!output.path().join("Images").exists());
+        assert!(!output.path().join(JOURNAL_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_undo_never_overwrites() {
+        let source = tempdir().unwrap();
+        let output = tempdir().unwrap();
+        let original = source.path().join("notes.txt");
+        let organized = output.path().join("Documents/notes.txt");
+        fs::create_dir_all(organized.parent().unwrap()).unwrap();
+        fs::write(&organized, "old").unwrap();
+        fs::write(&original, "new").unwrap();
+
+        let journal = Journal::create(output.path()).unwrap();
+        journal.record_done(&original, &organized).unwrap();
+        journal.finish(false).unwrap();
+
+        let result = undo(output.path(), false, false, ReflinkMode::Auto).unwrap();
+
+        assert!(result.restored.is_empty());
+        assert_eq!(result.skipped.len(), 1);
+        assert_eq!(fs::read_to_string(&original).unwrap(), "new");
+        // Kept so the undo can be finished later
+        assert!(output.path().join(JOURNAL_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_undo_keeps_folders_it_did_not_create() {
+        let source = tempdir().unwrap();
+        let output = tempdir().unwrap();
+        let original = source.path().join("photo.jpg");
+        let organized = output.path().join("Images/2024/photo.jpg");
+        // Documents and Images were there, empty, before the run
+        fs::create_dir_all(output.path().join("Documents")).unwrap();
+        fs::create_dir_all(organized.parent().unwrap()).unwrap();
+        fs::write(&organized, "image").unwrap();
+
+        let journal = Journal::create(output.path()).unwrap();
+        journal.record_mkdir(&output.path().join("Images/2024")).unwrap();
+        journal.record_done(&original, &organized).unwrap();
+        journal.finish(false).unwrap();
+
+        let result = undo(output.path(), false, false, ReflinkMode::Auto).unwrap();
+
+        assert_eq!(result.removed_dirs, vec![output.path().join("Images/2024")]);
+        assert!(output.path().join("Images").is_dir());
+        assert!(output.path().join("Documents").is_dir());
+    }
+
+    #[test]
+    fn test_copy_runs_cannot_be_undone() {
+        let source = tempdir().unwrap();
+        let output = tempdir().unwrap();
+        let original = source.path().join("photo.jpg");
+        let organized = output.path().join("Images/photo.jpg");
+        fs::write(&original, "image").unwrap();
+        fs::create_dir_all(organized.parent().unwrap()).unwrap();
+        fs::write(&organized, "image").unwrap();
+
+        let journal = Journal::create(output.path()).unwrap();
+        journal.record_copy_mode().unwrap();
+        journal.record_done(&original, &organized).unwrap();
+        journal.finish(false).unwrap();
+
+        let error = undo(output.path(), false, false, ReflinkMode::Auto).unwrap_err();
+        assert!(error.to_string().contains("--mode copy"));
+        assert!(organized.exists());
+        assert!(output.path().join(JOURNAL_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_nothing_to_undo() {
+        let output = tempdir().unwrap();
+        let error = undo(output.path(), false, false, ReflinkMode::Auto).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
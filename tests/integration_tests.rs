@@ -202,7 +202,8 @@ fn test_empty_directory() {
         .output()
         .expect("Failed to execute command");
 
-    assert!(output.status.success());
+    // Nothing to do
+    assert_eq!(output.status.code(), Some(3));
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.contains("Found 0 files"));
@@ -249,7 +250,8 @@ fn test_skip_existing_destination_file() {
         .output()
         .expect("Failed to execute command");
 
-    assert!(output.status.success());
+    // Some files skipped
+    assert_eq!(output.status.code(), Some(4));
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.contains("skipped"));
@@ -357,3 +359,300 @@ fn test_case_insensitive_extensions() {
     assert!(source_dir.path().join("Images/photo2.Jpg").exists());
     assert!(source_dir.path().join("Images/photo3.jpg").exists());
 }
+
+#[test]
+fn test_resume_after_interrupted_run() {
+    let binary = get_binary_path();
+    let source_dir = tempdir().unwrap();
+    let output_dir = tempdir().unwrap();
+
+    fs::write(source_dir.path().join("photo.jpg"), "complete image data").unwrap();
+
+    // A run that was killed halfway through copying photo.jpg
+    let source = source_dir.path().join("photo.jpg");
+    let destination = output_dir.path().join("Images/photo.jpg");
+    fs::create_dir_all(output_dir.path().join("Images")).unwrap();
+    fs::write(&destination, "complete im").unwrap();
+    fs::write(
+        output_dir.path().join(".file-organizer.journal"),
+        format!("RUN\t1\t2024-01-01 00:00:00\nSTART\t{}\t{}\n", source.display(), destination.display()),
+    )
+    .unwrap();
+
+    let args = ["-s", source_dir.path().to_str().unwrap(), "-o", output_dir.path().to_str().unwrap()];
+
+    let output = Command::new(&binary).args(args).output().expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--resume"));
+
+    let output = Command::new(&binary)
+        .args(args)
+        .arg("--resume")
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "Command failed: {:?}", output);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Removed partial copy"));
+    assert_eq!(fs::read_to_string(&destination).unwrap(), "complete image data");
+    assert!(!source.exists());
+
+    let journal = fs::read_to_string(output_dir.path().join(".file-organizer.journal")).unwrap();
+    assert!(journal.ends_with("END\tcomplete\n"));
+}
+
+#[test]
+fn test_failed_setup_does_not_need_resume() {
+    let binary = get_binary_path();
+    let source_dir = tempdir().unwrap();
+    let output_dir = tempdir().unwrap();
+
+    fs::write(source_dir.path().join("photo.jpg"), "image").unwrap();
+    // A file where the Images folder should go stops the run before any move
+    fs::write(output_dir.path().join("Images"), "not a folder").unwrap();
+
+    let args = ["-s", source_dir.path().to_str().unwrap(), "-o", output_dir.path().to_str().unwrap()];
+
+    let output = Command::new(&binary).args(args).output().expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!output_dir.path().join(".file-organizer.journal").exists());
+
+    fs::remove_file(output_dir.path().join("Images")).unwrap();
+    let output = Command::new(&binary).args(args).output().expect("Failed to execute command");
+    assert!(output.status.success(), "Command failed: {:?}", output);
+    assert!(output_dir.path().join("Images/photo.jpg").exists());
+}
+
+#[test]
+fn test_run_profile_with_override() {
+    let binary = get_binary_path();
+    let source_dir = tempdir().unwrap();
+    let output_dir = tempdir().unwrap();
+    let config_dir = tempdir().unwrap();
+
+    fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+    let config = config_dir.path().join("config.toml");
+    fs::write(
+        &config,
+        format!(
+            "[profiles.photos]\nsource = \"{}\"\noutput = \"{}\"\nmode = \"copy\"\n",
+            source_dir.path().display(),
+            output_dir.path().display()
+        ),
+    )
+    .unwrap();
+
+    let output = Command::new(&binary)
+        .args(["--config", config.to_str().unwrap(), "profiles", "list"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("photos\n"));
+
+    // The profile copies, the command line says move
+    let output = Command::new(&binary)
+        .args(["run", "photos", "--config", config.to_str().unwrap(), "--mode", "move"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(output_dir.path().join("Images/photo.jpg").exists());
+    assert!(!source_dir.path().join("photo.jpg").exists());
+
+    let output = Command::new(&binary)
+        .args(["run", "missing", "--config", config.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no profile 'missing'"));
+}
+
+#[test]
+fn test_quiet_run_exit_codes() {
+    let binary = get_binary_path();
+    let source_dir = tempdir().unwrap();
+    let output_dir = tempdir().unwrap();
+
+    fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+    fs::write(source_dir.path().join("report.pdf"), "document").unwrap();
+    fs::create_dir_all(output_dir.path().join("Documents")).unwrap();
+    fs::write(output_dir.path().join("Documents/report.pdf"), "older").unwrap();
+
+    let args = ["-q", "-s", source_dir.path().to_str().unwrap(), "-o", output_dir.path().to_str().unwrap()];
+
+    // report.pdf is skipped, its destination is taken
+    let output = Command::new(&binary).args(args).output().expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(output.stdout.is_empty(), "unexpected output: {:?}", output);
+    assert!(output_dir.path().join("Images/photo.jpg").exists());
+
+    fs::write(source_dir.path().join("notes.txt"), "text").unwrap();
+    fs::remove_file(source_dir.path().join("report.pdf")).unwrap();
+    let output = Command::new(&binary).args(args).output().expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    // Resuming a finished run has nothing to say either
+    let output = Command::new(&binary).args(args).arg("--resume").output().expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty(), "unexpected output: {:?}", output);
+
+    let output = Command::new(&binary)
+        .args(["-q", "-s", "/nonexistent/directory/path"])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn test_log_file_records_decisions() {
+    let binary = get_binary_path();
+    let source_dir = tempdir().unwrap();
+    let output_dir = tempdir().unwrap();
+    let log_dir = tempdir().unwrap();
+
+    fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+    fs::write(source_dir.path().join("taken.pdf"), "document").unwrap();
+    fs::create_dir_all(output_dir.path().join("Documents")).unwrap();
+    fs::write(output_dir.path().join("Documents/taken.pdf"), "older").unwrap();
+
+    let log_file = log_dir.path().join("logs/run.json");
+    let output = Command::new(&binary)
+        .args([
+            "-s",
+            source_dir.path().to_str().unwrap(),
+            "-o",
+            output_dir.path().to_str().unwrap(),
+            "--log-file",
+            log_file.to_str().unwrap(),
+            "--log-format",
+            "json",
+            "--log-level",
+            "debug",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(4));
+
+    let log = fs::read_to_string(&log_file).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert!(lines.iter().all(|line| line.starts_with("{\"time\":") && line.ends_with('}')));
+    assert!(lines[0].contains("\"message\":\"run started\""));
+    assert!(log.contains("\"message\":\"categorized\",\"file\":") && log.contains("\"reason\":\"extension .jpg\""));
+    assert!(log.contains("\"route\":\"category folder\""));
+    assert!(log.contains("\"level\":\"info\",\"message\":\"moved\""));
+    assert!(log.contains("\"level\":\"warn\",\"message\":\"skipped\""));
+    assert!(log.contains("\"reason\":\"destination file already exists\""));
+    assert!(lines.last().unwrap().contains("\"exit_status\":\"4\""));
+
+    // The run's own output is JSON records too
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.lines().all(|line| line.starts_with("{\"time\":") && line.ends_with('}')));
+    assert!(stdout.contains("\"message\":\"summary\",\"dry_run\":\"false\",\"moved\":\"1\",\"skipped\":\"1\""));
+    assert!(stdout.contains("\"level\":\"warn\",\"message\":\"skipped\""));
+}
+
+#[test]
+fn test_layout_with_unknown_category() {
+    let binary = get_binary_path();
+    let source_dir = tempdir().unwrap();
+    fs::write(source_dir.path().join("photo.jpg"), "image data").unwrap();
+
+    let output = Command::new(&binary)
+        .args(["-s", source_dir.path().to_str().unwrap(), "--layout", "imgaes=Photos/{date:%Y}"])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown category 'imgaes'"));
+    assert!(source_dir.path().join("photo.jpg").exists());
+}
+
+#[test]
+fn test_schedule_install_forwards_options() {
+    let binary = get_binary_path();
+    let home = tempdir().unwrap();
+    let source_dir = tempdir().unwrap();
+
+    let output = Command::new(&binary)
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", home.path().join("config"))
+        .args(["schedule", "install", "--daily", "--profile", "nightly"])
+        .args(["-s", source_dir.path().to_str().unwrap(), "-r", "--flatten", "--on-conflict", "rename"])
+        .args(["--layout", "images=Photos/{date:%Y}"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "Command failed: {:?}", output);
+
+    let service =
+        fs::read_to_string(home.path().join("config/systemd/user/file-organizer-nightly.service")).unwrap();
+    let exec = service.lines().find(|line| line.starts_with("ExecStart=")).unwrap();
+    assert!(exec.contains(&format!("--source {}", source_dir.path().display())));
+    assert!(exec.contains("--recursive --flatten --on-conflict rename"));
+    assert!(exec.contains("--layout images=Photos/{date:%%Y}"));
+
+    // An option that needs someone at the terminal is refused, not dropped
+    let output = Command::new(&binary)
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", home.path().join("config"))
+        .args(["schedule", "install", "--daily", "--profile", "review", "--interactive"])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--interactive cannot be used in a scheduled run"));
+    assert!(!home.path().join("config/systemd/user/file-organizer-review.service").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_relative_link_from_relative_source() {
+    let binary = get_binary_path();
+    let source_dir = tempdir().unwrap();
+    let output_dir = tempdir().unwrap();
+
+    // Not recursive, so the link target stays where it is
+    fs::create_dir(source_dir.path().join("keep")).unwrap();
+    fs::write(source_dir.path().join("keep/notes.txt"), "notes").unwrap();
+    std::os::unix::fs::symlink("keep/notes.txt", source_dir.path().join("notes-link.txt")).unwrap();
+
+    let output = Command::new(&binary)
+        .current_dir(source_dir.path())
+        .args(["-o", output_dir.path().to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "Command failed: {:?}", output);
+
+    let link = output_dir.path().join("Documents/notes-link.txt");
+    assert!(fs::read_link(&link).unwrap().is_absolute());
+    assert_eq!(fs::read_to_string(&link).unwrap(), "notes");
+}
+
+#[test]
+fn test_undo_from_another_directory() {
+    let binary = get_binary_path();
+    let work_dir = tempdir().unwrap();
+    let elsewhere = tempdir().unwrap();
+
+    fs::create_dir(work_dir.path().join("in")).unwrap();
+    fs::write(work_dir.path().join("in/photo.jpg"), "image").unwrap();
+
+    // Organized with paths relative to the working directory
+    let output = Command::new(&binary)
+        .current_dir(work_dir.path())
+        .args(["-s", "in", "-o", "out"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "Command failed: {:?}", output);
+    assert!(work_dir.path().join("out/Images/photo.jpg").exists());
+
+    let output = Command::new(&binary)
+        .current_dir(elsewhere.path())
+        .args(["-o", work_dir.path().join("out").to_str().unwrap(), "--undo"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "Command failed: {:?}", output);
+    assert!(work_dir.path().join("in/photo.jpg").exists());
+    assert!(!work_dir.path().join("out/Images/photo.jpg").exists());
+    // The run created Images, so the undo removes it again
+    assert!(!work_dir.path().join("out/Images").exists());
+    assert_eq!(fs::read_dir(elsewhere.path()).unwrap().count(), 0);
+}